@@ -1,12 +1,14 @@
 use winit::application::ApplicationHandler;
 use winit::dpi::{PhysicalPosition, PhysicalSize};
-use winit::event::{ElementState, MouseScrollDelta, WindowEvent};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, EventLoopProxy};
 use winit::window::Window;
 use std::sync::Arc;
 
 use crate::backend_admin::state::State;
 
+const PIXELS_PER_LINE: f64 = 40.0; // converts trackpad scroll pixels into wheel lines
+
 /// Setup for logical App struct \n
 /// App implements ApplicationHandler for resuming of app, WindowEvent handling \n
 #[derive(Default)]
@@ -94,11 +96,22 @@ impl ApplicationHandler<State> for App {
                         y: (position.y - old_pos.y) as f32
                     };
                     // handle cursor move here
-                    state_.world.camera.handle_rotate(delta.x, delta.y);
+                    if state_.pan_held { state_.world.camera.handle_pan(delta.x, delta.y); }
+                    else { state_.world.camera.handle_rotate(delta.x, delta.y); }
                     state_.mouse_pos = Some(position);
                 }
                 else { state_.mouse_pos = Some(position); }
             },
+            WindowEvent::MouseInput { state: button_state, button: MouseButton::Right, .. } => {
+                state_.pan_held = button_state == ElementState::Pressed;
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => (position.y / PIXELS_PER_LINE) as f32, // trackpads
+                };
+                state_.world.camera.handle_dolly(lines);
+            },
             WindowEvent::KeyboardInput {
                 event: winit::event::KeyEvent {
                         physical_key: winit::keyboard::PhysicalKey::Code(code),
@@ -121,7 +134,9 @@ impl ApplicationHandler<State> for App {
             },       
             WindowEvent::RedrawRequested => {
                 match state_.render() {
-                    Ok(_) => {state_.gfx_ctx.window.request_redraw();},
+                    Ok(_) => {
+                        if let Some(window) = state_.gfx_ctx.window() { window.request_redraw(); }
+                    },
                     Err(e) => {
                         println!("Unable to render {}", e);
                     }
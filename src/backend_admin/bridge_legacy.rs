@@ -15,6 +15,7 @@ const LAPLACIAN_GROUPS: GroupDims3 = [8, 4, 8]; // 256 is max x * y * z
 #[derive(Debug)]
 pub struct Bridge {
     pub raymarch_dispatch: DispatchDims,
+    pub slice_dispatch: DispatchDims, // whole window, slice() picks out its panes
 
     pub laplacian_dispatch: DispatchDims,
     pub init_dispatch: DispatchDims, // THIS IS THE SAME AS LAPLACIAN, but added as a separate field for clarity in state::render()
@@ -34,6 +35,8 @@ impl Bridge {
             1
         ];
 
+        let slice_dispatch = Bridge::window_dispatch(w, h);
+
         let laplacian_dispatch: DispatchDims = [
             voxel_grid.dims[0].div_ceil(LAPLACIAN_GROUPS[0]),
             voxel_grid.dims[1].div_ceil(LAPLACIAN_GROUPS[1]),
@@ -44,6 +47,7 @@ impl Bridge {
 
         Bridge {
             raymarch_dispatch: raymarch_dispatch,
+            slice_dispatch: slice_dispatch,
 
             laplacian_dispatch: laplacian_dispatch,
             init_dispatch: laplacian_dispatch,
@@ -54,12 +58,11 @@ impl Bridge {
 
     /// Determines dispatch dims on each render() 
     pub fn update_raymarch_dispatch(&mut self, bounding_box: BoundingBox){
+        // empty when the grid is out of view (FPVCamera can look away), dispatching nothing
         let (w, h) = (
-            (bounding_box[1][0] - bounding_box[0][0]) as u32, 
-            (bounding_box[1][1] - bounding_box[0][1]) as u32
+            (bounding_box[1][0] - bounding_box[0][0]).max(0) as u32, 
+            (bounding_box[1][1] - bounding_box[0][1]).max(0) as u32
         );  
-
-        assert!(w > 0 && h > 0); // OrbitalCamera implementation should always have voxel grid in view, so should never be 0
         
         self.raymarch_dispatch = [
             w.div_ceil(RAYMARCH_GROUPS[0]),
@@ -67,4 +70,23 @@ impl Bridge {
             1
         ];
     }
-}
\ No newline at end of file
+
+    /// Laplacian and init dispatch for a grid of new dims (see State::load_volume())
+    pub fn update_grid_dispatch(&mut self, voxel_grid: &VoxelGrid) {
+        self.laplacian_dispatch = [
+            voxel_grid.dims[0].div_ceil(LAPLACIAN_GROUPS[0]),
+            voxel_grid.dims[1].div_ceil(LAPLACIAN_GROUPS[1]),
+            voxel_grid.dims[2].div_ceil(LAPLACIAN_GROUPS[2])
+        ];
+        self.init_dispatch = self.laplacian_dispatch;
+    }
+
+    /// Slice dispatch for a resized window
+    pub fn update_slice_dispatch(&mut self, width: u32, height: u32) {
+        self.slice_dispatch = Bridge::window_dispatch(width, height);
+    }
+
+    fn window_dispatch(width: u32, height: u32) -> DispatchDims {
+        [width.div_ceil(RAYMARCH_GROUPS[0]), height.div_ceil(RAYMARCH_GROUPS[1]), 1]
+    }
+}
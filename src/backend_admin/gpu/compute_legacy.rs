@@ -1,18 +1,27 @@
-use wgpu::{BindGroup, PipelineCompilationOptions, BindGroupEntry, BindGroupLayout, BufferBinding, ComputePipeline, PipelineLayout, ShaderModule, ShaderStages, TextureFormat};
+use wgpu::{BindGroup, PipelineCompilationOptions, BindGroupEntry, BindGroupLayout, BufferBinding, ComputePipeline, PipelineLayout, ShaderModule, ShaderStages};
 use std::num::NonZero;
-use crate::{world::voxel_grid::Dims3, backend_admin::gpu::{
+use crate::{world::diffusion::CpuDiffusion, backend_admin::gpu::{
     enums::{Access, OffsetBehaviour}, 
     builders::BindGroupLayoutBuilder,
     gfx_context::GraphicsContext,
-    resources::{Uniforms, Resources}}};
+    resources::{Uniforms, Resources, STORAGE_FORMAT}}};
 
 
+/// Which solver advances the voxel field each frame
+/// Gpu: laplacian.wgsl dispatch
+/// Cpu: rayon reference in world::diffusion, uploaded into the voxel buffers for the raymarch
+/// (used when the adapter is a software one, and for cross-checking GPU output)
+pub enum ComputeBackend {
+    Gpu,
+    Cpu(CpuDiffusion),
+}
 
 /// Responsible for Compute pipeline, including
-/// init, raymarch and laplacian
+/// init, raymarch (and slice), laplacian and reaction_diffusion
 pub struct Compute{
     init_shader: ShaderModule,
     laplacian_shader: ShaderModule,
+    reaction_diffusion_shader: ShaderModule,
     raymarch_shader: ShaderModule,
 
     bg_layout: BindGroupLayout,
@@ -21,12 +30,14 @@ pub struct Compute{
     p_layout: PipelineLayout,
     pub init_p: ComputePipeline,
     pub laplacian_p: ComputePipeline,
-    pub raymarch_p: ComputePipeline
+    pub reaction_diffusion_p: ComputePipeline,
+    pub raymarch_p: ComputePipeline,
+    pub slice_p: ComputePipeline
     
 }
 
 impl Compute {
-    pub fn new(dims: &Dims3, resources: &Resources, gfx_ctx: &GraphicsContext) -> Self {
+    pub fn new(resources: &Resources, gfx_ctx: &GraphicsContext) -> Self {
         // Load shader module
         let init = gfx_ctx.device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Init"),
@@ -36,6 +47,10 @@ impl Compute {
             label: Some("Laplacian"),
             source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/laplacian.wgsl").into())
             });
+        let reaction_diffusion = gfx_ctx.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Reaction Diffusion"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/reaction_diffusion.wgsl").into())
+            });
         let raymarch = gfx_ctx.device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Raymarch"),
             source: wgpu::ShaderSource::Wgsl(include_str!("../../shaders/raymarch.wgsl").into())
@@ -56,9 +71,21 @@ impl Compute {
                 Access::ReadWrite)
             .with_storage_texture(
                 ShaderStages::COMPUTE, 
-                TextureFormat::Rgba8Unorm, 
+                STORAGE_FORMAT, 
                 wgpu::StorageTextureAccess::WriteOnly,
             wgpu::TextureViewDimension::D2)
+            .with_storage_buffer(
+                ShaderStages::COMPUTE,
+                OffsetBehaviour::Static,
+                Access::ReadOnly)
+            .with_storage_buffer(
+                ShaderStages::COMPUTE,
+                OffsetBehaviour::Static,
+                Access::ReadOnly)
+            .with_storage_buffer(
+                ShaderStages::COMPUTE,
+                OffsetBehaviour::Static,
+                Access::ReadOnly)
             .build(&gfx_ctx.device);
 
         let bind_group_descriptor = &wgpu::BindGroupDescriptor { //TODO: CONSIDER MAKING A BUILD GROUP DESCRIPTOR BUILDER
@@ -78,7 +105,7 @@ impl Compute {
             resource: wgpu::BindingResource::Buffer(BufferBinding{ 
                 buffer:  &resources.ping_voxel_buffer,
                 offset: 0,
-                size: NonZero::new((std::mem::size_of::<f32>() as u32 * dims[0] * dims[1] * dims[2]) as u64)
+                size: NonZero::new(resources.ping_voxel_buffer.size())
         })
         },
         BindGroupEntry {
@@ -86,11 +113,20 @@ impl Compute {
             resource: wgpu::BindingResource::Buffer(BufferBinding{ 
                 buffer:  &resources.pong_voxel_buffer,
                 offset: 0,
-                size: NonZero::new((std::mem::size_of::<f32>() as u32 * dims[0] * dims[1] * dims[2]) as u64)
+                size: NonZero::new(resources.pong_voxel_buffer.size())
         })},
         BindGroupEntry {
             binding: 3,
-            resource: wgpu::BindingResource::TextureView(&resources.texture_view)}
+            resource: wgpu::BindingResource::TextureView(&resources.texture_view)},
+        BindGroupEntry {
+            binding: 4,
+            resource: resources.transfer_function.as_entire_binding()},
+        BindGroupEntry {
+            binding: 5,
+            resource: resources.labels.as_entire_binding()},
+        BindGroupEntry {
+            binding: 6,
+            resource: resources.label_colours.as_entire_binding()}
         ]
         };
 
@@ -130,6 +166,19 @@ impl Compute {
                 zero_initialize_workgroup_memory: true 
             }
         });
+
+
+        let reaction_diffusion_pipeline = gfx_ctx.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Reaction Diffusion"),
+            layout: Some(&pipeline_layout),
+            module: &reaction_diffusion,
+            entry_point: Some("reaction_diffusion"),
+            cache: None,
+            compilation_options: PipelineCompilationOptions{
+                constants: &[],
+                zero_initialize_workgroup_memory: true 
+            }
+        });
          
         let raymarch_pipeline = gfx_ctx.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: Some("Raymarch"),
@@ -143,9 +192,22 @@ impl Compute {
             }
         });
 
+        let slice_pipeline = gfx_ctx.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Slice"),
+            layout: Some(&pipeline_layout),
+            module: &raymarch, // shares sampling and transfer function with the raymarch
+            entry_point: Some("slice"),
+            cache: None,
+            compilation_options: PipelineCompilationOptions{
+                constants: &[],
+                zero_initialize_workgroup_memory: true 
+            }
+        });
+
             Compute {
                 init_shader: init,
                 laplacian_shader: laplacian,
+                reaction_diffusion_shader: reaction_diffusion,
                 raymarch_shader: raymarch,
 
                 bg_layout: bind_group_layout,
@@ -154,12 +216,14 @@ impl Compute {
                 p_layout: pipeline_layout,
                 init_p: init_pipeline,
                 laplacian_p: laplacian_pipeline,
-                raymarch_p: raymarch_pipeline
+                reaction_diffusion_p: reaction_diffusion_pipeline,
+                raymarch_p: raymarch_pipeline,
+                slice_p: slice_pipeline
             }
 
     }
 
-    pub fn on_resize(&mut self, dims: &Dims3, gfx_ctx: &GraphicsContext, rsrcs: &Resources) {
+    pub fn on_resize(&mut self, gfx_ctx: &GraphicsContext, rsrcs: &Resources) {
         let bind_group_descriptor = &wgpu::BindGroupDescriptor {
             label: Some("Bind group descriptor"),
             layout: &self.bg_layout,
@@ -176,7 +240,7 @@ impl Compute {
                 resource: wgpu::BindingResource::Buffer(BufferBinding{ // actual voxel grid storage buffer @ binding 1
                     buffer:  &rsrcs.ping_voxel_buffer,
                     offset: 0,
-                    size: NonZero::new((std::mem::size_of::<f32>() as u32 * dims[0] * dims[1] * dims[2]) as u64)
+                    size: NonZero::new(rsrcs.ping_voxel_buffer.size())
             })
             },
             BindGroupEntry {
@@ -184,12 +248,24 @@ impl Compute {
                 resource: wgpu::BindingResource::Buffer(BufferBinding{ // actual voxel grid storage buffer @ binding 1
                     buffer:  &rsrcs.pong_voxel_buffer,
                     offset: 0,
-                    size: NonZero::new((std::mem::size_of::<f32>() as u32 * dims[0] * dims[1] * dims[2]) as u64)
+                    size: NonZero::new(rsrcs.pong_voxel_buffer.size())
             })
             },
             BindGroupEntry {
                 binding: 3,
                 resource: wgpu::BindingResource::TextureView(&rsrcs.texture_view)
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: rsrcs.transfer_function.as_entire_binding()
+            },
+            BindGroupEntry {
+                binding: 5,
+                resource: rsrcs.labels.as_entire_binding()
+            },
+            BindGroupEntry {
+                binding: 6,
+                resource: rsrcs.label_colours.as_entire_binding()
             }
             ]
         };
@@ -2,22 +2,69 @@ use std::{sync::Arc};
 use winit::{
     dpi::PhysicalSize,
     window::Window};
-use wgpu::{Adapter, Device, Instance, Queue, Surface, SurfaceConfiguration};
+use wgpu::{Adapter, Device, Instance, Queue, Surface, SurfaceConfiguration, SurfaceTexture, Texture, TextureView};
 use std::error::Error;
 
+/// Colour format of the offscreen texture used by headless contexts
+const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Where the presentation pass ends up
+/// Windowed: swapchain of an OS window (the interactive app)
+/// Headless: a texture owned by the context (batch jobs, tests, machines without a display)
+pub enum RenderTarget {
+    Windowed {
+        window: Arc<Window>,
+        surface: Surface<'static>,
+    },
+    Headless {
+        texture: Texture,
+        view: TextureView,
+    },
+}
+
+/// Colour attachment for a single frame, acquired through GraphicsContext::acquire_frame()
+/// Call present() once the frame's command buffer has been submitted
+pub enum Frame {
+    Windowed {
+        surface_texture: SurfaceTexture,
+        view: TextureView,
+    },
+    Headless {
+        view: TextureView,
+    },
+}
+
+impl Frame {
+    pub fn view(&self) -> &TextureView {
+        match self {
+            Frame::Windowed { view, .. } => view,
+            Frame::Headless { view } => view,
+        }
+    }
+
+    /// Swapchain frames are presented, offscreen frames simply stay in the target texture
+    pub fn present(self) {
+        match self {
+            Frame::Windowed { surface_texture, .. } => surface_texture.present(),
+            Frame::Headless { .. } => {},
+        }
+    }
+}
+
 pub struct GraphicsContext {
-    pub window: Arc<Window>,
+    pub target: RenderTarget,
     pub size: PhysicalSize<u32>,
     instance: Instance,
     adapter: Adapter,
 
-    pub surface: Surface<'static>,
+    /// Describes the render target for both variants (format, width, height)
+    /// Headless contexts never hand it to a surface
     pub surface_config: SurfaceConfiguration,
     pub surface_configured: bool,
 
     pub device: Device,
     pub queue: Queue,
-    
+
 }
 
 impl GraphicsContext {
@@ -31,16 +78,28 @@ impl GraphicsContext {
         // Surface == handle to window (GPU output)
         let surface = instance.create_surface(win.clone())?; // clone here otherwise surface takes ownership of window. Clone on arc is very cheap.
 
-        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+        // Without a usable hardware adapter, the software one still presents (State then diffuses on the CPU)
+        let (adapter, fallback) = match instance.request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::default(),
             compatible_surface: Some(&surface),
             force_fallback_adapter: false
-        }).await?;
+        }).await {
+            Ok(adapter) => (adapter, false),
+            Err(_) => {
+                println!("No hardware adapter found, requesting fallback adapter\n");
+                (instance.request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: Some(&surface),
+                    force_fallback_adapter: true
+                }).await?, true)
+            }
+        };
 
+        // Software adapters rarely meet Limits::defaults(), see new_headless()
         let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor{
             label: None,
-            required_features: wgpu::Features::default(), 
-            required_limits: wgpu::Limits::defaults(),
+            required_features: wgpu::Features::default(),
+            required_limits: if fallback { adapter.limits() } else { wgpu::Limits::defaults() },
             trace: wgpu::Trace::Off,
             memory_hints: Default::default(),
         }).await?;
@@ -66,12 +125,75 @@ impl GraphicsContext {
 
         Ok (
             GraphicsContext {
-                window: win,
+                target: RenderTarget::Windowed {
+                    window: win,
+                    surface: surface,
+                },
+                size: size,
+                instance: instance,
+                adapter: adapter,
+
+                surface_config: surface_config,
+                surface_configured: true,
+
+                device: device,
+                queue: queue,
+            }
+        )
+    }
+
+    /// Context without a window or surface, rendering into an offscreen texture of the given size
+    /// Prefers a hardware adapter, then falls back to the software adapter (e.g. lavapipe, WARP, llvmpipe)
+    /// so that servers without a display or GPU can still run the pipelines
+    pub async fn new_headless(size: PhysicalSize<u32>) -> Result<Self, Box<dyn Error>> {
+        if size.width == 0 || size.height == 0 {
+            return Err(format!("Headless size {}x{} is empty", size.width, size.height).into());
+        }
+
+        // All backends: GL is often the only software path available on display-less linux machines
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        let adapter = match instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: false
+        }).await {
+            Ok(adapter) => adapter,
+            Err(_) => {
+                println!("No hardware adapter found, requesting fallback adapter\n");
+                instance.request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter: true
+                }).await?
+            }
+        };
+
+        // Software adapters rarely meet Limits::defaults(), so take whatever this adapter offers
+        let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor{
+            label: None,
+            required_features: wgpu::Features::default(),
+            required_limits: adapter.limits(),
+            trace: wgpu::Trace::Off,
+            memory_hints: Default::default(),
+        }).await?;
+
+        let surface_config = GraphicsContext::headless_config(size);
+        let (texture, view) = GraphicsContext::create_offscreen_target(&device, &surface_config);
+
+        Ok (
+            GraphicsContext {
+                target: RenderTarget::Headless {
+                    texture: texture,
+                    view: view,
+                },
                 size: size,
                 instance: instance,
                 adapter: adapter,
 
-                surface: surface,
                 surface_config: surface_config,
                 surface_configured: true,
 
@@ -81,32 +203,108 @@ impl GraphicsContext {
         )
     }
 
+    /// None for headless contexts
+    pub fn window(&self) -> Option<&Arc<Window>> {
+        match &self.target {
+            RenderTarget::Windowed { window, .. } => Some(window),
+            RenderTarget::Headless { .. } => None,
+        }
+    }
+
+    /// True when running on a CPU (software) adapter
+    pub fn is_software(&self) -> bool {
+        self.adapter.get_info().device_type == wgpu::DeviceType::Cpu
+    }
+
+    /// Next colour attachment for the presentation pass
+    pub fn acquire_frame(&self) -> Result<Frame, wgpu::SurfaceError> {
+        match &self.target {
+            RenderTarget::Windowed { surface, .. } => {
+                let surface_texture = surface.get_current_texture()?;
+                // this defines how the texture is interpreted (sampled) to produce the actual pixel outputs to the surface
+                // texel -> pixel
+                let view = surface_texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(Frame::Windowed { surface_texture: surface_texture, view: view })
+            },
+            RenderTarget::Headless { view, .. } => {
+                Ok(Frame::Headless { view: view.clone() })
+            }
+        }
+    }
+
+    /// Windowed: reconfigures the surface to the window's inner size
+    /// Headless: recreates the offscreen texture at self.size
     pub fn update_surface_config(&mut self) -> PhysicalSize<u32> {
-        let surface_caps = self.surface.get_capabilities(&self.adapter);
+        let size = match &mut self.target {
+            RenderTarget::Windowed { window, surface } => {
+                let surface_caps = surface.get_capabilities(&self.adapter);
 
-        let surface_format = surface_caps.formats.iter()
-            .find(|f| f.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
+                let surface_format = surface_caps.formats.iter()
+                    .find(|f| f.is_srgb())
+                    .copied()
+                    .unwrap_or(surface_caps.formats[0]);
 
-        let size = self.window.inner_size();
+                let size = window.inner_size();
 
-        self.surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
+                self.surface_config = wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    format: surface_format,
+                    width: size.width,
+                    height: size.height,
+                    present_mode: surface_caps.present_modes[0],
+                    alpha_mode: surface_caps.alpha_modes[0],
+                    view_formats: vec![],
+                    desired_maximum_frame_latency: 2,
+                };
+                surface.configure(&self.device, &self.surface_config);
+
+                size
+            },
+            RenderTarget::Headless { texture, view } => {
+                self.surface_config = GraphicsContext::headless_config(self.size);
+                (*texture, *view) = GraphicsContext::create_offscreen_target(&self.device, &self.surface_config);
+
+                self.size
+            }
+        };
+
+
+        self.surface_configured = true;
+
+        size
+    }
+
+    fn headless_config(size: PhysicalSize<u32>) -> SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: HEADLESS_FORMAT,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
+            present_mode: wgpu::PresentMode::Fifo, // unused without a surface
+            alpha_mode: wgpu::CompositeAlphaMode::Auto, // unused without a surface
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
-        };
-        self.surface.configure(&self.device, &self.surface_config);
+        }
+    }
 
-        
-        self.surface_configured = true;
+    fn create_offscreen_target(device: &Device, config: &SurfaceConfiguration) -> (Texture, TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        size
+        (texture, view)
     }
 
-}
\ No newline at end of file
+}
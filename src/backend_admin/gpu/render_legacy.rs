@@ -1,6 +1,7 @@
 use wgpu::{BindGroup, BindGroupEntry, BindGroupLayout, PipelineLayout, PipelineLayoutDescriptor, RenderPipeline, ShaderModule, ShaderModuleDescriptor, ShaderStages};
 use crate::{backend_admin::gpu::{
     builders::BindGroupLayoutBuilder,
+    enums::OffsetBehaviour,
     gfx_context::GraphicsContext,
     resources::Resources}
 };
@@ -34,6 +35,7 @@ impl Render {
         let bind_group_layout = BindGroupLayoutBuilder::new("Render Bind Group".to_string())
                 .with_sampler(ShaderStages::FRAGMENT)
                 .with_sampled_texture(ShaderStages::FRAGMENT)
+                .with_uniform_buffer(ShaderStages::FRAGMENT, OffsetBehaviour::Static)
                 .build(&gfx_ctx.device);
         
         let bind_group = gfx_ctx.device.create_bind_group(&wgpu::BindGroupDescriptor{
@@ -46,6 +48,11 @@ impl Render {
                 BindGroupEntry {
                     binding: 1,
                     resource: wgpu::BindingResource::TextureView(&resources.texture_view)
+                },
+
+                BindGroupEntry {
+                    binding: 2,
+                    resource: resources.present.as_entire_binding()
                 }
             ]
         });
@@ -120,6 +127,11 @@ impl Render {
                 BindGroupEntry {
                     binding: 1,
                     resource: wgpu::BindingResource::TextureView(&rsrcs.texture_view)
+                },
+
+                BindGroupEntry {
+                    binding: 2,
+                    resource: rsrcs.present.as_entire_binding()
                 }]
             });
     }
@@ -1,8 +1,12 @@
 use crate::{backend_admin::{
     bridge::Bridge, gpu::gfx_context::GraphicsContext},
-    world::{voxel_grid::Dims3, world::{BoundingBox, World}
+    world::{channels::{Channels, MAX_COMPOSITE}, clipping::MAX_CLIP_PLANES, labels::{LabelTable, LabelVolume}, transfer_function::{TransferFunction, TF_RESOLUTION}, voxel_grid::Dims3, world::{BoundingBox, World}
     }};
 use wgpu::{Buffer, BufferUsages, Extent3d, Sampler, Texture, TextureDescriptor, TextureUsages, TextureView, TextureViewDescriptor};
+
+/// Format of the storage texture the raymarch and slices write into
+/// Half floats so 16-bit captures (see State::capture_frame()) hold more than 8 bits
+pub const STORAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
 use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalSize;
 
@@ -11,9 +15,15 @@ pub struct Resources {
     pub sampler: Sampler,
     pub ping_voxel_buffer: Buffer,
     pub pong_voxel_buffer: Buffer,
+    pub transfer_function: Buffer, // TransferFunction::lut() then Channels::composite_luts(), (1 + MAX_COMPOSITE) * TF_RESOLUTION vec4s
+    pub labels: Buffer, // LabelVolume::slots(), one u32 per voxel (a single background voxel without a label volume)
+    pub label_colours: Buffer, // LabelTable::colours(), a vec4 per slot
+    pub read_ping: bool, // true: ping holds the current field, pong is written next (flipped after every simulation step)
     storage_texture: Texture,
     pub texture_view: TextureView,
-    pub uniforms: Buffer
+    pub size: PhysicalSize<u32>, // storage texture size, the window's unless capturing offscreen
+    pub uniforms: Buffer,
+    pub present: Buffer // Present, read by the fragment shader (see present_refresh())
 }
 
 impl Resources {
@@ -23,34 +33,32 @@ impl Resources {
              PhysicalSize::new(gfx_ctx.surface_config.width, gfx_ctx.surface_config.height) }
              else { gfx_ctx.update_surface_config() };
 
+        // one dims sized block per channel, channel-major with the species first (see Channels)
+        let channels = world.voxel_grid.channels.len() as u64;
         let ping_voxels = gfx_ctx.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Compute store a"),
-            size:  (std::mem::size_of::<f32>() as u32 * dims[0] * dims[1] * dims[2]) as u64,
-            usage: BufferUsages::STORAGE,
+            size:  std::mem::size_of::<f32>() as u64 * dims[0] as u64 * dims[1] as u64 * dims[2] as u64 * channels,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
             mapped_at_creation: false 
         });
 
         let pong_voxels = gfx_ctx.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Compute store b"),
-            size:  (std::mem::size_of::<f32>() as u32 * dims[0] * dims[1] * dims[2]) as u64,
-            usage: BufferUsages::STORAGE,
+            size:  std::mem::size_of::<f32>() as u64 * dims[0] as u64 * dims[1] as u64 * dims[2] as u64 * channels,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
             mapped_at_creation: false
         }); 
 
 
-        let uniforms = Uniforms {
-            window_dims: [size.width/2, size.height/2, 0, 0],
-            dims: [dims[0], dims[1], dims[2], dims[0] * dims[1]],
-            bounding_box: [0, 0, 0, 0], // set in render() 
-            cam_pos: [world.camera.c[0], world.camera.c[1], world.camera.c[2], 0.0 as f32],
-            forward: [world.camera.f[0], world.camera.f[1], world.camera.f[2], 0.0 as f32],
-            centre: [world.camera.centre[0], world.camera.centre[1], world.camera.centre[2], 0.0 as f32],
-            up: [world.camera.u[0], world.camera.u[1], world.camera.u[2], 0.0 as f32],
-            right: [world.camera.r[0], world.camera.r[1], world.camera.r[2], 0.0 as f32],
-            timestep: [0.0 as f32, 0.0 as f32, 0.0 as f32, 0.0 as f32],
-            seed: [bridge.rand_seed, 0, 0, 0],
-            flags: [1, 0, 0, 0]
-        };
+        let transfer_function = gfx_ctx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Transfer function"),
+            contents: Resources::flatten_lut(&[world.transfer.lut(), world.voxel_grid.channels.composite_luts()].concat()),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+
+        let (labels, label_colours) = Resources::label_buffers(gfx_ctx, world.labels.as_ref());
+
+        let uniforms = Uniforms::from_world(size, dims, BoundingBox::default(), 0.0, true, bridge.rand_seed, world); // bounding box set in render()
         
         let uniforms = gfx_ctx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform buffer"),
@@ -58,6 +66,13 @@ impl Resources {
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
+        let present = gfx_ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Present uniform buffer"),
+            size: std::mem::size_of::<Present>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false // written every frame before presenting
+        });
+
         let storage_texture = gfx_ctx.device.create_texture(&TextureDescriptor{
             label: Some("Storage Texture"),
             size: Extent3d {
@@ -68,14 +83,14 @@ impl Resources {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
-            view_formats: &[wgpu::TextureFormat::Rgba8Unorm]
+            format: STORAGE_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC,
+            view_formats: &[STORAGE_FORMAT]
         });
 
         let texture_view = storage_texture.create_view(&TextureViewDescriptor{
             label: Some("Texture View"),
-            format: Some(wgpu::TextureFormat::Rgba8Unorm),
+            format: Some(STORAGE_FORMAT),
             dimension: Some(wgpu::TextureViewDimension::D2),
             usage: None,
             aspect: wgpu::TextureAspect::All,
@@ -105,14 +120,21 @@ impl Resources {
             sampler: sampler,
             ping_voxel_buffer: ping_voxels,
             pong_voxel_buffer: pong_voxels,
+            transfer_function: transfer_function,
+            labels: labels,
+            label_colours: label_colours,
+            read_ping: true, // init writes ping
             storage_texture: storage_texture,
             texture_view: texture_view,
-            uniforms: uniforms
+            size: size,
+            uniforms: uniforms,
+            present: present
         }
 
     }
 
     pub fn on_resize(&mut self, dims: &Dims3, width: u32, height: u32, gfx_ctx: &GraphicsContext, world: &World, bridge: &Bridge){
+        self.size = PhysicalSize::new(width, height);
 
         self.storage_texture = gfx_ctx.device.create_texture(&TextureDescriptor{
             label: Some("Storage Texture"),
@@ -124,14 +146,14 @@ impl Resources {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
-            view_formats: &[wgpu::TextureFormat::Rgba8Unorm]
+            format: STORAGE_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC,
+            view_formats: &[STORAGE_FORMAT]
         });
 
         self.texture_view = self.storage_texture.create_view(&TextureViewDescriptor{
             label: Some("Texture View"),
-            format: Some(wgpu::TextureFormat::Rgba8Unorm),
+            format: Some(STORAGE_FORMAT),
             dimension: Some(wgpu::TextureViewDimension::D2),
             usage: None,
             aspect: wgpu::TextureAspect::All,
@@ -141,19 +163,7 @@ impl Resources {
             array_layer_count:None
         });
 
-        let unis = Uniforms {
-            window_dims: [width/2, height/2, 0, 0], // could update these via command encoder
-            dims: [dims[0], dims[1], dims[2], dims[0] * dims[1]],
-            bounding_box: [0, 0, 0, 0], // set in render() 
-            cam_pos: [world.camera.c[0], world.camera.c[1], world.camera.c[2], 0.0 as f32],
-            forward: [world.camera.f[0], world.camera.f[1], world.camera.f[2], 0.0 as f32],
-            centre: [world.camera.centre[0], world.camera.centre[1], world.camera.centre[2], 0.0 as f32],
-            up: [world.camera.u[0], world.camera.u[1], world.camera.u[2], 0.0 as f32],
-            right: [world.camera.r[0], world.camera.r[1], world.camera.r[2], 0.0 as f32],
-            timestep: [0.0 as f32, 0.0 as f32, 0.0 as f32, 0.0 as f32],
-            seed: [bridge.rand_seed, 0, 0, 0],
-            flags: [1, 0, 0, 0]
-        };
+        let unis = Uniforms::from_world(PhysicalSize::new(width, height), dims, BoundingBox::default(), 0.0, self.read_ping, bridge.rand_seed, world);
 
         self.uniforms = gfx_ctx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform buffer"),
@@ -165,40 +175,253 @@ impl Resources {
 
     }
 
+    /// Voxel buffer holding the current field (ping if read_ping, else pong)
+    pub fn current_voxels(&self) -> &Buffer {
+        if self.read_ping { &self.ping_voxel_buffer } else { &self.pong_voxel_buffer }
+    }
+
+    /// Overwrites the voxel buffer the next pass reads (see current_voxels())
+    /// e.g. with the field from the CPU backend
+    pub fn write_voxels(&self, gfx_ctx: &GraphicsContext, voxels: &[f32]) {
+        let target = self.current_voxels();
+        assert!((std::mem::size_of_val(voxels) as u64) <= target.size());
+
+        let data = unsafe {
+            std::slice::from_raw_parts(voxels.as_ptr() as *const u8, std::mem::size_of_val(voxels))
+        };
+        gfx_ctx.queue.write_buffer(target, 0, data);
+    }
+
+    /// Writes one static channel's block into both grids (see Channels), the simulation never touches it
+    pub fn write_channel(&self, gfx_ctx: &GraphicsContext, channel: usize, values: &[f32]) {
+        let offset = (channel * std::mem::size_of_val(values)) as u64;
+        assert!(offset + (std::mem::size_of_val(values) as u64) <= self.ping_voxel_buffer.size());
+
+        let data = unsafe {
+            std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values))
+        };
+        gfx_ctx.queue.write_buffer(&self.ping_voxel_buffer, offset, data);
+        gfx_ctx.queue.write_buffer(&self.pong_voxel_buffer, offset, data);
+    }
+
+    /// Writes a box of one static channel into both grids, row by row (a slab at a time when rows span the grid)
+    /// origin and size in voxels, values x fastest over size, e.g. a streamed ZarrBlock
+    pub fn write_block(&self, gfx_ctx: &GraphicsContext, channel: usize, dims: &Dims3, origin: [usize; 3], size: [usize; 3], values: &[f32]) {
+        let [w, h, d] = dims.map(|n| n as usize);
+        assert!((0..3).all(|a| origin[a] + size[a] <= [w, h, d][a]) && values.len() == size[0] * size[1] * size[2]);
+        let block = channel * w * h * d;
+        let rows = if size[0] == w { size[1] } else { 1 }; // per write, whole rows run on into the next
+        for z in 0..size[2] {
+            for y in (0..size[1]).step_by(rows) {
+                let src = &values[((z * size[1]) + y) * size[0]..((z * size[1]) + y + rows) * size[0]];
+                let offset = ((block + ((origin[2] + z) * w * h) + ((origin[1] + y) * w) + origin[0]) * std::mem::size_of::<f32>()) as u64;
+                let data = unsafe {
+                    std::slice::from_raw_parts(src.as_ptr() as *const u8, std::mem::size_of_val(src))
+                };
+                gfx_ctx.queue.write_buffer(&self.ping_voxel_buffer, offset, data);
+                gfx_ctx.queue.write_buffer(&self.pong_voxel_buffer, offset, data);
+            }
+        }
+    }
+
+    /// Uploads a TransferFunction edited at runtime, picked up by the next raymarch
+    /// (range travels in the uniforms, see uniforms_refresh())
+    pub fn write_transfer_function(&self, gfx_ctx: &GraphicsContext, transfer: &TransferFunction) {
+        gfx_ctx.queue.write_buffer(&self.transfer_function, 0, Resources::flatten_lut(&transfer.lut()));
+    }
+
+    /// Uploads the composited channels' lookup tables after the TransferFunction's,
+    /// whenever channel visibility or display settings change
+    pub fn write_channel_luts(&self, gfx_ctx: &GraphicsContext, channels: &Channels) {
+        let offset = (TF_RESOLUTION * std::mem::size_of::<[f32; 4]>()) as u64;
+        gfx_ctx.queue.write_buffer(&self.transfer_function, offset, Resources::flatten_lut(&channels.composite_luts()));
+    }
+
+    /// Replaces the label buffers, the compute bind group has to be rebuilt to see them (see Compute::on_resize())
+    pub fn set_labels(&mut self, gfx_ctx: &GraphicsContext, labels: Option<&LabelVolume>) {
+        (self.labels, self.label_colours) = Resources::label_buffers(gfx_ctx, labels);
+    }
+
+    /// Uploads label colours and visibility edited at runtime, the table's ids must be unchanged
+    pub fn write_label_table(&self, gfx_ctx: &GraphicsContext, table: &LabelTable) {
+        gfx_ctx.queue.write_buffer(&self.label_colours, 0, Resources::flatten_lut(&table.colours()));
+    }
+
+    /// Slot per voxel and colour per slot, a single background voxel and colour without a label volume
+    fn label_buffers(gfx_ctx: &GraphicsContext, labels: Option<&LabelVolume>) -> (Buffer, Buffer) {
+        let (slots, colours) = match labels {
+            Some(l) => (l.slots(), l.table.colours()),
+            None => (vec![0], vec![[0.0; 4]]),
+        };
+        let slots = gfx_ctx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Labels"),
+            contents: unsafe { std::slice::from_raw_parts(slots.as_ptr() as *const u8, std::mem::size_of_val(slots.as_slice())) },
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let colours = gfx_ctx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Label colours"),
+            contents: Resources::flatten_lut(&colours),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        (slots, colours)
+    }
+
+    fn flatten_lut(lut: &[[f32; 4]]) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(lut.as_ptr() as *const u8, std::mem::size_of_val(lut))
+        }
+    }
+
+    /// GPU -> CPU copy of the current field (see current_voxels()), channel-major
+    /// Blocks until the copy has landed, so avoid calling it every frame
+    pub fn read_voxels(&self, gfx_ctx: &GraphicsContext) -> Vec<f32> {
+        let staging = self.stage_voxels(gfx_ctx);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        staging.slice(..).map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+        gfx_ctx.device.poll(wgpu::PollType::Wait).expect("Device lost while reading back voxels\n");
+
+        receiver.recv()
+            .expect("Voxel readback callback dropped\n")
+            .expect("Failed to map voxel staging buffer\n");
+        Resources::unpack_staging(&staging)
+    }
+
+    /// Non-blocking read_voxels(): the copy is queued straight away, the future resolves once it has been mapped
+    /// Mapping callbacks only fire when the device is polled (any queue submit or device.poll()),
+    /// so keep rendering frames, or poll, while awaiting
+    pub async fn read_voxels_async(&self, gfx_ctx: &GraphicsContext) -> Result<Vec<f32>, wgpu::BufferAsyncError> {
+        let staging = self.stage_voxels(gfx_ctx);
+
+        let (sender, receiver) = futures_channel::oneshot::channel();
+        staging.slice(..).map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+
+        receiver.await.map_err(|_| wgpu::BufferAsyncError)??;
+        Ok(Resources::unpack_staging(&staging))
+    }
+
+    /// Copies the current voxel buffer into a new mappable buffer and submits the copy
+    fn stage_voxels(&self, gfx_ctx: &GraphicsContext) -> Buffer {
+        let source = self.current_voxels();
+        let staging = gfx_ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Voxel readback"),
+            size: source.size(),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let mut encoder = gfx_ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder")
+        });
+        encoder.copy_buffer_to_buffer(source, 0, &staging, 0, source.size());
+        gfx_ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        staging
+    }
+
+    /// Storage texture copied back from the GPU as premultiplied RGBA, one row after another
+    /// Rows run bottom to top, the way the raymarch writes them (see world::capture::save_image())
+    pub fn read_frame(&self, gfx_ctx: &GraphicsContext) -> Vec<[f32; 4]> {
+        let (width, height) = (self.size.width, self.size.height);
+        let texel = STORAGE_FORMAT.block_copy_size(None).unwrap();
+        let bytes_per_row = (width * texel).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let staging = gfx_ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame readback"),
+            size: (bytes_per_row * height) as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let mut encoder = gfx_ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Frame Readback Encoder")
+        });
+        encoder.copy_texture_to_buffer(
+            self.storage_texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &staging,
+                layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(bytes_per_row), rows_per_image: None }
+            },
+            Extent3d { width: width, height: height, depth_or_array_layers: 1 });
+        gfx_ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        staging.slice(..).map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+        gfx_ctx.device.poll(wgpu::PollType::Wait).expect("Device lost while reading back frame\n");
+        receiver.recv()
+            .expect("Frame readback callback dropped\n")
+            .expect("Failed to map frame staging buffer\n");
+
+        let pixels = {
+            let data = staging.slice(..).get_mapped_range();
+            data.chunks_exact(bytes_per_row as usize)
+                .flat_map(|row| row[..(width * texel) as usize].chunks_exact(texel as usize))
+                .map(|px| [0, 1, 2, 3].map(|c| half::f16::from_le_bytes([px[2 * c], px[(2 * c) + 1]]).to_f32()))
+                .collect()
+        }; // mapped view dropped before unmap
+        staging.unmap();
+        pixels
+    }
+
+    /// Mapped staging buffer -> f32s, unmaps it again
+    fn unpack_staging(staging: &Buffer) -> Vec<f32> {
+        let voxels = {
+            let data = staging.slice(..).get_mapped_range();
+            data.chunks_exact(std::mem::size_of::<f32>())
+                .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect()
+        }; // mapped view dropped before unmap
+        staging.unmap();
+        voxels
+    }
+
     pub fn uniforms_refresh(&mut self, 
-        gfx_ctx: &GraphicsContext, read_ping: &bool, 
+        gfx_ctx: &GraphicsContext, 
         duration: f32, bbox: BoundingBox, dims: &Dims3, 
         world: &World) {
         if gfx_ctx.surface_configured == true {
 
-            let uniforms = Uniforms {
-                window_dims: [gfx_ctx.surface_config.width/2, gfx_ctx.surface_config.height/2, 0, 0],
-                dims: [dims[0], dims[1], dims[2], dims[0] * dims[1]],
-                bounding_box: [bbox[0][0], bbox[0][1], bbox[1][0], bbox[1][1]],
-                cam_pos: [world.camera.c[0], world.camera.c[1], world.camera.c[2], 0.0],
-                forward: [world.camera.f[0], world.camera.f[1], world.camera.f[2], 0.0],
-                centre: [world.camera.centre[0], world.camera.centre[1], world.camera.centre[2], 0.0],
-                up: [world.camera.u[0], world.camera.u[1], world.camera.u[2], 0.0 as f32],
-                right: [world.camera.r[0], world.camera.r[1], world.camera.r[2], world.right_sf],
-                timestep: [duration, 0.0, 0.0, 0.0],
-                seed: [0, 0, 0, 0 ], // could later reintroduce seed here for hot sim resizing 
-                flags: [*read_ping as u32, 0, 0, 0]
-            };
+            let uniforms = Uniforms::from_world(self.size, dims, bbox, duration, self.read_ping, 0, world); // seed only matters to init
 
             let data = uniforms.flatten_u8();
 
-            self.uniforms = gfx_ctx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform buffer"),
-            contents: data,
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-            });
-
+            // write into the existing buffer: the compute bind group holds this one,
+            // a freshly created buffer would never reach the shaders
             gfx_ctx.queue.write_buffer(&self.uniforms, 0, data);
         }
         else { panic!("Tried to update uniforms with outdated graphics context\n") }
 
     }
 
+    /// Writes how the fragment shader fits the storage texture onto the surface:
+    /// letterboxed when their aspects differ (App keeps the texture 16:9), background outside the
+    /// bounding box and slice panes, decoded to linear for sRGB surface formats
+    pub fn present_refresh(&self, gfx_ctx: &GraphicsContext, bbox: BoundingBox, world: &World) {
+        let (tw, th) = (self.size.width as f32, self.size.height as f32);
+        let (sw, sh) = (gfx_ctx.surface_config.width as f32, gfx_ctx.surface_config.height as f32);
+        let scale = (sw / tw).min(sh / th);
+        let (w, h) = ((tw * scale) / sw, (th * scale) / sh);
+
+        // bounding box is in pixels from mid window, see World::generate_bb_projection()
+        let (mx, my) = ((self.size.width / 2) as f32, (self.size.height / 2) as f32);
+        let volume = if world.slices.shows_volume() {
+            [(mx + bbox[0][0] as f32) / tw, (my + bbox[0][1] as f32) / th, (mx + bbox[1][0] as f32 + 1.0) / tw, (my + bbox[1][1] as f32 + 1.0) / th]
+        } else { [0.0; 4] };
+        let slices = match world.slices.region(self.size.width, self.size.height) {
+            Some(r) => [r[0] as f32 / tw, r[1] as f32 / th, r[2] as f32 / tw, r[3] as f32 / th],
+            None => [0.0; 4],
+        };
+
+        let bg = world.render.background;
+        let present = Present {
+            letterbox: [(1.0 - w) / 2.0, (1.0 - h) / 2.0, w, h],
+            volume: volume,
+            slices: slices,
+            background: [bg[0], bg[1], bg[2], 1.0],
+            flags: [gfx_ctx.surface_config.format.is_srgb() as u32, 0, 0, 0]
+        };
+        gfx_ctx.queue.write_buffer(&self.present, 0, present.flatten_u8());
+    }
+
     }
 
 
@@ -216,13 +439,80 @@ pub struct Uniforms {
     centre: [f32; 4],
     up: [f32; 4], // [2]< padding
     right: [f32; 4], // [2]< padding
-    timestep: [f32; 4], // only [0]
+    timestep: [f32; 4], // only [0], substep dt
     seed: [u32; 4], // only [0]
-    flags: [u32; 4]
+    flags: [u32; 4], // [0] read ping and render mode (see RenderSettings::flags()), [1..4] x, y, z boundary conditions (see Boundaries::flags())
+    boundary_low: [f32; 4], // Dirichlet values on the index 0 faces
+    boundary_high: [f32; 4], // Dirichlet values on the index dims - 1 faces
+    diffusion: [f32; 4], // [0] D, [1..3] inverse squared spacing (see SimulationParams::uniform())
+    reaction: [f32; 4], // see ReactionModel::params()
+    species_diffusion: [f32; 4], // D per species
+    reaction_flags: [u32; 4], // see ReactionDiffusion::flags()
+    transfer: [f32; 4], // see TransferFunction::uniform()
+    raymarch: [f32; 4], // see RenderSettings::uniform()
+    render_params: [f32; 4], // see RenderSettings::params()
+    lighting: [f32; 4], // see Lighting::uniform()
+    slices: [u32; 4], // see Slices::uniform()
+    clip_planes: [[f32; 4]; MAX_CLIP_PLANES], // see Clipping::planes_uniform()
+    crop_min: [f32; 4], // crop box in voxel coordinates, see Clipping::crop_uniform()
+    crop_max: [f32; 4],
+    composite_flags: [u32; 4], // [0] ChannelBlend::code(), [1] composited channel count
+    composite_channels: [u32; 4], // see Channels::composite_uniform()
+    composite_ranges: [[f32; 4]; MAX_COMPOSITE],
+    label_flags: [u32; 4], // see LabelVolume::uniform(), zeros without a label volume
+    world_from_voxel: [[f32; 4]; 3], // see VoxelTransform::uniform()
+    voxel_from_world: [[f32; 4]; 3],
+    spacing: [f32; 4], // VoxelTransform::spacing, for the slices' aspect
 
 }
 
 impl Uniforms {
+    /// Everything the shaders read about the world this frame
+    /// size: the storage texture, duration: substep dt, read_ping: which grid holds the current field, seed: init.wgsl's
+    pub fn from_world(size: PhysicalSize<u32>, dims: &Dims3, bbox: BoundingBox, duration: f32, read_ping: bool, seed: u32, world: &World) -> Self {
+        let view = world.camera.view();
+        let bc_flags = world.sim.boundaries.flags();
+        let bc_values = world.sim.boundaries.values();
+        let crop = world.clipping.crop_uniform();
+        let (composite_channels, composite_ranges) = world.voxel_grid.channels.composite_uniform();
+        let (world_from_voxel, voxel_from_world) = world.voxel_grid.transform.uniform();
+        let spacing = world.voxel_grid.transform.spacing;
+        Uniforms {
+            window_dims: [size.width/2, size.height/2, 0, 0],
+            dims: [dims[0], dims[1], dims[2], dims[0] * dims[1]],
+            bounding_box: [bbox[0][0], bbox[0][1], bbox[1][0], bbox[1][1]],
+            cam_pos: [view.c[0], view.c[1], view.c[2], 0.0],
+            forward: [view.f[0], view.f[1], view.f[2], 0.0],
+            centre: [view.centre[0], view.centre[1], view.centre[2], 0.0],
+            up: [view.u[0], view.u[1], view.u[2], 0.0],
+            right: [view.r[0], view.r[1], view.r[2], world.right_sf],
+            timestep: [duration, 0.0, 0.0, 0.0],
+            seed: [seed, 0, 0, 0],
+            flags: [world.render.flags(read_ping), bc_flags[0], bc_flags[1], bc_flags[2]],
+            boundary_low: bc_values[0],
+            boundary_high: bc_values[1],
+            diffusion: world.sim.uniform(),
+            reaction: world.sim.reaction.model.params(),
+            species_diffusion: world.sim.reaction.diffusion,
+            reaction_flags: world.sim.reaction.flags(world.voxel_grid.channels.shown(), world.voxel_grid.channels.len()),
+            transfer: world.transfer.uniform(),
+            raymarch: world.render.uniform(world.frame),
+            render_params: world.render.params(),
+            lighting: world.render.lighting.uniform(),
+            slices: world.slices.uniform(),
+            clip_planes: world.clipping.planes_uniform(),
+            crop_min: crop[0],
+            crop_max: crop[1],
+            composite_flags: [world.render.blend.code(), world.voxel_grid.channels.composited().len() as u32, 0, 0],
+            composite_channels: composite_channels,
+            composite_ranges: composite_ranges,
+            label_flags: world.labels.as_ref().map_or([0; 4], |l| l.uniform()),
+            world_from_voxel: world_from_voxel,
+            voxel_from_world: voxel_from_world,
+            spacing: [spacing[0], spacing[1], spacing[2], 0.0]
+        }
+    }
+
     pub fn flatten_u8(&self) -> &[u8] {
         let ptr = self as *const _ as *const u8;
 
@@ -233,4 +523,23 @@ impl Uniforms {
             std::slice::from_raw_parts(ptr, len)
         }
     }
-}
\ No newline at end of file
+}
+
+/// Fragment shader's view of the storage texture, see Resources::present_refresh()
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Present {
+    letterbox: [f32; 4], // storage texture on the surface in uv (top left origin): x, y, width, height
+    volume: [f32; 4], // raymarched bounding box in texture uv (rows counting up): min u, min v, max u, max v
+    slices: [f32; 4], // slice panes in texture uv, as volume (see Slices::region())
+    background: [f32; 4], // sRGB, see RenderSettings::background
+    flags: [u32; 4] // [0] surface format is sRGB
+}
+
+impl Present {
+    pub fn flatten_u8(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(self as *const _ as *const u8, std::mem::size_of::<Present>())
+        }
+    }
+}
@@ -4,12 +4,61 @@ use crate::{
     backend_admin::{
         bridge::Bridge, 
         gpu::{
-            compute::Compute, gfx_context::GraphicsContext, render::Render, resources::Resources}}, 
+            compute::{Compute, ComputeBackend}, gfx_context::GraphicsContext, render::Render, resources::Resources}}, 
     world::{
-        voxel_grid::Dims3, 
+        camera::{Camera, MoveInput},
+        channels::{Channel, ChannelDisplay, ChannelType, Channels},
+        clipping::{Clipping, MAX_CLIP_PLANES},
+        labels::{Label, LabelVolume},
+        diffusion::{CpuDiffusion, TimeStep, CFL_SAFETY},
+        mesh::Mesh,
+        reaction::ReactionDiffusion,
+        capture::{self, CameraKey, CameraPath, FrameSequence, ImageFormat},
+        rendering::RenderMode,
+        slices::Slices,
+        tiff_stack::TiffStack,
+        zarr::{OmeZarr, ZarrStream},
+        transfer_function::TransferFunction,
+        voxel_grid::{Dims3, VoxelGrid, VoxelTransform},
+        volume::{Encoding, Volume}, 
         world::{World}}
     };
 use std::error::Error;
+use rayon::prelude::*;
+
+/// Upper bound on diffusion substeps per frame for TimeStep::Adaptive
+const MAX_SUBSTEPS: u32 = 64;
+
+/// Raymarch step size bounds for the - and = keys, in voxels
+const MIN_STEP: f32 = 0.125;
+const MAX_STEP: f32 = 4.0;
+
+/// Voxels the ; and ' keys move a crop box face
+const CROP_STEP: f32 = 4.0;
+
+/// Where F5 saves and F9 loads the transfer function
+const TRANSFER_FUNCTION_PATH: &str = "transfer_function.json";
+
+/// Where F12 saves a screenshot (format follows the extension, see ImageFormat)
+const CAPTURE_PATH: &str = "capture.png";
+
+/// Where F6 exports the isosurface mesh (format follows the extension, see MeshFormat)
+const MESH_PATH: &str = "isosurface.ply";
+
+/// Where F7 saves the shown channel and F8 loads a volume (NRRD, see Volume)
+const VOLUME_PATH: &str = "field.nrrd";
+
+/// Where F10 opens a multi-page TIFF or OME-TIFF stack, PageUp/PageDown step its time points (see TiffStack)
+const STACK_PATH: &str = "stack.tif";
+
+/// Where F11 opens an OME-Zarr image, PageUp/PageDown step its time points (see OmeZarr)
+const ZARR_PATH: &str = "image.zarr";
+
+/// OME-Zarr blocks decoded and uploaded per frame, see State::stream_zarr()
+const ZARR_BLOCKS_PER_FRAME: usize = 8;
+
+/// Label table opacities the O key cycles through: opaque, semi-transparent, hidden
+const LABEL_OPACITIES: [f32; 3] = [1.0, 0.4, 0.0];
 
 pub struct State {
     pub gfx_ctx: GraphicsContext,
@@ -17,20 +66,35 @@ pub struct State {
     bridge: Bridge,
     resources: Resources,
     compute: Compute,
+    pub backend: ComputeBackend,
     render: Render,
 
     dims: Dims3,
     init_complete: bool,
-    read_ping: bool,
     time: std::time::Instant,
 
     pub mouse_pos: Option<PhysicalPosition<f64>>,
+    pub pan_held: bool, // right mouse button, cursor moves pan instead of rotate
+    pub move_input: MoveInput, // WASD held down
+    pub stack: Option<(TiffStack, usize)>, // opened stack and the time point loaded from it
+    pub zarr: Option<ZarrStream>, // OME-Zarr image streaming into the grid, see stream_zarr()
 }
 
 impl State {
     
     pub async fn new(window: Arc<Window>) -> Result<Self, Box<dyn Error>> {
-        let mut gfx_ctx: GraphicsContext = GraphicsContext::new(window).await?;
+        let gfx_ctx: GraphicsContext = GraphicsContext::new(window).await?;
+        Ok(State::from_context(gfx_ctx))
+    }
+
+    /// State without a window: frames are rendered into the context's offscreen texture
+    /// See GraphicsContext::new_headless() for adapter selection
+    pub async fn new_headless(size: PhysicalSize<u32>) -> Result<Self, Box<dyn Error>> {
+        let gfx_ctx: GraphicsContext = GraphicsContext::new_headless(size).await?;
+        Ok(State::from_context(gfx_ctx))
+    }
+
+    fn from_context(mut gfx_ctx: GraphicsContext) -> Self {
         let dims: Dims3 = [200, 200, 200];
         // World contains voxel_grid and camera
         let world = World::new(dims, &gfx_ctx);
@@ -40,126 +104,447 @@ impl State {
 
         let resources = Resources::new(&dims, &world, &bridge, &mut gfx_ctx);
         
-        let compute = Compute::new(&dims, &resources, &gfx_ctx);
+        let compute = Compute::new(&resources, &gfx_ctx);
         
         let render = Render::new(&resources, &gfx_ctx);
+
+        // Software adapters run the stencil slower than rayon does, so diffuse on the CPU instead
+        let backend = if gfx_ctx.is_software() {
+            println!("Software adapter detected, using CPU diffusion backend\n");
+            ComputeBackend::Cpu(CpuDiffusion::new(dims))
+        }
+        else { ComputeBackend::Gpu };
         
-        Ok (
-            Self { 
-                gfx_ctx: gfx_ctx,
-                world: world,
-                bridge: bridge,
-                resources: resources,
-                compute: compute,
-                render: render,
-
-                init_complete: false,
-                read_ping: true,
-                dims: dims,
-                time: std::time::Instant::now(),
-
-                mouse_pos: None
-                }
-        )
+        Self { 
+            gfx_ctx: gfx_ctx,
+            world: world,
+            bridge: bridge,
+            resources: resources,
+            compute: compute,
+            backend: backend,
+            render: render,
+
+            init_complete: false,
+            dims: dims,
+            time: std::time::Instant::now(),
+
+            mouse_pos: None,
+            pan_held: false,
+            move_input: MoveInput::default(),
+            stack: None,
+            zarr: None
+            }
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         println!("Resize called\n");
         if (width != self.gfx_ctx.surface_config.width || height != self.gfx_ctx.surface_config.height) && (width > 0 && height > 0) { 
             println!("Resize if passed\n");
+            if self.gfx_ctx.window().is_none() { self.gfx_ctx.size = PhysicalSize { width, height }; } // headless targets take their size from here
             self.gfx_ctx.update_surface_config();
-            self.world.camera.update(None, None, None, Some(&PhysicalSize {width, height})); // TODO: REPLACE OPTIONS WITH ENUMS
+            self.retarget(PhysicalSize {width, height});
+        }
+    }   
 
-            self.bridge.update_raymarch_dispatch(self.world.bbox);
+    /// Swaps the reaction model and species, reallocating the voxel buffers and restarting the simulation
+    /// The preset's timestep replaces SimulationParams::timestep
+    /// Simulated channels are fitted to the model's species (see Channels::fit_species()), showing the preset's species
+    pub fn set_reaction(&mut self, reaction: ReactionDiffusion) {
+        self.world.sim.reaction = reaction;
+        self.world.sim.timestep = reaction.preset_timestep();
 
-            self.resources.on_resize(&self.dims, width, height, &self.gfx_ctx, &self.world, &self.bridge);
+        let channels = &mut self.world.voxel_grid.channels;
+        channels.fit_species(reaction.species, |s| reaction.species_name(s));
+        let _ = channels.show(&reaction.species_name(reaction.preset_shown()));
+        self.restart();
+    }
 
-            self.compute.on_resize(&self.dims, &self.gfx_ctx, &self.resources);
+    /// Adds a named channel to the grid, returns its index
+    /// Reallocates the voxel buffers, so the simulation restarts
+    pub fn add_channel(&mut self, channel: Channel) -> Result<usize, Box<dyn Error>> {
+        let voxels = self.world.voxel_grid.voxels();
+        let index = self.world.voxel_grid.channels.add(channel, voxels)?;
+        self.fit_reaction()?;
+        self.restart();
+        Ok(index)
+    }
 
-            self.render.on_resize(&self.gfx_ctx, &self.resources);
+    /// Removes a named channel from the grid, restarting the simulation
+    pub fn remove_channel(&mut self, name: &str) -> Result<Channel, Box<dyn Error>> {
+        let channel = self.world.voxel_grid.channels.remove(name)?;
+        if let Err(e) = self.fit_reaction() {
+            let _ = self.world.voxel_grid.channels.add(channel, self.world.voxel_grid.voxels());
+            return Err(e);
         }
-    }   
-
-    
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let surface_texture = self.gfx_ctx.surface.get_current_texture()?;
-        // this defines how the texture is interpreted (sampled) to produce the actual pixel outputs to the surface
-        // texel -> pixel
-        let surface_texture_view = surface_texture.texture.create_view(&wgpu::TextureViewDescriptor::default()); // both associated with surface
+        self.restart();
+        Ok(channel)
+    }
 
-        self.world.generate_bb_projection(&self.gfx_ctx); 
+    /// Moves a channel in or out of the simulation, restarting it (a channel's values become its starting field)
+    pub fn set_simulated(&mut self, name: &str, simulated: bool) -> Result<(), Box<dyn Error>> {
+        self.world.voxel_grid.channels.set_simulated(name, simulated)?;
+        if let Err(e) = self.fit_reaction() {
+            self.world.voxel_grid.channels.set_simulated(name, !simulated)?;
+            return Err(e);
+        }
+        self.restart();
+        Ok(())
+    }
 
-        let mut encoder = self.gfx_ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Command Encoder")
-        });
+    /// Channel read by the raymarch, slices and mesh extraction, the transfer function takes its value range
+    pub fn show_channel(&mut self, index: usize) {
+        let channels = &mut self.world.voxel_grid.channels;
+        let Some(name) = channels.get(index).map(|c| c.name.clone()) else { return; };
+        let _ = channels.show(&name);
+        self.world.transfer.range = channels.get(index).unwrap().range; // range travels in the uniforms
+    }
 
-        // UPDATE TIMESTEP //
-        let now = std::time::Instant::now();
-        let duration = ((now - self.time).as_secs_f32()).min(0.1666666); // stability bound for 3D euler integration
-        // let fps = 1.0 / duration;
-        //println!("fps: {}\n", fps);
-        self.time = now;
+    /// Replaces a channel's colour, transfer function, visibility and weight, uploading the composite lookup tables
+    pub fn set_channel_display(&mut self, index: usize, display: ChannelDisplay) -> Result<(), Box<dyn Error>> {
+        let channel = self.world.voxel_grid.channels.get_mut(index).ok_or(format!("No channel {}", index))?;
+        channel.display = display;
+        self.resources.write_channel_luts(&self.gfx_ctx, &self.world.voxel_grid.channels);
+        Ok(())
+    }
 
-        // Ping pong flag
-        self.read_ping = if self.init_complete{ !self.read_ping }
-        else { self.read_ping };
+    /// Places the grid in the world in physical units (spacing, origin, affine) and reframes the camera
+    /// The diffusion step takes the same spacing, so D is in the transform's units squared per second
+    pub fn set_voxel_transform(&mut self, transform: VoxelTransform) {
+        self.world.set_voxel_transform(transform, &self.gfx_ctx.size);
+        self.world.sim.spacing = transform.spacing;
+    }
 
-        // UPDATE AND WRITE NEW UNIFORMS BUFFER TO QUEUE
-        self.resources.uniforms_refresh(&self.gfx_ctx, &self.read_ping, duration, self.world.bbox, &self.dims, &self.world);
+    /// Replaces the grid with a loaded volume (its dims, placement and values), restarting the simulation from
+    /// the volume in place of init.wgsl's noise, see load_volumes()
+    pub fn load_volume(&mut self, volume: Volume) -> Result<(), Box<dyn Error>> {
+        self.load_volumes(vec![volume])
+    }
 
-        if !self.init_complete {
-        {   
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
-                label: Some("Init"),
-                timestamp_writes: None
-                });
+    /// Replaces the grid with co-registered volumes (e.g. the channels of a TiffStack time point), one channel each
+    /// The first becomes the simulated channel, starting from its values in place of init.wgsl's noise, the rest are static
+    /// A grid of new dims or placement resets labels, slices, clipping and the camera; loading into the same grid
+    /// (another time point) keeps them, along with each channel's display settings
+    pub fn load_volumes(&mut self, volumes: Vec<Volume>) -> Result<(), Box<dyn Error>> {
+        let first = volumes.first().ok_or("No volumes to load")?;
+        let (dims, transform) = (first.dims, first.transform);
+        if dims.contains(&0) {
+            return Err(format!("Volume {} has an empty axis, {}x{}x{}", first.name, dims[0], dims[1], dims[2]).into());
+        }
+        if !transform.is_invertible() {
+            return Err(format!("Volume {} has a singular transform, spacing {:?}", first.name, transform.spacing).into());
+        }
+        let voxels: usize = dims.iter().map(|&d| d as usize).product();
+        if let Some(v) = volumes.iter().find(|v| v.dims != dims || v.values.len() != voxels) {
+            return Err(format!("Volume {} doesn't match {}x{}x{}", v.name, dims[0], dims[1], dims[2]).into());
+        }
+        let same_grid = dims == self.dims && transform == self.world.voxel_grid.transform;
+        let previous = self.world.voxel_grid.channels.clone();
 
-            // RAND SEED INIT
-            compute_pass.set_pipeline(&self.compute.init_p);
-            compute_pass.set_bind_group(0, &self.compute.bg, &[]); 
-            let [x, y, z] = self.bridge.init_dispatch;
-            compute_pass.dispatch_workgroups(x, y, z);  // group size is 8 * 4 * 8 <= 256 (256, 256, 64 respective limits)
-            self.init_complete = true;
+        let mut volumes = volumes.into_iter();
+        let first = volumes.next().unwrap();
+        let mut channels = Channels::from_channel(Channel::from_values(&first.name, first.dtype, first.values));
+        for volume in volumes {
+            channels.add(Channel::from_values(&volume.name, volume.dtype, volume.values), voxels)?;
+        }
+        let reaction = self.world.sim.reaction;
+        channels.fit_species(reaction.species, |s| reaction.species_name(s));
+        let bytes = (voxels * std::mem::size_of::<f32>() * channels.len()) as u64;
+        if bytes > self.max_voxel_bytes() {
+            return Err(format!("{}x{}x{} with {} channels needs {} bytes per voxel buffer, the device allows {}", dims[0], dims[1], dims[2], channels.len(), bytes, self.max_voxel_bytes()).into());
+        }
 
-            // RAYMARCH
-            compute_pass.set_pipeline(&self.compute.raymarch_p);
-            compute_pass.set_bind_group(0, &self.compute.bg, &[]); 
-            let [x, y, z] = self.bridge.raymarch_dispatch;
-            compute_pass.dispatch_workgroups(x, y, z); 
+        let mut shown = 0; // MUT
+        if same_grid {
+            for c in 0..channels.len() {
+                let name = channels.get(c).unwrap().name.clone();
+                if let Some(old) = previous.index_of(&name).and_then(|i| previous.get(i)) {
+                    channels.get_mut(c).unwrap().display = old.display.clone();
+                }
+            }
+            shown = previous.get(previous.shown()).and_then(|old| channels.index_of(&old.name)).unwrap_or(0);
+        } else {
+            self.replace_grid(dims, transform, true);
         }
+        self.world.voxel_grid.channels = channels;
+        self.zarr = None;
+
+        self.show_channel(shown);
+        self.restart();
+        Ok(())
+    }
+
+    /// New grid of dims placed by transform, keeping the channels (their values are for the caller to replace), labels dropped
+    /// reframe: a different grid, so clipping, slices and the camera start over; otherwise the same grid at other dims
+    /// (an OmeZarr level), so clipping and slices are rescaled and the camera kept
+    fn replace_grid(&mut self, dims: Dims3, transform: VoxelTransform, reframe: bool) {
+        if reframe {
+            self.world.clipping = Clipping::new(&dims);
+            self.world.slices = Slices::new(&dims);
+        } else {
+            self.world.clipping.rescale(&self.dims, &dims);
+            self.world.slices.rescale(&self.dims, &dims);
         }
+        self.dims = dims;
+        let channels = std::mem::take(&mut self.world.voxel_grid.channels);
+        self.world.voxel_grid = VoxelGrid::new_centered_at_origin(dims);
+        self.world.voxel_grid.channels = channels;
+        self.world.labels = None;
+        if reframe { self.set_voxel_transform(transform); }
         else {
-            {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
-                label: Some("Laplacian"),
-                timestamp_writes: None
-                });
-    
+            self.world.voxel_grid.set_transform(transform);
+            self.world.sim.spacing = transform.spacing;
+        }
+        self.bridge.update_grid_dispatch(&self.world.voxel_grid);
+        if let ComputeBackend::Cpu(_) = self.backend { self.backend = ComputeBackend::Cpu(CpuDiffusion::new(dims)); }
+    }
 
-            compute_pass.set_pipeline(&self.compute.laplacian_p);
-            compute_pass.set_bind_group(0, &self.compute.bg, &[]); 
-             let [x, y, z] = self.bridge.laplacian_dispatch;
-            compute_pass.dispatch_workgroups(x, y, z);  // group size is 8 * 4 * 8 <= 256 (256, 256, 64 respective limits)
-            // Raymarch
-            compute_pass.set_pipeline(&self.compute.raymarch_p);
-            compute_pass.set_bind_group(0, &self.compute.bg, &[]); 
-            let [x, y, z] = self.bridge.raymarch_dispatch; 
-            compute_pass.dispatch_workgroups(x, y, z);
+    /// Opens a stack and loads its first time point, later ones load on demand through load_time_point()
+    pub fn open_stack(&mut self, path: &std::path::Path) -> Result<(), Box<dyn Error>> {
+        let stack = TiffStack::open(path)?;
+        self.load_volumes(stack.load_time_point(0)?)?;
+        self.stack = Some((stack, 0));
+        Ok(())
+    }
+
+    /// Decodes time point t of the open stack into the grid, keeping the view
+    pub fn load_time_point(&mut self, t: usize) -> Result<(), Box<dyn Error>> {
+        let (stack, previous) = self.stack.take().ok_or("No stack open")?;
+        let loaded = stack.load_time_point(t).and_then(|volumes| self.load_volumes(volumes));
+        self.stack = Some((stack, if loaded.is_ok() { t } else { previous }));
+        loaded
+    }
+
+    /// Opens an OME-Zarr image at its coarsest level, one static channel per image channel (the simulated species hidden)
+    /// Blocks then stream in each frame, and the level follows the grid's size on screen, see stream_zarr()
+    pub fn open_zarr(&mut self, path: &std::path::Path) -> Result<(), Box<dyn Error>> {
+        let source = OmeZarr::open(path)?;
+        let ranges = source.ranges(0)?;
+        let level = source.levels.len() - 1;
+
+        let mut channels = Channels::new();
+        for (name, range) in source.channel_names.iter().zip(ranges) {
+            channels.add(Channel { range: range, ..Channel::new(name, source.levels[level].array.dtype) }, 0)?;
+        }
+        let reaction = self.world.sim.reaction;
+        channels.fit_species(reaction.species, |s| reaction.species_name(s));
+        for s in 0..channels.species() { channels.get_mut(s).unwrap().display.visible = false; }
+        let shown = channels.species();
+
+        self.replace_grid(source.levels[level].dims, source.transform(level), true);
+        self.world.voxel_grid.channels = channels;
+        self.zarr = Some(ZarrStream::new(source, level, 0));
+        self.stack = None;
+
+        self.show_channel(shown);
+        self.restart();
+        Ok(())
+    }
+
+    /// Streams time point t of the open OME-Zarr image in place of the current one
+    pub fn load_zarr_time_point(&mut self, t: usize) -> Result<(), Box<dyn Error>> {
+        let stream = self.zarr.as_mut().ok_or("No OME-Zarr image open")?;
+        if t >= stream.source.time_points() {
+            return Err(format!("Time point {} out of range, {} has {}", t, stream.source.path.display(), stream.source.time_points()).into());
+        }
+        stream.t = t;
+        stream.requeue();
+        Ok(())
+    }
+
+    /// Switches the open OME-Zarr image to the level for the grid's size on screen (OmeZarr::level_for()),
+    /// otherwise uploads the ZARR_BLOCKS_PER_FRAME pending blocks nearest the camera, decoded in parallel
+    /// Runs after advance(), so a restarted grid has had its init pass before blocks land in it
+    fn stream_zarr(&mut self) {
+        let max_bytes = self.max_voxel_bytes();
+        let Some(stream) = &mut self.zarr else { return; };
+
+        // LEVEL OF DETAIL //
+        let max_voxels = (max_bytes / (std::mem::size_of::<f32>() * self.world.voxel_grid.channels.len()) as u64) as usize;
+        let crop = self.world.clipping.crop;
+        let fraction = [0, 1, 2].map(|a| (crop.max[a] - crop.min[a]) / self.dims[a] as f32);
+        let level = stream.source.level_for(self.world.projected_extent(), fraction, max_voxels);
+        if level != stream.level {
+            println!("OME-Zarr level {}: {:?} voxels\n", level, stream.source.levels[level].dims);
+            stream.level = level;
+            let (dims, transform) = (stream.source.levels[level].dims, stream.source.transform(level));
+            self.replace_grid(dims, transform, false);
+            self.restart(); // requeues every block
+            return;
+        }
+
+        // STREAM //
+        if stream.is_complete() { return; }
+        let eye = self.world.voxel_grid.transform.to_voxel(self.world.camera.view().c);
+        let blocks = stream.next_blocks(ZARR_BLOCKS_PER_FRAME, eye);
+        let source = &stream.source;
+        let decoded: Vec<_> = blocks.par_iter().map(|&b| source.read_block(stream.level, stream.t, b).map_err(|e| e.to_string())).collect(); // Box<dyn Error> isn't Send
+        for block in decoded {
+            let block = match block {
+                Ok(block) => block,
+                Err(e) => { println!("Couldn't read OME-Zarr block: {}\n", e); continue; },
+            };
+            for (name, values) in source.channel_names.iter().zip(&block.values) {
+                if let Some(c) = self.world.voxel_grid.channels.index_of(name) {
+                    self.resources.write_block(&self.gfx_ctx, c, &self.dims, block.origin, block.size, values);
+                }
             }
         }
-        
+    }
+
+    /// Writes the shown channel of the current field to NRRD, placed by the grid's VoxelTransform
+    pub fn save_volume(&self, path: &std::path::Path, encoding: Encoding) -> Result<(), Box<dyn Error>> {
+        let voxels = self.world.voxel_grid.voxels();
+        let shown = self.world.voxel_grid.channels.shown();
+        let field = self.read_voxels();
+        let volume = Volume {
+            name: self.world.voxel_grid.channels.get(shown).unwrap().name.clone(),
+            dims: self.dims,
+            dtype: ChannelType::F32,
+            values: field[shown * voxels..(shown + 1) * voxels].to_vec(),
+            transform: self.world.voxel_grid.transform
+        };
+        volume.save_nrrd(path, encoding)
+    }
+
+    /// Replaces the label volume drawn over the grid (None removes it), without restarting the simulation
+    pub fn set_labels(&mut self, labels: Option<LabelVolume>) -> Result<(), Box<dyn Error>> {
+        let voxels = self.world.voxel_grid.voxels();
+        if let Some(l) = labels.as_ref().filter(|l| l.ids.len() != voxels) {
+            return Err(format!("Label volume {} has {} voxels, expected {}", l.name, l.ids.len(), voxels).into());
+        }
+        self.world.labels = labels;
+        self.resources.set_labels(&self.gfx_ctx, self.world.labels.as_ref());
+        self.compute.on_resize(&self.gfx_ctx, &self.resources); // bind group holds the old buffers
+        Ok(())
+    }
+
+    /// Replaces the name, colour and visibility of a label already in the table
+    pub fn set_label(&mut self, id: u32, label: Label) -> Result<(), Box<dyn Error>> {
+        let volume = self.world.labels.as_mut().ok_or("No label volume")?;
+        *volume.table.get_mut(id).ok_or(format!("No label {} in {}", id, volume.name))? = label;
+        self.resources.write_label_table(&self.gfx_ctx, &volume.table);
+        Ok(())
+    }
+
+    /// Opacity of every visible label, 1 for opaque shaded regions
+    pub fn set_label_opacity(&mut self, opacity: f32) -> Result<(), Box<dyn Error>> {
+        let volume = self.world.labels.as_mut().ok_or("No label volume")?;
+        volume.table.opacity = opacity;
+        self.resources.write_label_table(&self.gfx_ctx, &volume.table);
+        Ok(())
+    }
+
+    /// Species count follows the simulated channels, which have to cover the reaction model
+    fn fit_reaction(&mut self) -> Result<(), Box<dyn Error>> {
+        let species = self.world.voxel_grid.channels.species();
+        if species < self.world.sim.reaction.model.species() {
+            return Err(format!("{:?} needs {} simulated channels", self.world.sim.reaction.model, self.world.sim.reaction.model.species()).into());
+        }
+        self.world.sim.reaction.species = species;
+        Ok(())
+    }
+
+    /// Largest voxel buffer (every channel of the grid) the device can bind
+    fn max_voxel_bytes(&self) -> u64 {
+        let limits = self.gfx_ctx.device.limits();
+        (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size)
+    }
+
+    /// Reallocates the voxel buffers for the current channels and restarts the simulation
+    fn restart(&mut self) {
+        self.resources = Resources::new(&self.dims, &self.world, &self.bridge, &mut self.gfx_ctx);
+        self.compute = Compute::new(&self.resources, &self.gfx_ctx);
+        self.render = Render::new(&self.resources, &self.gfx_ctx);
+        if let Some(stream) = &mut self.zarr { stream.requeue(); } // streamed blocks went with the old buffers
+
+        self.init_complete = false;
+    }
+
+    /// Species-major starting field: the reaction model's, with simulated channels that hold values starting from them
+    /// None leaves plain diffusion without data to init.wgsl's noise
+    fn initial_species(&self) -> Option<Vec<f32>> {
+        let reaction = &self.world.sim.reaction;
+        let channels = &self.world.voxel_grid.channels;
+        let simulated = channels.iter().take(reaction.species);
+        if !reaction.is_active() && simulated.clone().all(|c| c.values.is_empty()) { return None; }
+
+        let voxels = self.world.voxel_grid.voxels();
+        let mut field = reaction.initial_state(&self.dims, self.bridge.rand_seed);
+        for (s, channel) in simulated.enumerate().filter(|(_, c)| !c.values.is_empty()) {
+            field[s * voxels..(s + 1) * voxels].copy_from_slice(&channel.values);
+        }
+        Some(field)
+    }
+
+    /// Replaces the raymarch's transfer function, uploading its lookup table
+    pub fn set_transfer_function(&mut self, transfer: TransferFunction) {
+        self.world.transfer = transfer;
+        self.resources.write_transfer_function(&self.gfx_ctx, &self.world.transfer);
+    }
+
+    /// Current field copied back from the GPU, channel-major (see Resources::read_voxels())
+    pub fn read_voxels(&self) -> Vec<f32> {
+        self.resources.read_voxels(&self.gfx_ctx)
+    }
+
+    /// Marching cubes over the shown channel of the field read back from the GPU, in world units (see VoxelTransform)
+    /// Every other channel is sampled at the vertices as a scalar attribute named after it
+    pub fn extract_mesh(&self, iso: f32) -> Mesh {
+        let voxels = self.world.voxel_grid.voxels();
+        let field = self.read_voxels();
+        let channels = &self.world.voxel_grid.channels;
+        let shown = channels.shown();
+
+        let mut mesh = Mesh::marching_cubes(&field[shown * voxels..(shown + 1) * voxels], &self.dims, iso);
+        for (c, channel) in channels.iter().enumerate().filter(|&(c, _)| c != shown) {
+            mesh.add_scalar(&channel.name, &field[c * voxels..(c + 1) * voxels], &self.dims);
+        }
+        mesh.to_world(&self.world.voxel_grid.transform);
+        mesh
+    }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let frame = self.gfx_ctx.acquire_frame()?; // swapchain texture when windowed, offscreen texture when headless
+
+        // UPDATE TIMESTEP //
+        // simulated time per frame comes from SimulationParams (fixed dt, or wall clock when adaptive)
+        // and is split into substeps that respect the explicit Euler CFL limit
+        let now = std::time::Instant::now();
+        let wall_dt = (now - self.time).as_secs_f32();
+        // let fps = 1.0 / wall_dt;
+        //println!("fps: {}\n", fps);
+        self.time = now;
+
+        // camera moves before projecting the grid, so the bounding box matches this frame's view
+        self.world.camera.update(wall_dt, &self.move_input);
+        self.world.frame = self.world.frame.wrapping_add(1);
+        self.world.generate_bb_projection(&self.resources.size);
+        self.bridge.update_raymarch_dispatch(self.world.bbox);
+
+        self.advance(wall_dt);
+        self.stream_zarr();
+
+        // UPDATE AND WRITE NEW UNIFORMS BUFFER TO QUEUE (raymarch reads the current field)
+        self.resources.uniforms_refresh(&self.gfx_ctx, 0.0, self.world.bbox, &self.dims, &self.world);
+
+        let mut encoder = self.gfx_ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Command Encoder")
+        });
+        self.encode_views(&mut encoder);
+        self.resources.present_refresh(&self.gfx_ctx, self.world.bbox, &self.world);
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor { // mutable borrow of encoder here
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment { // framebuffer
                     depth_slice: None,
-                    view: &surface_texture_view,
+                    view: frame.view(),
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.75,
-                            g: 0.75,
-                            b: 0.75,
+                            r: self.world.render.background[0] as f64,
+                            g: self.world.render.background[1] as f64,
+                            b: self.world.render.background[2] as f64,
                             a: 1.0,
                         }),
                         store: wgpu::StoreOp::Store,
@@ -177,29 +562,457 @@ impl State {
         
         // submit will accept anything that implements IntoIter
         self.gfx_ctx.queue.submit(std::iter::once(encoder.finish())); // allowing encoder call here
-        surface_texture.present();
+        frame.present();
     
         Ok(())
 
     }
 
-    pub fn handle_key(&self, event_loop: &winit::event_loop::ActiveEventLoop, code: winit::keyboard::KeyCode, is_pressed: bool) {
+
+    /// Initialises the field on the first call, then steps the simulation through one frame
+    /// wall_dt: seconds the frame took, only used under TimeStep::Adaptive
+    fn advance(&mut self, wall_dt: f32) {
+        let mut frame_dt = self.world.sim.frame_dt(wall_dt);
+        if self.world.sim.timestep == TimeStep::Adaptive {
+            // after a stall, drop simulated time rather than queueing hundreds of substeps
+            frame_dt = frame_dt.min(MAX_SUBSTEPS as f32 * CFL_SAFETY * self.world.sim.cfl_limit());
+        }
+        let (substeps, dt) = if self.init_complete { self.world.sim.substeps(frame_dt) } else { (0, 0.0) };
+
+        let initial = if self.init_complete { None } else { self.initial_species() };
+        if !self.init_complete {
+            // STATIC CHANNELS: uploaded once into both grids, the simulation only writes the species blocks
+            for (c, channel) in self.world.voxel_grid.channels.iter().enumerate().filter(|(_, c)| !c.simulated && !c.values.is_empty()) {
+                self.resources.write_channel(&self.gfx_ctx, c, &channel.values);
+            }
+        }
+
+        if let ComputeBackend::Cpu(cpu) = &mut self.backend {
+            // CPU backend keeps its own ping-pong, and uploads into the buffer being read (read_ping stays put)
+            cpu.set_params(self.world.sim);
+            if !self.init_complete {
+                match &initial {
+                    Some(field) => cpu.load(field),
+                    None => cpu.init_random(self.bridge.rand_seed),
+                }
+                self.init_complete = true;
+            }
+            for _ in 0..substeps {
+                cpu.step(dt);
+            }
+            self.resources.write_voxels(&self.gfx_ctx, cpu.current());
+        }
+        else if let Some(field) = initial {
+            // REACTION DIFFUSION INIT: every species' starting field uploaded into grid_a (ping)
+            self.resources.write_voxels(&self.gfx_ctx, &field);
+            self.init_complete = true;
+        }
+        else if !self.init_complete {
+            // RAND SEED INIT into grid_a (ping)
+            self.resources.uniforms_refresh(&self.gfx_ctx, 0.0, self.world.bbox, &self.dims, &self.world);
+
+            let mut encoder = self.gfx_ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Init Encoder")
+            });
+            {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("Init"),
+                timestamp_writes: None
+                });
+
+            compute_pass.set_pipeline(&self.compute.init_p);
+            compute_pass.set_bind_group(0, &self.compute.bg, &[]); 
+            let [x, y, z] = self.bridge.init_dispatch;
+            compute_pass.dispatch_workgroups(x, y, z);  // group size is 8 * 4 * 8 <= 256 (256, 256, 64 respective limits)
+            }
+            self.gfx_ctx.queue.submit(std::iter::once(encoder.finish()));
+            self.init_complete = true;
+        }
+        else {
+            self.step_gpu(substeps, dt);
+        }
+    }
+
+    /// substeps diffusion (or reaction–diffusion) passes of dt on the GPU
+    /// One submit per substep: each needs its own ping pong flag in the uniforms,
+    /// and queued uniform writes only land between submits
+    fn step_gpu(&mut self, substeps: u32, dt: f32) {
+        // reaction_diffusion.wgsl steps every species, laplacian.wgsl only species 0 so just a lone diffusing one
+        let reaction = &self.world.sim.reaction;
+        let pipeline = if reaction.is_active() || reaction.species > 1 { &self.compute.reaction_diffusion_p } else { &self.compute.laplacian_p };
+        for _ in 0..substeps {
+            self.resources.uniforms_refresh(&self.gfx_ctx, dt, self.world.bbox, &self.dims, &self.world);
+
+            let mut encoder = self.gfx_ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Laplacian Encoder")
+            });
+            {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("Laplacian"),
+                timestamp_writes: None
+                });
+
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &self.compute.bg, &[]); 
+            let [x, y, z] = self.bridge.laplacian_dispatch;
+            compute_pass.dispatch_workgroups(x, y, z);  // group size is 8 * 4 * 8 <= 256 (256, 256, 64 respective limits)
+            }
+            self.gfx_ctx.queue.submit(std::iter::once(encoder.finish()));
+
+            // Ping pong flag: the grid just written now holds the current field
+            self.resources.read_ping = !self.resources.read_ping;
+        }
+    }
+
+    /// Steps the GPU solver and the CPU reference (CpuDiffusion) steps times by dt from the same current field,
+    /// returns the largest per-voxel difference between their species blocks (see CpuDiffusion::max_abs_diff())
+    pub fn cross_check(&mut self, steps: u32, dt: f32) -> Result<f32, Box<dyn Error>> {
+        if let ComputeBackend::Cpu(_) = self.backend {
+            return Err("The CPU backend is stepping the field, there's no GPU output to check".into());
+        }
+        if !self.init_complete {
+            return Err("The field isn't initialised yet, render a frame first".into());
+        }
+        if !self.world.sim.is_stable(dt) {
+            return Err(format!("dt {} is above the CFL limit {}", dt, self.world.sim.cfl_limit()).into());
+        }
+        let species = self.world.sim.reaction.species * self.world.voxel_grid.voxels();
+
+        let mut cpu = CpuDiffusion::new(self.dims);
+        cpu.set_params(self.world.sim);
+        cpu.load(&self.read_voxels()[..species]);
+
+        self.step_gpu(steps, dt);
+        for _ in 0..steps { cpu.step(dt); }
+        Ok(cpu.max_abs_diff(&self.read_voxels()[..species]))
+    }
+
+    /// Raymarch and slice passes into the storage texture, for whatever size Resources currently has
+    fn encode_views(&self, encoder: &mut wgpu::CommandEncoder) {
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("Raymarch"),
+                timestamp_writes: None
+                });
+
+            compute_pass.set_bind_group(0, &self.compute.bg, &[]); 
+            if self.world.slices.shows_volume() {
+                compute_pass.set_pipeline(&self.compute.raymarch_p);
+                let [x, y, z] = self.bridge.raymarch_dispatch; 
+                compute_pass.dispatch_workgroups(x, y, z);
+            }
+            // slices draw over the raymarch, into the same storage texture
+            if self.world.slices.is_visible() {
+                compute_pass.set_pipeline(&self.compute.slice_p);
+                let [x, y, z] = self.bridge.slice_dispatch;
+                compute_pass.dispatch_workgroups(x, y, z);
+            }
+        }
+    }
+
+    /// Draws the current view at size into an offscreen storage texture and saves it to path
+    /// (.png for 8-bit, .tif/.tiff for 16-bit), independent of the window's size. The simulation doesn't advance
+    pub fn capture_frame(&mut self, size: PhysicalSize<u32>, path: &std::path::Path) -> Result<(), Box<dyn Error>> {
+        let format = ImageFormat::from_path(path).ok_or(format!("No image format for {}, expected .png, .tif or .tiff", path.display()))?;
+        let window = self.resources.size;
+
+        self.retarget(size);
+        let pixels = self.draw_offscreen();
+        self.retarget(window);
+
+        capture::save_image(path, format, size.width, size.height, &pixels, self.world.render.background)
+    }
+
+    /// Batch mode: renders sequence.frames numbered images, advancing the simulation and moving
+    /// the camera along sequence.camera between them. Returns the paths written
+    pub fn render_sequence(&mut self, sequence: &FrameSequence) -> Result<Vec<std::path::PathBuf>, Box<dyn Error>> {
+        if sequence.width == 0 || sequence.height == 0 {
+            return Err(format!("Frame sequence size {}x{} is empty", sequence.width, sequence.height).into());
+        }
+        std::fs::create_dir_all(&sequence.directory)?;
+        if sequence.camera != CameraPath::Still && matches!(self.world.camera, Camera::FPV(_)) {
+            self.world.toggle_camera(); // paths orbit the grid
+        }
+        let start = match &self.world.camera {
+            Camera::Orbital(orbital) => CameraKey::of(orbital),
+            Camera::FPV(_) => CameraKey { azimuth: 0.0, elevation: 0.0, distance: 1.0 }, // unused, Still only
+        };
+
+        let window = self.resources.size;
+        self.retarget(PhysicalSize::new(sequence.width, sequence.height));
+
+        let written = (0..sequence.frames).map(|i| {
+            sequence.camera.apply(&mut self.world.camera, start, i, sequence.frames);
+            if sequence.simulate || !self.init_complete { self.advance(sequence.frame_dt); }
+            self.world.frame = self.world.frame.wrapping_add(1);
+
+            let pixels = self.draw_offscreen();
+            let path = sequence.path(i);
+            capture::save_image(&path, sequence.format, sequence.width, sequence.height, &pixels, self.world.render.background)?;
+            Ok(path)
+        }).collect::<Result<Vec<_>, Box<dyn Error>>>();
+
+        self.retarget(window); // even on a failed write, or every later windowed frame is drawn at the sequence size
+        written
+    }
+
+    /// Points the camera, dispatches and storage texture at a new size without touching the surface
+    fn retarget(&mut self, size: PhysicalSize<u32>) {
+        self.world.camera.on_resize(&size);
+        self.bridge.update_slice_dispatch(size.width, size.height);
+
+        self.resources.on_resize(&self.dims, size.width, size.height, &self.gfx_ctx, &self.world, &self.bridge);
+
+        self.compute.on_resize(&self.gfx_ctx, &self.resources);
+
+        self.render.on_resize(&self.gfx_ctx, &self.resources);
+    }
+
+    /// Raymarch (and slices) of the current field into the storage texture, read straight back
+    fn draw_offscreen(&mut self) -> Vec<[f32; 4]> {
+        self.world.generate_bb_projection(&self.resources.size);
+        self.bridge.update_raymarch_dispatch(self.world.bbox);
+        self.resources.uniforms_refresh(&self.gfx_ctx, 0.0, self.world.bbox, &self.dims, &self.world);
+
+        let mut encoder = self.gfx_ctx.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Capture Encoder")
+        });
+        self.encode_views(&mut encoder);
+        self.gfx_ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        self.resources.read_frame(&self.gfx_ctx)
+    }
+
+    pub fn handle_key(&mut self, event_loop: &winit::event_loop::ActiveEventLoop, code: winit::keyboard::KeyCode, is_pressed: bool) {
         match (code, is_pressed) {
             (winit::keyboard::KeyCode::Escape, true) => {
                 event_loop.exit()
             },
-            (winit::keyboard::KeyCode::KeyW, true) => {
-                
+            // MOVEMENT: held state is integrated every frame in render(), FPV camera only
+            (winit::keyboard::KeyCode::KeyW, pressed) => {
+                self.move_input.forward = pressed;
             },
-            (winit::keyboard::KeyCode::KeyS, true) => {
-
+            (winit::keyboard::KeyCode::KeyS, pressed) => {
+                self.move_input.back = pressed;
             },
-            (winit::keyboard::KeyCode::KeyA, true) => {
-
+            (winit::keyboard::KeyCode::KeyA, pressed) => {
+                self.move_input.left = pressed;
+            },
+            (winit::keyboard::KeyCode::KeyD, pressed) => {
+                self.move_input.right = pressed;
+            },
+            // BOUNDARY CONDITIONS: 1, 2, 3 cycle x, y, z (Neumann -> Periodic -> Dirichlet -> Absorbing)
+            (winit::keyboard::KeyCode::Digit1, true) => {
+                println!("x boundary: {:?}\n", self.world.sim.boundaries.cycle_axis(0));
+            },
+            (winit::keyboard::KeyCode::Digit2, true) => {
+                println!("y boundary: {:?}\n", self.world.sim.boundaries.cycle_axis(1));
+            },
+            (winit::keyboard::KeyCode::Digit3, true) => {
+                println!("z boundary: {:?}\n", self.world.sim.boundaries.cycle_axis(2));
+            },
+            // CAMERA: C switches between orbital and first person
+            (winit::keyboard::KeyCode::KeyC, true) => {
+                self.world.toggle_camera();
+                match self.world.camera {
+                    Camera::FPV(_) => println!("camera: first person\n"),
+                    Camera::Orbital(_) => println!("camera: orbital\n"),
+                }
+            },
+            // TRANSFER FUNCTION: M cycles colormaps, [ and ] scale opacity, F5 saves and F9 loads JSON
+            (winit::keyboard::KeyCode::KeyM, true) => {
+                let mut transfer = self.world.transfer.clone();
+                transfer.colormap = transfer.colormap.next();
+                println!("colormap: {:?}\n", transfer.colormap);
+                self.set_transfer_function(transfer);
+            },
+            (winit::keyboard::KeyCode::BracketLeft, true) => {
+                let mut transfer = self.world.transfer.clone();
+                transfer.scale_opacity(0.8);
+                self.set_transfer_function(transfer);
+            },
+            (winit::keyboard::KeyCode::BracketRight, true) => {
+                let mut transfer = self.world.transfer.clone();
+                transfer.scale_opacity(1.25);
+                self.set_transfer_function(transfer);
+            },
+            (winit::keyboard::KeyCode::F5, true) => {
+                match self.world.transfer.save_json(std::path::Path::new(TRANSFER_FUNCTION_PATH)) {
+                    Ok(_) => println!("Saved transfer function to {}\n", TRANSFER_FUNCTION_PATH),
+                    Err(e) => println!("Couldn't save transfer function: {}\n", e),
+                }
+            },
+            (winit::keyboard::KeyCode::F9, true) => {
+                match TransferFunction::load_json(std::path::Path::new(TRANSFER_FUNCTION_PATH)) {
+                    Ok(transfer) => self.set_transfer_function(transfer),
+                    Err(e) => println!("Couldn't load transfer function: {}\n", e),
+                }
+            },
+            // VOLUME: F7 saves the shown channel and F8 loads a volume, as gzip NRRD
+            (winit::keyboard::KeyCode::F7, true) => {
+                match self.save_volume(std::path::Path::new(VOLUME_PATH), Encoding::Gzip) {
+                    Ok(_) => println!("Saved field to {}\n", VOLUME_PATH),
+                    Err(e) => println!("Couldn't save field: {}\n", e),
+                }
+            },
+            (winit::keyboard::KeyCode::F8, true) => {
+                match Volume::load_nrrd(std::path::Path::new(VOLUME_PATH)).and_then(|v| self.load_volume(v)) {
+                    Ok(_) => println!("Loaded {}x{}x{} volume from {}\n", self.dims[0], self.dims[1], self.dims[2], VOLUME_PATH),
+                    Err(e) => println!("Couldn't load volume: {}\n", e),
+                }
+            },
+            // STACK: F10 opens a TIFF stack and F11 an OME-Zarr image, PageUp/PageDown step through their time points
+            (winit::keyboard::KeyCode::F10, true) => {
+                match self.open_stack(std::path::Path::new(STACK_PATH)) {
+                    Ok(_) => {
+                        let layout = &self.stack.as_ref().unwrap().0.layout;
+                        println!("Opened {:?} stack {} with {} channels and {} time points at {:?} spacing\n", layout.metadata, STACK_PATH, layout.size[3], layout.size[4], layout.spacing);
+                    },
+                    Err(e) => println!("Couldn't open stack: {}\n", e),
+                }
+            },
+            (winit::keyboard::KeyCode::F11, true) => {
+                match self.open_zarr(std::path::Path::new(ZARR_PATH)) {
+                    Ok(_) => {
+                        let source = &self.zarr.as_ref().unwrap().source;
+                        println!("Opened OME-Zarr {} with {} levels, {} channels and {} time points\n", ZARR_PATH, source.levels.len(), source.channels(), source.time_points());
+                    },
+                    Err(e) => println!("Couldn't open OME-Zarr image: {}\n", e),
+                }
+            },
+            (winit::keyboard::KeyCode::PageUp, true) | (winit::keyboard::KeyCode::PageDown, true) => {
+                if let Some(stream) = &self.zarr {
+                    let times = stream.source.time_points();
+                    let next = if code == winit::keyboard::KeyCode::PageUp { (stream.t + 1) % times } else { (stream.t + times - 1) % times };
+                    match self.load_zarr_time_point(next) {
+                        Ok(_) => println!("Time point {}\n", next),
+                        Err(e) => println!("Couldn't load time point {}: {}\n", next, e),
+                    }
+                }
+                else if let Some((stack, t)) = &self.stack {
+                    let next = if code == winit::keyboard::KeyCode::PageUp { (t + 1) % stack.time_points() } else { (t + stack.time_points() - 1) % stack.time_points() };
+                    match self.load_time_point(next) {
+                        Ok(_) => println!("Time point {}\n", next),
+                        Err(e) => println!("Couldn't load time point {}: {}\n", next, e),
+                    }
+                }
+            },
+            // MESH: F6 exports the isosurface at the isosurface mode's iso value (mid range of the transfer function otherwise)
+            (winit::keyboard::KeyCode::F6, true) => {
+                let iso = match self.world.render.mode {
+                    RenderMode::Isosurface { iso } => iso,
+                    _ => (self.world.transfer.range[0] + self.world.transfer.range[1]) / 2.0,
+                };
+                let mesh = self.extract_mesh(iso);
+                match mesh.save(std::path::Path::new(MESH_PATH)) {
+                    Ok(_) => println!("Saved {} triangle isosurface at {} to {}\n", mesh.triangle_count(), iso, MESH_PATH),
+                    Err(e) => println!("Couldn't save mesh: {}\n", e),
+                }
+            },
+            // CAPTURE: F12 saves the view at twice the window's resolution
+            (winit::keyboard::KeyCode::F12, true) => {
+                let size = PhysicalSize::new(self.resources.size.width * 2, self.resources.size.height * 2);
+                match self.capture_frame(size, std::path::Path::new(CAPTURE_PATH)) {
+                    Ok(_) => println!("Saved {}x{} capture to {}\n", size.width, size.height, CAPTURE_PATH),
+                    Err(e) => println!("Couldn't save capture: {}\n", e),
+                }
+            },
+            // RAYMARCH: P cycles render modes, J toggles jittered ray starts, I cycles interpolation, - and = halve and double the step size
+            (winit::keyboard::KeyCode::KeyP, true) => {
+                let threshold = (self.world.transfer.range[0] + self.world.transfer.range[1]) / 2.0; // first hit at mid range
+                self.world.render.mode = self.world.render.mode.next(threshold);
+                println!("render mode: {:?}\n", self.world.render.mode);
+            },
+            (winit::keyboard::KeyCode::KeyJ, true) => {
+                self.world.render.jitter = !self.world.render.jitter;
+                println!("jitter: {}\n", self.world.render.jitter);
+            },
+            (winit::keyboard::KeyCode::KeyI, true) => {
+                self.world.render.interpolation = self.world.render.interpolation.next();
+                println!("interpolation: {:?}\n", self.world.render.interpolation);
+            },
+            (winit::keyboard::KeyCode::Minus, true) => {
+                self.world.render.step_size = (self.world.render.step_size * 0.5).max(MIN_STEP);
+                println!("step size: {} voxels\n", self.world.render.step_size);
+            },
+            (winit::keyboard::KeyCode::Equal, true) => {
+                self.world.render.step_size = (self.world.render.step_size * 2.0).min(MAX_STEP);
+                println!("step size: {} voxels\n", self.world.render.step_size);
+            },
+            // SLICES: L cycles layouts (hidden, panes, axial, coronal, sagittal), Tab cycles the focused pane, comma and period move the focused slice
+            (winit::keyboard::KeyCode::KeyL, true) => {
+                self.world.slices.next_layout();
+                println!("slices: {:?}\n", self.world.slices.layout);
+            },
+            (winit::keyboard::KeyCode::Tab, true) => {
+                self.world.slices.focus = self.world.slices.focus.next();
+                println!("slice focus: {:?}\n", self.world.slices.focus);
+            },
+            (winit::keyboard::KeyCode::Comma, true) => {
+                let index = self.world.slices.move_focus(-1, &self.dims);
+                println!("{:?} slice: {}\n", self.world.slices.focus, index);
+            },
+            (winit::keyboard::KeyCode::Period, true) => {
+                let index = self.world.slices.move_focus(1, &self.dims);
+                println!("{:?} slice: {}\n", self.world.slices.focus, index);
+            },
+            // CLIPPING: X cuts away the near half along the view, Z removes the last cut, K cycles the crop box face, ; and ' pull it in and push it out, Backspace resets
+            (winit::keyboard::KeyCode::KeyX, true) => {
+                if self.world.add_view_clip_plane() { println!("clip planes: {}\n", self.world.clipping.planes.len()); }
+                else { println!("Already at {} clip planes\n", MAX_CLIP_PLANES); }
+            },
+            (winit::keyboard::KeyCode::KeyZ, true) => {
+                self.world.clipping.remove_plane();
+                println!("clip planes: {}\n", self.world.clipping.planes.len());
+            },
+            (winit::keyboard::KeyCode::KeyK, true) => {
+                self.world.clipping.focus = self.world.clipping.focus.next();
+                println!("crop face: {:?}\n", self.world.clipping.focus);
+            },
+            (winit::keyboard::KeyCode::Semicolon, true) => {
+                let at = self.world.clipping.nudge_crop(-CROP_STEP, &self.dims);
+                println!("{:?} crop face: {}\n", self.world.clipping.focus, at);
+            },
+            (winit::keyboard::KeyCode::Quote, true) => {
+                let at = self.world.clipping.nudge_crop(CROP_STEP, &self.dims);
+                println!("{:?} crop face: {}\n", self.world.clipping.focus, at);
+            },
+            (winit::keyboard::KeyCode::Backspace, true) => {
+                self.world.clipping = Clipping::new(&self.dims);
+                println!("clipping reset\n");
+            },
+            // REACTION DIFFUSION: R cycles presets (None -> Gray-Scott -> FitzHugh-Nagumo -> Schnakenberg), V cycles the channel shown
+            (winit::keyboard::KeyCode::KeyR, true) => {
+                self.set_reaction(self.world.sim.reaction.next_preset());
+                println!("reaction: {:?}\n", self.world.sim.reaction.model);
+            },
+            (winit::keyboard::KeyCode::KeyV, true) => {
+                let channels = &self.world.voxel_grid.channels;
+                let next = (channels.shown() + 1) % channels.len();
+                self.show_channel(next);
+                println!("showing channel {}\n", self.world.voxel_grid.channels.get(next).unwrap().name);
+            },
+            // LABELS: O cycles the label volume's opacity (opaque, semi-transparent, hidden)
+            (winit::keyboard::KeyCode::KeyO, true) => {
+                let Some(volume) = self.world.labels.as_ref() else { println!("No label volume\n"); return; };
+                let current = LABEL_OPACITIES.iter().position(|&o| o == volume.table.opacity).unwrap_or(LABEL_OPACITIES.len() - 1);
+                let opacity = LABEL_OPACITIES[(current + 1) % LABEL_OPACITIES.len()];
+                let _ = self.set_label_opacity(opacity);
+                println!("label opacity: {}\n", opacity);
+            },
+            // CHANNEL BLENDING: B cycles the blend (shown channel only, additive, maximum), N toggles the shown channel's visibility
+            (winit::keyboard::KeyCode::KeyB, true) => {
+                self.world.render.blend = self.world.render.blend.next();
+                println!("channel blend: {:?}\n", self.world.render.blend);
+            },
+            (winit::keyboard::KeyCode::KeyN, true) => {
+                let shown = self.world.voxel_grid.channels.shown();
+                let mut display = self.world.voxel_grid.channels.get(shown).unwrap().display.clone();
+                display.visible = !display.visible;
+                let _ = self.set_channel_display(shown, display);
+                let channel = self.world.voxel_grid.channels.get(shown).unwrap();
+                println!("channel {} visible: {}\n", channel.name, channel.display.visible);
             },
-            (winit::keyboard::KeyCode::KeyD, true) => {
-
-            }
             _ => {}
         }
     
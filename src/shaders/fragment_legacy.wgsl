@@ -1,16 +1,47 @@
+struct Present {
+    letterbox: vec4<f32>, // storage texture on the surface in uv: x, y, width, height
+    volume: vec4<f32>, // raymarched bounding box in texture uv (rows counting up): min, max
+    slices: vec4<f32>, // slice panes in texture uv, as volume
+    background: vec4<f32>, // sRGB
+    flags: vec4<u32>, // [0] surface format is sRGB
+};
+
 @group(0) @binding(0)
 var my_sampler: sampler;
 
 @group(0) @binding(1)
 var input_tex: texture_2d<f32>;
 
+@group(0) @binding(2)
+var<uniform> present: Present;
+
+fn inside(p: vec2<f32>, rect: vec4<f32>) -> bool {
+    return all(p >= rect.xy) && all(p < rect.zw);
+}
+
+// sRGB transfer curve -> linear, per channel
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let low = c / 12.92;
+    let high = pow((c + vec3<f32>(0.055)) / 1.055, vec3<f32>(2.4));
+    return select(high, low, c <= vec3<f32>(0.04045));
+}
+
 @fragment
 fn main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
-    let flipped = vec2f(uv.x, 1.0 - uv.y);
-    let p = vec2<i32>(10, 10);
-    let c = textureLoad(input_tex, p, 0);
-    return c;
-    //return textureSample(input_tex, my_sampler, flipped);
-   // return textureLoad(input_tex, vec2(10, 10), 0);
-   //return vec4f(1.0, 0.0, 1.0, 1.0); // solid magenta
+    // LETTERBOX //
+    let local = (uv - present.letterbox.xy) / present.letterbox.zw;
+    let tex_uv = vec2<f32>(local.x, 1.0 - local.y); // quad uv runs top down, texture rows bottom up
+
+    // sample before branching, textureSample needs uniform control flow
+    let texel = textureSample(input_tex, my_sampler, tex_uv);
+
+    var colour = present.background.rgb; // MUT
+    let drawn = inside(tex_uv, present.volume) || inside(tex_uv, present.slices);
+    if all(local >= vec2<f32>(0.0)) && all(local <= vec2<f32>(1.0)) && drawn {
+        colour = texel.rgb + (colour * (1.0 - texel.a)); // premultiplied over, in sRGB like save_image()
+    }
+
+    // sRGB surfaces encode on write, so hand them linear values
+    if present.flags[0] == 1u { colour = srgb_to_linear(clamp(colour, vec3<f32>(0.0), vec3<f32>(1.0))); }
+    return vec4<f32>(colour, 1.0);
 }
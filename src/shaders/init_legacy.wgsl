@@ -7,9 +7,30 @@ struct Uniforms{
     centre: vec4<f32>, // some k*forward
     up: vec4<f32>,
     right: vec4<f32>, // [3] horizontal scaling factor (not needed for up, 1:1)
-    timestep: vec4<f32>, // [0] time in seconds
+    timestep: vec4<f32>, // [0] substep dt in simulated seconds
     seed: vec4<f32>,
-    flags: vec4<u32> // [0] reada flag 1 true, 0 false
+    flags: vec4<u32>, // [0] bit 0 reada flag 1 true, 0 false, bits 8-15 render mode, bits 16-23 interpolation, [1..3] x, y, z boundary conditions (low face bits 0-7, high face bits 8-15)
+    boundary_low: vec4<f32>, // Dirichlet values on the index 0 face of x, y, z
+    boundary_high: vec4<f32>, // Dirichlet values on the index dims - 1 face of x, y, z
+    diffusion: vec4<f32>, // [0] D, [1..3] 1/dx^2, 1/dy^2, 1/dz^2
+    reaction: vec4<f32>, // reaction model parameters (see ReactionModel::params())
+    species_diffusion: vec4<f32>, // D per species
+    reaction_flags: vec4<u32>, // [0] reaction model, [1] species count, [2] channel shown by the raymarch, [3] channel count
+    transfer: vec4<f32>, // [0..1] voxel values at either end of the transfer function
+    raymarch: vec4<f32>, // [0] step size in voxels, [1] early exit alpha, [2] jitter 1 on 0 off, [3] frame
+    render_params: vec4<f32>, // [0] threshold of the first hit mode or iso value of the isosurface mode
+    lighting: vec4<f32>, // isosurface Blinn–Phong: [0] ambient, [1] diffuse, [2] specular, [3] shininess
+    slices: vec4<u32>, // [0..2] sagittal, coronal, axial slice index, [3] layout bits 0-7, single axis bits 8-15, focus bits 16-23
+    clip_planes: array<vec4<f32>, 6>, // keeps dot(xyz, p) + w >= 0, p in voxel coords, unused planes keep everything
+    crop_min: vec4<f32>, // crop box in voxel coords
+    crop_max: vec4<f32>,
+    composite_flags: vec4<u32>, // [0] channel blend, [1] composited channel count
+    composite_channels: vec4<u32>, // channel index of each composited slot
+    composite_ranges: array<vec4<f32>, 4>, // [0..1] values at either end of each slot's lookup table
+    label_flags: vec4<u32>, // [0] label volume loaded, [1] label slots (see LabelTable::slot())
+    world_from_voxel: array<vec4<f32>, 3>, // rows of the voxel -> world affine, translation in w
+    voxel_from_world: array<vec4<f32>, 3>, // its inverse
+    spacing: vec4<f32> // voxel edge length along x, y, z in world units
 }
 
 // CONSTS
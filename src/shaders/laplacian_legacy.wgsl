@@ -7,9 +7,30 @@ struct Uniforms{
     centre: vec4<f32>, // some k*forward
     up: vec4<f32>,
     right: vec4<f32>, // [3] horizontal scaling factor (not needed for up, 1:1)
-    timestep: vec4<f32>, // [0] time in seconds
+    timestep: vec4<f32>, // [0] substep dt in simulated seconds
     seed: vec4<f32>,
-    flags: vec4<u32> // [0] reada flag 1 true, 0 false
+    flags: vec4<u32>, // [0] bit 0 reada flag 1 true, 0 false, bits 8-15 render mode, bits 16-23 interpolation, [1..3] x, y, z boundary conditions (low face bits 0-7, high face bits 8-15)
+    boundary_low: vec4<f32>, // Dirichlet values on the index 0 face of x, y, z
+    boundary_high: vec4<f32>, // Dirichlet values on the index dims - 1 face of x, y, z
+    diffusion: vec4<f32>, // [0] D, [1..3] 1/dx^2, 1/dy^2, 1/dz^2
+    reaction: vec4<f32>, // reaction model parameters (see ReactionModel::params())
+    species_diffusion: vec4<f32>, // D per species
+    reaction_flags: vec4<u32>, // [0] reaction model, [1] species count, [2] channel shown by the raymarch, [3] channel count
+    transfer: vec4<f32>, // [0..1] voxel values at either end of the transfer function
+    raymarch: vec4<f32>, // [0] step size in voxels, [1] early exit alpha, [2] jitter 1 on 0 off, [3] frame
+    render_params: vec4<f32>, // [0] threshold of the first hit mode or iso value of the isosurface mode
+    lighting: vec4<f32>, // isosurface Blinn–Phong: [0] ambient, [1] diffuse, [2] specular, [3] shininess
+    slices: vec4<u32>, // [0..2] sagittal, coronal, axial slice index, [3] layout bits 0-7, single axis bits 8-15, focus bits 16-23
+    clip_planes: array<vec4<f32>, 6>, // keeps dot(xyz, p) + w >= 0, p in voxel coords, unused planes keep everything
+    crop_min: vec4<f32>, // crop box in voxel coords
+    crop_max: vec4<f32>,
+    composite_flags: vec4<u32>, // [0] channel blend, [1] composited channel count
+    composite_channels: vec4<u32>, // channel index of each composited slot
+    composite_ranges: array<vec4<f32>, 4>, // [0..1] values at either end of each slot's lookup table
+    label_flags: vec4<u32>, // [0] label volume loaded, [1] label slots (see LabelTable::slot())
+    world_from_voxel: array<vec4<f32>, 3>, // rows of the voxel -> world affine, translation in w
+    voxel_from_world: array<vec4<f32>, 3>, // its inverse
+    spacing: vec4<f32> // voxel edge length along x, y, z in world units
 }
 // BINDINGS
 
@@ -23,7 +44,7 @@ var<storage, read_write> grid_a: array<f32>;
 var<storage, read_write> grid_b: array<f32>;
 
 @group(0) @binding(3)
-var output_tex: texture_storage_2d<rgba8unorm, write>; 
+var output_tex: texture_storage_2d<rgba16float, write>; 
 
 // CONSTS AND SHARED MEMORY
 const group_x: u32 = 8;
@@ -37,9 +58,35 @@ const shared_z: u32 = group_z + 2;
 var<workgroup> shared_cells: array<f32, shared_x * shared_y * shared_z>;
 
 
+// BOUNDARY CONDITIONS, must match BoundaryCondition::code() Rust-side
+const NEUMANN: u32 = 0;
+const DIRICHLET: u32 = 1;
+const PERIODIC: u32 = 2;
+const ABSORBING: u32 = 3;
+
+// reads whichever grid holds the current field
+fn read_current(idx: u32) -> f32 {
+    if (uniforms.flags[0] & 1) == 1 { return grid_a[idx]; }
+    return grid_b[idx];
+}
+
+// halo value one voxel beyond a face of the grid
+// c_i is the boundary voxel, wrapped_idx the voxel on the opposite face (only read for periodic)
+fn ghost(axis: u32, high: bool, c_i: f32, wrapped_idx: u32) -> f32 {
+    let packed = uniforms.flags[axis + 1];
+    let code = select(packed & 0xff, (packed >> 8) & 0xff, high);
+    let value = select(uniforms.boundary_low[axis], uniforms.boundary_high[axis], high);
+    switch code {
+        case DIRICHLET: { return (2.0 * value) - c_i; } // value held on the face itself
+        case PERIODIC: { return read_current(wrapped_idx); }
+        case ABSORBING: { return 0.0; }
+        default: { return c_i; } // NEUMANN, zero flux
+    }
+}
+
 // COLLABORATIVE LOADING AND LAPLACIAN STENCIL
 // WORKGROUP DIMS + 2 = SHARED MEMORY CUBOID WITH HALO
-// TODO: ADD PERIODIC SWAP FOR NEUMANN ON KEYPRESS RUST-SIDE, LOGIC DIVERGENCE HERE
+// Halo cells on grid faces come from ghost() (see BoundaryCondition Rust-side)
 @compute @workgroup_size(group_x, group_y, group_z)
 fn laplacian(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(local_invocation_id) loc: vec3<u32>, @builtin(workgroup_id) gro: vec3<u32>){
     // ALL THREADS IN DOMAIN TO FETCH INNER CELLS
@@ -47,202 +94,77 @@ fn laplacian(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(local_invoc
     let global_z_stride = gid.z * uniforms.dims[3];
     let idx: u32 = gid.x + global_y_stride + global_z_stride; // index for inner cells
     if gid.x < uniforms.dims[0] && gid.y < uniforms.dims[1] && gid.z < uniforms.dims[2] {
-        if uniforms.flags[0] == 1{
-            let middle_voxel = grid_a[idx];
-            
-            // insert inner cell float
-            shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = middle_voxel; // +1 for halo offset, using xyz + 2 for stride calcs
-            
-            // FACE THREADS TO FETCH HALOS
-            // X HALOS
-            if gid.x == uniforms.dims[0] - 1 { // catches both short tiles and clean tiles
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch x in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 2 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x + 2 in shared for Neumann bound
-            
-            }
-            else if loc.x == group_x - 1  && gid.x + 1 < uniforms.dims[0] { // catches clean tiles before final x bound tile which still needs halo
-                let idx: u32 = gid.x + 1 + global_y_stride + global_z_stride; // fetch x + 1 in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 2 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x + 2 in shared
-            
-            }
-            else if loc.x == 0 && gid.x > 0  { // && gid.x < uniforms.dims[0] already assured
-                let idx: u32 = gid.x - 1 + global_y_stride + global_z_stride; // fetch x - 1  in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x in shared
-                
-            }
-            else if gid.x == 0  {
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch x  in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y in shared for Neumann bound
-                }
-
-                // Y HALOS
-            if gid.y == uniforms.dims[1] - 1 { // catches both short tiles and clean tiles
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch y in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 2) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y + 2 in shared for Neumann bound
-            
-            }
-            else if loc.y == group_y - 1  && gid.y + 1 < uniforms.dims[1] { // catches clean tiles before final y bound tile which still needs halo
-                let global_y_stride = (gid.y + 1) * uniforms.dims[0]; // recompute y + 1 stride
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch y + 1 in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 2) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y + 2 in shared
-            
-            }
-            else if loc.y == 0 && gid.y > 0  { // && gid.y < uniforms.dims[1] already assured
-                let global_y_stride = (gid.y -1) * uniforms.dims[0]; // recompute y - 1 stride
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch y - 1  in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 1 + (loc.y * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y in shared
-                
-            }
-            else if gid.y == 0  {
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch y  in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 1 + (loc.y * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y in shared for Neumann bound
-                }
-                // Z HALOS
-            if gid.z == uniforms.dims[2] - 1 { // catches both short tiles and clean tiles
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch z in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 2) * shared_x * shared_y)] = halo_cell; // write z + 2 in shared for Neumann bound
-            
-            }
-            else if loc.z == group_z - 1  && gid.z + 1 < uniforms.dims[2] { // catches clean tiles before final y bound tile which still needs halo
-                let global_z_stride = (gid.z + 1) * uniforms.dims[3]; // recompute z + 1 stride
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch z + 1 in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 2) * shared_x * shared_y)] = halo_cell; // write z + 2 in shared
-            
-            }
-            else if loc.z == 0 && gid.z > 0 { // && gid.z < uniforms.dims[2] already assured
-                let global_z_stride = (gid.z - 1) * uniforms.dims[3]; // recompute z + 1 stride
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch z - 1  in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + (loc.z * shared_x * shared_y)] = halo_cell; // write z in shared
-                
-            }
-            else if gid.z == 0  {
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch z in global
-
-                let halo_cell = grid_a[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 1) *shared_x) + (loc.z * shared_x * shared_y)] = halo_cell; // write z in shared for Neumann bound
-                }
-        } // READ GRID A
-        else {
-            let middle_voxel = grid_b[idx];
-            
-            // insert inner cell float
-            shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = middle_voxel; // +1 for halo offset, using xyz + 2 for stride calcs
-            
-            // FACE THREADS TO FETCH HALOS
-            // X HALOS
-            if gid.x == uniforms.dims[0] - 1 { // catches both short tiles and clean tiles
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch x in global
-
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 2 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x + 2 in shared for Neumann bound
-            
-            }
-            else if loc.x == group_x - 1  && gid.x + 1 < uniforms.dims[0] { // catches clean tiles before final x bound tile which still needs halo
-                let idx: u32 = gid.x + 1 + global_y_stride + global_z_stride; // fetch x + 1 in global
-
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 2 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x + 2 in shared
-            
-            }
-            else if loc.x == 0 && gid.x > 0  { // && gid.x < uniforms.dims[0] already assured
-                let idx: u32 = gid.x - 1 + global_y_stride + global_z_stride; // fetch x - 1  in global
-
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x in shared
-                
-            }
-            else if gid.x == 0  {
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch x  in global
-
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y in shared for Neumann bound
-                }
-
-
-                // Y HALOS
-            if gid.y == uniforms.dims[1] - 1 { // catches both short tiles and clean tiles
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch y in global
-
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 2) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y + 2 in shared for Neumann bound
-            
-            }
-            else if loc.y == group_y - 1  && gid.y + 1 < uniforms.dims[1] { // catches clean tiles before final y bound tile which still needs halo
-                let global_y_stride = (gid.y + 1) * uniforms.dims[0]; // recompute y + 1 stride
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch y + 1 in global
-
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 2) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y + 2 in shared
-            
-            }
-            else if loc.y == 0 && gid.y > 0  { // && gid.y < uniforms.dims[1] already assured
-                let global_y_stride = (gid.y -1) * uniforms.dims[0]; // recompute y - 1 stride
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch y - 1  in global
+        let middle_voxel = read_current(idx);
+        
+        // insert inner cell float
+        shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = middle_voxel; // +1 for halo offset, using xyz + 2 for stride calcs
+        
+        // FACE THREADS TO FETCH HALOS
+        // X HALOS
+        if gid.x == uniforms.dims[0] - 1 { // catches both short tiles and clean tiles
+            let wrapped_idx: u32 = global_y_stride + global_z_stride; // x = 0 in global
+
+            let halo_cell = ghost(0, true, middle_voxel, wrapped_idx);
+            shared_cells[loc.x + 2 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x + 2 in shared for boundary
+        }
+        else if loc.x == group_x - 1 { // catches clean tiles before final x bound tile which still needs halo
+            let halo_cell = read_current(idx + 1); // fetch x + 1 in global
+            shared_cells[loc.x + 2 + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x + 2 in shared
+        }
+        if gid.x == 0 {
+            let wrapped_idx: u32 = uniforms.dims[0] - 1 + global_y_stride + global_z_stride; // x = dims - 1 in global
 
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 1 + (loc.y * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y in shared
-                
-            }
-            else if gid.y == 0  {
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch y  in global
+            let halo_cell = ghost(0, false, middle_voxel, wrapped_idx);
+            shared_cells[loc.x + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x in shared for boundary
+        }
+        else if loc.x == 0 { // gid.x > 0 already assured
+            let halo_cell = read_current(idx - 1); // fetch x - 1 in global
+            shared_cells[loc.x + ((loc.y + 1) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write x in shared
+        }
 
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 1 + (loc.y * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y in shared for Neumann bound
-                }
+        // Y HALOS
+        if gid.y == uniforms.dims[1] - 1 { // catches both short tiles and clean tiles
+            let wrapped_idx: u32 = gid.x + global_z_stride; // y = 0 in global
 
-                // Z HALOS
-            if gid.z == uniforms.dims[2] - 1 { // catches both short tiles and clean tiles
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch z in global
+            let halo_cell = ghost(1, true, middle_voxel, wrapped_idx);
+            shared_cells[loc.x + 1 + ((loc.y + 2) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y + 2 in shared for boundary
+        }
+        else if loc.y == group_y - 1 { // catches clean tiles before final y bound tile which still needs halo
+            let halo_cell = read_current(idx + uniforms.dims[0]); // fetch y + 1 in global
+            shared_cells[loc.x + 1 + ((loc.y + 2) * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y + 2 in shared
+        }
+        if gid.y == 0 {
+            let wrapped_idx: u32 = gid.x + ((uniforms.dims[1] - 1) * uniforms.dims[0]) + global_z_stride; // y = dims - 1 in global
 
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 2) * shared_x * shared_y)] = halo_cell; // write z + 2 in shared for Neumann bound
-            
-            }
-            else if loc.z == group_z - 1  && gid.z + 1 < uniforms.dims[2] { // catches clean tiles before final y bound tile which still needs halo
-                let global_z_stride = (gid.z + 1) * uniforms.dims[3]; // recompute z + 1 stride
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch z + 1 in global
+            let halo_cell = ghost(1, false, middle_voxel, wrapped_idx);
+            shared_cells[loc.x + 1 + (loc.y * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y in shared for boundary
+        }
+        else if loc.y == 0 { // gid.y > 0 already assured
+            let halo_cell = read_current(idx - uniforms.dims[0]); // fetch y - 1 in global
+            shared_cells[loc.x + 1 + (loc.y * shared_x) + ((loc.z + 1) * shared_x * shared_y)] = halo_cell; // write y in shared
+        }
 
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 2) * shared_x * shared_y)] = halo_cell; // write z + 2 in shared
-            
-            }
-            else if loc.z == 0 && gid.z > 0 { // && gid.z < uniforms.dims[2] already assured
-                let global_z_stride = (gid.z - 1) * uniforms.dims[3]; // recompute z + 1 stride
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch z - 1  in global
+        // Z HALOS
+        if gid.z == uniforms.dims[2] - 1 { // catches both short tiles and clean tiles
+            let wrapped_idx: u32 = gid.x + global_y_stride; // z = 0 in global
 
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + (loc.z * shared_x * shared_y)] = halo_cell; // write z in shared
-                
-            }
-            else if gid.z == 0  {
-                let idx: u32 = gid.x + global_y_stride + global_z_stride; // fetch z in global
+            let halo_cell = ghost(2, true, middle_voxel, wrapped_idx);
+            shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 2) * shared_x * shared_y)] = halo_cell; // write z + 2 in shared for boundary
+        }
+        else if loc.z == group_z - 1 { // catches clean tiles before final z bound tile which still needs halo
+            let halo_cell = read_current(idx + uniforms.dims[3]); // fetch z + 1 in global
+            shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + ((loc.z + 2) * shared_x * shared_y)] = halo_cell; // write z + 2 in shared
+        }
+        if gid.z == 0 {
+            let wrapped_idx: u32 = gid.x + global_y_stride + ((uniforms.dims[2] - 1) * uniforms.dims[3]); // z = dims - 1 in global
 
-                let halo_cell = grid_b[idx];
-                shared_cells[loc.x + 1 + ((loc.y + 1) *shared_x) + (loc.z * shared_x * shared_y)] = halo_cell; // write z in shared for Neumann bound
-                }
-        } // READ GRID_B
+            let halo_cell = ghost(2, false, middle_voxel, wrapped_idx);
+            shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + (loc.z * shared_x * shared_y)] = halo_cell; // write z in shared for boundary
+        }
+        else if loc.z == 0 { // gid.z > 0 already assured
+            let halo_cell = read_current(idx - uniforms.dims[3]); // fetch z - 1 in global
+            shared_cells[loc.x + 1 + ((loc.y + 1) * shared_x) + (loc.z * shared_x * shared_y)] = halo_cell; // write z in shared
+        }
     }
         // all halos and inner cells loaded, OOB still arrive here
         workgroupBarrier();
@@ -264,9 +186,12 @@ fn laplacian(@builtin(global_invocation_id) gid: vec3<u32>, @builtin(local_invoc
         let c_i_zmin = shared_cells[ idx_zmin ];
         let c_i_zplus = shared_cells[ idx_zplus ];
 
-        // LAPLACIAN x^2 == 1.0, D = 1.0
-        let next_c_i = c_i + ((1.0 * uniforms.timestep[0] / 1.0) * ((c_i_xmin + c_i_xplus + c_i_ymin + c_i_yplus + c_i_zmin + c_i_zplus) - (6.0 * c_i)));
-        if uniforms.flags[0] == 1 {
+        // LAPLACIAN, per-axis spacing, D from SimulationParams
+        let laplacian = ((c_i_xmin + c_i_xplus - (2.0 * c_i)) * uniforms.diffusion[1])
+            + ((c_i_ymin + c_i_yplus - (2.0 * c_i)) * uniforms.diffusion[2])
+            + ((c_i_zmin + c_i_zplus - (2.0 * c_i)) * uniforms.diffusion[3]);
+        let next_c_i = c_i + (uniforms.diffusion[0] * uniforms.timestep[0] * laplacian);
+        if (uniforms.flags[0] & 1) == 1 {
             grid_b[idx] = next_c_i;
         }
         else {
@@ -7,9 +7,30 @@ struct Uniforms{
     centre: vec4<f32>, // some k*forward
     up: vec4<f32>,
     right: vec4<f32>, // [3] horizontal scaling factor (not needed for up, 1:1)
-    timestep: vec4<f32>, // [0] time in seconds
+    timestep: vec4<f32>, // [0] substep dt in simulated seconds
     seed: vec4<f32>,
-    flags: vec4<u32> // [0] reada flag 1 true, 0 false
+    flags: vec4<u32>, // [0] bit 0 reada flag 1 true, 0 false, bits 8-15 render mode, bits 16-23 interpolation, [1..3] x, y, z boundary conditions (low face bits 0-7, high face bits 8-15)
+    boundary_low: vec4<f32>, // Dirichlet values on the index 0 face of x, y, z
+    boundary_high: vec4<f32>, // Dirichlet values on the index dims - 1 face of x, y, z
+    diffusion: vec4<f32>, // [0] D, [1..3] 1/dx^2, 1/dy^2, 1/dz^2
+    reaction: vec4<f32>, // reaction model parameters (see ReactionModel::params())
+    species_diffusion: vec4<f32>, // D per species
+    reaction_flags: vec4<u32>, // [0] reaction model, [1] species count, [2] channel shown by the raymarch, [3] channel count
+    transfer: vec4<f32>, // [0..1] voxel values at either end of the transfer function
+    raymarch: vec4<f32>, // [0] step size in voxels, [1] early exit alpha, [2] jitter 1 on 0 off, [3] frame
+    render_params: vec4<f32>, // [0] threshold of the first hit mode or iso value of the isosurface mode
+    lighting: vec4<f32>, // isosurface Blinn–Phong: [0] ambient, [1] diffuse, [2] specular, [3] shininess
+    slices: vec4<u32>, // [0..2] sagittal, coronal, axial slice index, [3] layout bits 0-7, single axis bits 8-15, focus bits 16-23
+    clip_planes: array<vec4<f32>, 6>, // keeps dot(xyz, p) + w >= 0, p in voxel coords, unused planes keep everything
+    crop_min: vec4<f32>, // crop box in voxel coords
+    crop_max: vec4<f32>,
+    composite_flags: vec4<u32>, // [0] channel blend, [1] composited channel count
+    composite_channels: vec4<u32>, // channel index of each composited slot
+    composite_ranges: array<vec4<f32>, 4>, // [0..1] values at either end of each slot's lookup table
+    label_flags: vec4<u32>, // [0] label volume loaded, [1] label slots (see LabelTable::slot())
+    world_from_voxel: array<vec4<f32>, 3>, // rows of the voxel -> world affine, translation in w
+    voxel_from_world: array<vec4<f32>, 3>, // its inverse
+    spacing: vec4<f32> // voxel edge length along x, y, z in world units
 }
 
 // BINDINGS
@@ -23,173 +44,469 @@ var<storage, read_write> grid_a: array<f32>;
 var<storage, read_write> grid_b: array<f32>;
 
 @group(0) @binding(3)
-var output_tex: texture_storage_2d<rgba8unorm, write>; 
+var output_tex: texture_storage_2d<rgba16float, write>; 
+
+@group(0) @binding(4)
+var<storage, read> transfer_lut: array<vec4<f32>>; // TransferFunction::lut(), sRGB colour + opacity
+
+@group(0) @binding(5)
+var<storage, read> labels: array<u32>; // label slot per voxel (see LabelVolume::slots())
+
+@group(0) @binding(6)
+var<storage, read> label_colours: array<vec4<f32>>; // sRGB colour + opacity per slot, transparent background
 
 // CONSTS
 const ray_group: u32 = 16; 
 
+// RENDER MODES, must match RenderMode::code() Rust-side
+const EMISSION_ABSORPTION: u32 = 0;
+const MAXIMUM_INTENSITY: u32 = 1;
+const MINIMUM_INTENSITY: u32 = 2;
+const AVERAGE: u32 = 3;
+const FIRST_HIT: u32 = 4;
+const ISOSURFACE: u32 = 5;
+
+// INTERPOLATION, must match Interpolation::code() Rust-side
+const NEAREST: u32 = 0;
+const TRILINEAR: u32 = 1;
+const TRICUBIC: u32 = 2;
+
+// CHANNEL BLENDS, must match ChannelBlend::code() Rust-side
+const BLEND_SHOWN: u32 = 0;
+const BLEND_ADDITIVE: u32 = 1;
+const BLEND_MAXIMUM: u32 = 2;
+
+const TF_RESOLUTION: u32 = 256; // lookup table length, transfer_lut holds the TransferFunction's then one per composited channel
+
+// SLICE LAYOUTS, must match SliceLayout::code() Rust-side
+const SLICES_HIDDEN: u32 = 0;
+const SLICES_PANES: u32 = 1;
+const SLICES_SINGLE: u32 = 2;
+
+const BISECTION_STEPS: u32 = 6; // halves the step size each, 1/64 of a step is well below a voxel
+
+// reads whichever grid holds the current field
+fn read_current(idx: u32) -> f32 {
+    if (uniforms.flags[0] & 1) == 1 { return grid_a[idx]; }
+    return grid_b[idx];
+}
+
+// voxel value -> sRGB colour + opacity, linear between lookup table entries
+fn transfer(value: f32) -> vec4<f32> {
+    let t = clamp((value - uniforms.transfer[0]) / (uniforms.transfer[1] - uniforms.transfer[0]), 0.0, 1.0);
+    return lut_at(0u, t);
+}
+
+// lookup table slot (0 the TransferFunction, 1 + i composited channel i) at normalised t
+fn lut_at(slot: u32, t: f32) -> vec4<f32> {
+    let x = t * f32(TF_RESOLUTION - 1);
+    let i = min(u32(floor(x)), TF_RESOLUTION - 2) + (slot * TF_RESOLUTION);
+    return mix(transfer_lut[i], transfer_lut[i + 1], x - f32(i % TF_RESOLUTION));
+}
+
+// composited channel i's colour and opacity for value, over that channel's range (see ChannelDisplay)
+fn channel_transfer(i: u32, value: f32) -> vec4<f32> {
+    let range = uniforms.composite_ranges[i];
+    let t = clamp((value - range.x) / (range.y - range.x), 0.0, 1.0);
+    return lut_at(1u + i, t);
+}
+
+// combines two channels' samples by the channel blend
+fn blend(a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
+    if uniforms.composite_flags[0] == BLEND_MAXIMUM { return max(a, b); }
+    return min(a + b, vec4<f32>(1.0));
+}
+
+// every composited channel at p, blended
+fn composite_sample(p: vec3<f32>) -> vec4<f32> {
+    var sample = vec4<f32>(0.0); // MUT
+    for (var i = 0u; i < uniforms.composite_flags[1]; i++) {
+        sample = blend(sample, channel_transfer(i, sample_channel(p, uniforms.composite_channels[i])));
+    }
+    return sample;
+}
+
+
+// hash of pixel and frame -> [0, 1), for ray jitter
+fn jitter(pixel: vec2<u32>, frame: u32) -> f32 {
+    var h = (pixel.x * 1973u) ^ (pixel.y * 9277u) ^ (frame * 26699u);
+    h = (h ^ (h >> 15u)) * 0x2c1b3c6du;
+    h = (h ^ (h >> 12u)) * 0x297a2d39u;
+    h = h ^ (h >> 15u);
+    return f32(h & 0xffffffu) / 16777216.0;
+}
+
+// entry and exit ray parameters through the crop box [crop_min, crop_max], the whole grid [0, dims] uncropped (slab intersection)
+// entry > exit when the ray misses
+fn slab(origin: vec3<f32>, dir: vec3<f32>) -> vec2<f32> {
+    let low_planes = (uniforms.crop_min.xyz - origin) / dir; // crop_min[x] coefficients
+    let high_planes = (uniforms.crop_max.xyz - origin) / dir; // crop_max[x] coefficients
+    let near = min(low_planes, high_planes);
+    let far = max(low_planes, high_planes);
+    let entry = max(max(near.x, near.y), max(near.z, 0.0)); // never behind the camera
+    let exit = min(min(far.x, far.y), far.z);
+    return vec2<f32>(entry, exit);
+}
+
+// narrows [entry, exit] from slab() to the part of the ray every clip plane keeps
+fn clip(origin: vec3<f32>, dir: vec3<f32>, hits: vec2<f32>) -> vec2<f32> {
+    var span = hits; // MUT
+    for (var i = 0u; i < 6u; i++) {
+        let plane = uniforms.clip_planes[i];
+        let facing = dot(plane.xyz, dir);
+        let side = dot(plane.xyz, origin) + plane.w; // >= 0 where the ray starts on the kept side
+        if abs(facing) < 1e-6 { // parallel, all in or all out
+            if side < 0.0 { span.y = span.x; }
+            continue;
+        }
+        let t = -side / facing;
+        if facing > 0.0 { span.x = max(span.x, t); } // heading into the kept side
+        else { span.y = min(span.y, t); }
+    }
+    return span;
+}
+
+// voxel of a channel, clamped to the grid (clamp to edge beyond the outer voxel centres)
+fn voxel_at(channel: u32, v: vec3<i32>) -> f32 {
+    let c = vec3<u32>(clamp(v, vec3<i32>(0), vec3<i32>(uniforms.dims.xyz) - vec3<i32>(1)));
+    let channel_offset = channel * uniforms.dims.z * uniforms.dims[3]; // channel block (see Channels)
+    return read_current(channel_offset + c.x + (c.y * uniforms.dims.x) + (c.z * uniforms.dims[3]));
+}
+
+// VOXEL TRANSFORM, see VoxelTransform Rust-side //
+// world position -> voxel coordinates
+fn to_voxel(w: vec3<f32>) -> vec3<f32> {
+    return offset_to_voxel(w) + vec3<f32>(uniforms.voxel_from_world[0].w, uniforms.voxel_from_world[1].w, uniforms.voxel_from_world[2].w);
+}
+
+// world direction or offset -> voxel coordinates
+fn offset_to_voxel(d: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(dot(uniforms.voxel_from_world[0].xyz, d), dot(uniforms.voxel_from_world[1].xyz, d), dot(uniforms.voxel_from_world[2].xyz, d));
+}
+
+// voxel direction or offset -> world
+fn offset_to_world(d: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(dot(uniforms.world_from_voxel[0].xyz, d), dot(uniforms.world_from_voxel[1].xyz, d), dot(uniforms.world_from_voxel[2].xyz, d));
+}
+
+// voxel space gradient -> world normal direction (inverse transpose)
+fn normal_to_world(g: vec3<f32>) -> vec3<f32> {
+    return (uniforms.voxel_from_world[0].xyz * g.x) + (uniforms.voxel_from_world[1].xyz * g.y) + (uniforms.voxel_from_world[2].xyz * g.z);
+}
+
+// label slot of the voxel containing p, never interpolated so regions keep hard boundaries
+fn label_at(p: vec3<f32>) -> u32 {
+    let c = vec3<u32>(clamp(vec3<i32>(floor(p)), vec3<i32>(0), vec3<i32>(uniforms.dims.xyz) - vec3<i32>(1)));
+    return labels[c.x + (c.y * uniforms.dims.x) + (c.z * uniforms.dims[3])];
+}
+
+// gradient of "inside label slot" at p, points into the region like the scalar gradient into higher values
+fn label_gradient(p: vec3<f32>, slot: u32) -> vec3<f32> {
+    let inside = vec3<f32>(
+        f32(label_at(p + vec3<f32>(1.0, 0.0, 0.0)) == slot) - f32(label_at(p - vec3<f32>(1.0, 0.0, 0.0)) == slot),
+        f32(label_at(p + vec3<f32>(0.0, 1.0, 0.0)) == slot) - f32(label_at(p - vec3<f32>(0.0, 1.0, 0.0)) == slot),
+        f32(label_at(p + vec3<f32>(0.0, 0.0, 1.0)) == slot) - f32(label_at(p - vec3<f32>(0.0, 0.0, 1.0)) == slot)
+    );
+    return inside * 0.5;
+}
+
+// Catmull–Rom weights for the 4 voxels around a sample, f is the fraction past the second
+fn catmull_rom(f: f32) -> vec4<f32> {
+    let f2 = f * f;
+    let f3 = f2 * f;
+    return vec4<f32>(
+        (-0.5 * f3) + f2 - (0.5 * f),
+        (1.5 * f3) - (2.5 * f2) + 1.0,
+        (-1.5 * f3) + (2.0 * f2) + (0.5 * f),
+        (0.5 * f3) - (0.5 * f2)
+    );
+}
+
+// shown channel at a point in voxel space
+fn sample_voxel(p: vec3<f32>) -> f32 {
+    return sample_channel(p, uniforms.reaction_flags[2]);
+}
+
+// channel at a point in voxel space, voxel i spans [i, i + 1) with its value at the centre i + 0.5
+fn sample_channel(p: vec3<f32>, channel: u32) -> f32 {
+    let interpolation = (uniforms.flags[0] >> 16) & 0xff;
+    if interpolation == NEAREST {
+        return voxel_at(channel, vec3<i32>(floor(p)));
+    }
+
+    let q = p - vec3<f32>(0.5); // centres on integer coords
+    let base = vec3<i32>(floor(q));
+    let f = q - floor(q);
+
+    if interpolation == TRICUBIC {
+        let wx = catmull_rom(f.x);
+        let wy = catmull_rom(f.y);
+        let wz = catmull_rom(f.z);
+        var value: f32 = 0.0; // MUT
+        for (var k: i32 = 0; k < 4; k++) {
+            for (var j: i32 = 0; j < 4; j++) {
+                var row: f32 = 0.0; // MUT
+                for (var i: i32 = 0; i < 4; i++) {
+                    row += wx[i] * voxel_at(channel, base + vec3<i32>(i - 1, j - 1, k - 1));
+                }
+                value += wz[k] * wy[j] * row;
+            }
+        }
+        return value;
+    }
+
+    // TRILINEAR
+    let c00 = mix(voxel_at(channel, base), voxel_at(channel, base + vec3<i32>(1, 0, 0)), f.x);
+    let c10 = mix(voxel_at(channel, base + vec3<i32>(0, 1, 0)), voxel_at(channel, base + vec3<i32>(1, 1, 0)), f.x);
+    let c01 = mix(voxel_at(channel, base + vec3<i32>(0, 0, 1)), voxel_at(channel, base + vec3<i32>(1, 0, 1)), f.x);
+    let c11 = mix(voxel_at(channel, base + vec3<i32>(0, 1, 1)), voxel_at(channel, base + vec3<i32>(1, 1, 1)), f.x);
+    return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
+}
+
+// central difference gradient of the shown channel, points towards increasing value
+fn gradient(p: vec3<f32>) -> vec3<f32> {
+    let dx = vec3<f32>(1.0, 0.0, 0.0);
+    let dy = vec3<f32>(0.0, 1.0, 0.0);
+    let dz = vec3<f32>(0.0, 0.0, 1.0);
+    return vec3<f32>(
+        sample_voxel(p + dx) - sample_voxel(p - dx),
+        sample_voxel(p + dy) - sample_voxel(p - dy),
+        sample_voxel(p + dz) - sample_voxel(p - dz)
+    ) * 0.5;
+}
+
+// refines an iso crossing bracketed by ray parameters [t_near, t_far], returns the crossing's t
+fn bisect(origin: vec3<f32>, dir: vec3<f32>, t_near: f32, t_far: f32, iso: f32) -> f32 {
+    var near = t_near; // MUT
+    var far = t_far; // MUT
+    let near_above = sample_voxel(origin + (dir * near)) >= iso;
+    for (var i: u32 = 0; i < BISECTION_STEPS; i++) {
+        let mid = (near + far) * 0.5;
+        if (sample_voxel(origin + (dir * mid)) >= iso) == near_above { near = mid; } else { far = mid; }
+    }
+    return (near + far) * 0.5;
+}
+
+// Blinn–Phong with a headlight: light and view directions are both back along the ray, so the half vector is too
+// two sided, the normal is flipped to face the camera
+fn shade(p: vec3<f32>, dir: vec3<f32>, colour: vec3<f32>) -> vec3<f32> {
+    return light(gradient(p), dir, colour);
+}
+
+// shade() with the normal along g, both g and dir in voxel coordinates
+// lit in the world, so anisotropic spacing doesn't skew the normals
+fn light(g_voxel: vec3<f32>, dir_voxel: vec3<f32>, colour: vec3<f32>) -> vec3<f32> {
+    let g = normal_to_world(g_voxel);
+    let dir = normalize(offset_to_world(dir_voxel));
+    var n = -dir; // MUT, flat regions face the camera
+    if dot(g, g) > 1e-12 { n = faceForward(normalize(g), dir, normalize(g)); }
+    let to_eye = -dir;
+    let diffuse = max(dot(n, to_eye), 0.0);
+    let specular = pow(diffuse, uniforms.lighting[3]); // dot(n, half vector), half vector == to_eye
+    return (colour * (uniforms.lighting[0] + (uniforms.lighting[1] * diffuse))) + vec3<f32>(uniforms.lighting[2] * specular);
+}
 
 @compute @workgroup_size(ray_group, ray_group)
 fn raymarch(@builtin(global_invocation_id) gid: vec3<u32>) {
-    
-    // if (gid.x == 0u && gid.y == 0u) {
-    //  textureStore(output_tex, vec2<u32>(10u, 10u), vec4f(1.0, 0.0, 0.0, 1.0));
-    // return;
-    //}
-
     // no bounds check here beucase dispatch only launched for threads in bounding box
-    // first undo horizontal scaling of bounding box (later, scaled version is still used to write to texture)
-    let screen_to_world = vec2<f32>(
-        f32(uniforms.bounding_box.x) / uniforms.right.w, // steps left from centre
-        f32(uniforms.bounding_box.y) // steps down from centre
-        //f32(uniforms.bounding_box[2]) / uniforms.right[3], // steps right from centre 
-        //f32(uniforms.bounding_box[3]) // steps up from centre
-    );
-    
-    // all directions accessed by some r, u addition onto screen_to_world[0] and [1]
+    // undo horizontal scaling of bounding box pixels to get near plane coords (scaled version is still used to write to texture)
+    let pixel_coord = vec2<i32>(uniforms.bounding_box.x + i32(gid.x), uniforms.bounding_box.y + i32(gid.y));
     let plane_coord = vec2<f32>(
-        screen_to_world.x + f32(gid.x),
-        screen_to_world.y + f32(gid.y)
+        f32(pixel_coord.x) / uniforms.right.w, // steps right from centre
+        f32(pixel_coord.y) // steps up from centre
     );
+    let final_window_coord = vec2<u32>(u32(i32(uniforms.mid_window.x) + pixel_coord.x), u32(i32(uniforms.mid_window.y) + pixel_coord.y));
 
-    let right = uniforms.right * plane_coord.x; // both orthogonal to centre
-    let up = uniforms.up * plane_coord.y;
+    // ray through this pixel of the near plane, in the world as centre, right and up are
+    let direction = uniforms.centre.xyz + (uniforms.right.xyz * plane_coord.x) + (uniforms.up.xyz * plane_coord.y);
 
-    let direction = uniforms.centre + right + up; 
+    // march in voxel coordinates (grid corner at the origin, voxel i spans [i, i + 1)), so t and the step size
+    // count voxels whatever the physical spacing
+    let origin = to_voxel(uniforms.cam_pos.xyz);
+    let ijk_step = normalize(offset_to_voxel(direction));
 
-    let magnitude = sqrt(((direction.x*direction.x) + (direction.y*direction.y) + (direction.z*direction.z)));
-    let norm_dir = vec3<f32>(direction.x / magnitude, direction.y / magnitude, direction.z / magnitude);
+    let hits = clip(origin, ijk_step, slab(origin, ijk_step));
+    if hits.x >= hits.y {
+        textureStore(output_tex, final_window_coord, vec4<f32>(0.0)); // missed the grid, transparent
+        return;
+    }
 
-    // direction into world coords
-    // dot ruf onto ijk
-    let ijk_direction = vec3<f32>(
-        (direction.x * uniforms.right.x) + (direction.y * uniforms.up.x) + (direction.z * uniforms.forward.x),
-        (direction.x * uniforms.right.y) + (direction.y * uniforms.up.y) + (direction.z * uniforms.forward.y),
-        (direction.x * uniforms.right.z) + (direction.y * uniforms.up.z) + (direction.z * uniforms.forward.z)
-    );
-    
-    // dot norm on ijk
-    let ijk_step = vec3<f32>(
-        (norm_dir.x * uniforms.right.x) + (norm_dir.y * uniforms.up.x) + (norm_dir.z * uniforms.forward.x),
-        (norm_dir.x * uniforms.right.y) + (norm_dir.y * uniforms.up.y) + (norm_dir.z * uniforms.forward.y),
-        (norm_dir.x * uniforms.right.z) + (norm_dir.y * uniforms.up.z) + (norm_dir.z * uniforms.forward.z)
-    );
+    let step_size = uniforms.raymarch[0];
+    var t = hits.x; // MUT
+    if uniforms.raymarch[2] > 0.5 { // jittered start removes wood grain banding
+        t += step_size * jitter(final_window_coord, u32(uniforms.raymarch[3]));
+    }
 
-    // now direction is in terms of i, j and k
-    // shift into voxel space by + dims/2.0 (treat voxel grid itself in R3, indices are sampled by weighted averages of N3)
-    let voxel_direction = vec3<f32>(
-        ijk_direction.x + (f32(uniforms.dims.x)/2.0),
-        ijk_direction.y + (f32(uniforms.dims.y)/2.0),
-        ijk_direction.z + (f32(uniforms.dims.z)/2.0)
-    );
+    let mode = (uniforms.flags[0] >> 8) & 0xff;
+    var accumulated = vec4<f32>(0.0); // MUT
+    var extreme = select(-3.4e38, 3.4e38, mode == MINIMUM_INTENSITY); // MUT
+    var sum: f32 = 0.0; // MUT
+    var count: f32 = 0.0; // MUT
+    var hit = false; // MUT
+    var previous = sample_voxel(origin + (ijk_step * t)); // MUT, isosurface crossings are sign changes of value - iso
+    var t_previous = t; // MUT
+    // channel blends composite every visible channel in emission–absorption and maximum intensity, other modes read the shown one
+    let composited = uniforms.composite_flags[1];
+    let compositing = uniforms.composite_flags[0] != BLEND_SHOWN && composited > 0u
+        && (mode == EMISSION_ABSORPTION || mode == MAXIMUM_INTENSITY);
+    var extremes = vec4<f32>(-3.4e38); // MUT, per composited slot
+    // labels accumulate front to back on their own, drawn over wherever the render mode stops the ray
+    var labelled = vec4<f32>(0.0); // MUT
 
-    // compute entry and exit plane intersection of voxel direction + k*ijk_step
-    // + dims[x] coefficients
-    let k = (f32(uniforms.dims.z) - voxel_direction.z) / ijk_step.z;
-    let j = (f32(uniforms.dims.y) - voxel_direction.y) / ijk_step.y;
-    let i = (f32(uniforms.dims.x) - voxel_direction.x) / ijk_step.x;
-    // dims[x] = 0 coefficients
-    let zerok = - voxel_direction.z / ijk_step.z;
-    let zeroj = - voxel_direction.y / ijk_step.y;
-    let zeroi = - voxel_direction.x / ijk_step.x;
-
-    // near plane
-    let mink = min(zerok, k);
-    let minj = min(zeroj, j);
-    let mini = min(zeroi, i);
-
-    let entry = max(max(mink, minj), mini);
-
-    // far plane
-    let maxk = max(zerok, k);
-    let maxj = max(zeroj, j);
-    let maxi = max(zeroi, i);
-
-    let exit = min(min(maxi,maxj), maxk);
-
-    let x_nudge = voxel_direction.x / 10; // if ray lands intersects exactly at cell boundaries
-    let y_nudge = voxel_direction.y / 10;
-    let z_nudge = voxel_direction.z / 10;
-    let nudged_direction = vec3<f32>(voxel_direction.x + x_nudge, voxel_direction.y + y_nudge, voxel_direction.z + z_nudge); // add a little nudge
-
-    // get entry exit coords in voxel space (ijk but offset)
-    let entry_point: vec3<f32> = nudged_direction + (ijk_step * entry);
-    if entry_point.x >= f32(uniforms.dims.x) || entry_point.y >= f32(uniforms.dims.y) || entry_point.z >= f32(uniforms.dims.z) { return; }
-    
-    let exit_point: vec3<f32> = nudged_direction + (ijk_step * exit); // handles exit plane intersection at boundary
-
-    let entry_idx: f32 = entry_point.x + (entry_point.y * f32(uniforms.dims.x)) + (entry_point.z * f32(uniforms.dims[3]));
-    
-    let flat_size: f32 = f32((uniforms.dims.z * uniforms.dims[3]));
-    var accumulated_values: f32 = 0.0; // MUT
-    let travel_vector = exit_point - entry_point;
-    let max_projection = (travel_vector.x * ijk_step.x)  + (travel_vector.y * ijk_step.y) + (travel_vector.z * ijk_step.z);
-
-    if entry_idx < flat_size && entry_idx >= 0 { // entry idx bounds check
-        let floored_entry_idx = u32(floor(entry_idx));
-        
-        var next_point = vec3<f32>(entry_point + ijk_step); // MUT
-        var next_projection = (ijk_step.x * ijk_step.x)  + (ijk_step.y * ijk_step.y) + (ijk_step.z * ijk_step.z); // MUT
-        let unit_projection = ((ijk_step.x * ijk_step.x)  + (ijk_step.y * ijk_step.y) + (ijk_step.z * ijk_step.z));
-        
-        if uniforms.flags.x == 1u { // read a (reading from ping, this frame computes the frame displayed on succeeding loop)
-            accumulated_values = grid_a[floored_entry_idx];
-            while next_projection <= max_projection {
-                 if (next_point.x >= f32(uniforms.dims.x) || next_point[1] >= f32(uniforms.dims.y) || next_point.z >= f32(uniforms.dims.z) || 
-                 next_point.x < 0.0 || next_point.y < 0.0 || next_point.z < 0.0) { break; }
-                    let idx = u32(floor(next_point.x 
-                    + next_point.y * f32(uniforms.dims.x) 
-                    + next_point.z * f32(uniforms.dims[3])
-                    ));
-                    accumulated_values += grid_a[idx]; // how are you going to handle colour and opacity?
-                    next_point += ijk_step;
-                    next_projection += unit_projection;
-                }
+    while t < hits.y {
+        let p = origin + (ijk_step * t);
+        if uniforms.label_flags[0] == 1u {
+            let slot = label_at(p);
+            let colour = label_colours[slot];
+            if colour.a >= 1.0 { // opaque regions are solid, shaded at their surface
+                labelled += vec4<f32>(light(label_gradient(p, slot), ijk_step, colour.rgb), 1.0) * (1.0 - labelled.a);
+            } else if colour.a > 0.0 {
+                let alpha = 1.0 - pow(1.0 - colour.a, step_size);
+                labelled += vec4<f32>(colour.rgb * alpha, alpha) * (1.0 - labelled.a);
+            }
+            if labelled.a >= uniforms.raymarch[1] { break; } // nothing behind is visible
+        }
+        if compositing && mode == MAXIMUM_INTENSITY {
+            for (var i = 0u; i < composited; i++) {
+                extremes[i] = max(extremes[i], sample_channel(p, uniforms.composite_channels[i]));
+            }
+            t += step_size;
+            continue;
         }
-        else if uniforms.flags.x == 0u { // read b (ping buffer)
-            accumulated_values = grid_b[floored_entry_idx];
-            while next_projection <= max_projection {
-                 if (next_point.x >= f32(uniforms.dims.x) || next_point.y >= f32(uniforms.dims.y) || next_point[2] >= f32(uniforms.dims.z) ||
-                 next_point.x < 0.0 || next_point.y < 0.0 || next_point.z < 0.0) { break; }
-                    let idx = u32(floor(next_point.x 
-                    + next_point.y * f32(uniforms.dims.x) 
-                    + next_point.z * f32(uniforms.dims.w)
-                    ));
-                    accumulated_values += grid_b[idx]; // how are you going to handle colour and opacity?
-                    next_point += ijk_step;
-                    next_projection = next_projection + next_projection;
+        let value = sample_voxel(p);
+        switch mode {
+            case MAXIMUM_INTENSITY: { extreme = max(extreme, value); }
+            case MINIMUM_INTENSITY: { extreme = min(extreme, value); }
+            case AVERAGE: {
+                sum += value;
+                count += 1.0;
+            }
+            case FIRST_HIT: {
+                if value >= uniforms.render_params[0] {
+                    extreme = value;
+                    hit = true;
+                }
+            }
+            case ISOSURFACE: {
+                let iso = uniforms.render_params[0];
+                if (value >= iso) != (previous >= iso) { // crossed in either direction
+                    let t_hit = bisect(origin, ijk_step, t_previous, t, iso);
+                    let colour = transfer(iso).rgb;
+                    accumulated = vec4<f32>(shade(origin + (ijk_step * t_hit), ijk_step, colour), 1.0);
+                    hit = true;
                 }
+                previous = value;
+                t_previous = t;
+            }
+            default: { // EMISSION_ABSORPTION, front to back
+                // opacity from the transfer function is per voxel travelled, corrected for step size
+                // colour is accumulated premultiplied by alpha
+                var sample = transfer(value); // MUT
+                if compositing { sample = composite_sample(p); }
+                let alpha = 1.0 - pow(1.0 - clamp(sample.a, 0.0, 1.0), step_size);
+                accumulated += vec4<f32>(sample.rgb * alpha, alpha) * (1.0 - accumulated.a);
+                hit = accumulated.a >= uniforms.raymarch[1]; // early ray termination, nothing behind is visible
+            }
         }
+        if hit { break; }
+        t += step_size;
     }
-    else { return; }
 
-    // map accumulated value to texture coord and write
-    // output_tex is rgba8unorm
-    // larger accumulate, more R and A
-    // I want to be able to see through the voxel cuboid mostly, so accumulate of 1.0 == A 1.0 is not a good idea
-    // the cells were initialised with the fract() of a stretched out sin(seed), so the max of a cell is .99999
-    
-    // Using Beer-Lambert
-    let o: f32 = 0.6;
-    let b: f32 = 0.4;
+    // projection modes report one value, coloured by the transfer function and opaque
+    switch mode {
+        case MAXIMUM_INTENSITY, MINIMUM_INTENSITY: { accumulated = vec4<f32>(transfer(extreme).rgb, 1.0); }
+        case AVERAGE: { accumulated = vec4<f32>(transfer(sum / max(count, 1.0)).rgb, 1.0); }
+        case FIRST_HIT: {
+            if hit { accumulated = vec4<f32>(transfer(extreme).rgb, 1.0); }
+        }
+        default: {}
+    }
+    if compositing && mode == MAXIMUM_INTENSITY {
+        var colour = vec4<f32>(0.0); // MUT
+        for (var i = 0u; i < composited; i++) { colour = blend(colour, channel_transfer(i, extremes[i])); }
+        accumulated = vec4<f32>(colour.rgb, 1.0);
+    }
+    accumulated = labelled + (accumulated * (1.0 - labelled.a));
 
-    let alpha = clamp(1 - exp((-accumulated_values/max_projection * o)), 0.0, 1.0);
-    let blue = max(1 - exp((accumulated_values * - b)), 0.0);
-    let red = alpha; // for now
+    // write to storage texture (output_tex is rgba16float, premultiplied alpha)
+    textureStore(output_tex, final_window_coord, accumulated);
+}
 
-    var write_val= vec4<f32>(red, 0.0 , blue, alpha);
+// pane of the window holding one slice: origin and size in texture pixels (rows count upwards, as in raymarch())
+struct Pane {
+    origin: vec2<f32>,
+    size: vec2<f32>,
+    axis: u32, // normal of the slice, 0 sagittal, 1 coronal, 2 axial
+}
 
-    // write to storage texture    
-    let pixel_coord = vec2<i32>(uniforms.bounding_box.x + i32(gid.x), uniforms.bounding_box.y + i32(gid.y));
-    let final_window_coord = vec2<u32>(u32((i32(uniforms.mid_window.x) + pixel_coord.x)), u32(i32(uniforms.mid_window.y) + pixel_coord.y));
+// in plane (right, up) voxel axes of a slice across normal
+fn slice_axes(normal: u32) -> vec2<u32> {
+    switch normal {
+        case 0u: { return vec2<u32>(1u, 2u); } // sagittal: y by z
+        case 1u: { return vec2<u32>(0u, 2u); } // coronal: x by z
+        default: { return vec2<u32>(0u, 1u); } // axial: x by y
+    }
+}
+
+@compute @workgroup_size(ray_group, ray_group)
+fn slice(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let window = textureDimensions(output_tex);
+    if gid.x >= window.x || gid.y >= window.y { return; }
+    let arrangement = uniforms.slices[3] & 0xff;
+    let focus = (uniforms.slices[3] >> 16) & 0xff;
+    let pixel = vec2<f32>(gid.xy) + vec2<f32>(0.5);
 
-    if final_window_coord.x < 0 || final_window_coord.y < 0 || final_window_coord.x > uniforms.mid_window.x * 2 || final_window_coord.y > uniforms.mid_window.y * 2 {
-        write_val.y = 1.0; // green indicates corrupt final window coord
+    // FIND PANE //
+    var pane: Pane; // MUT
+    if arrangement == SLICES_SINGLE {
+        pane = Pane(vec2<f32>(0.0), vec2<f32>(window), (uniforms.slices[3] >> 8) & 0xff);
+    } else {
+        // square panes stacked down the right edge, axial at the top
+        let side = f32(window.y / 3u);
+        let column = f32(window.x) - side;
+        if pixel.x < column { return; } // raymarched volume
+        let from_top = u32((f32(window.y) - pixel.y) / side);
+        if from_top > 2u { return; }
+        pane = Pane(vec2<f32>(column, f32(window.y) - (f32(from_top + 1u) * side)), vec2<f32>(side), 2u - from_top);
     }
 
-    textureStore(output_tex, final_window_coord, write_val);
-    
+    // FIT SLICE IN PANE, keeping the physical aspect (voxels are spacing sized) //
+    let axes = slice_axes(pane.axis);
+    let spacing = vec2<f32>(uniforms.spacing[axes.x], uniforms.spacing[axes.y]);
+    let voxels = vec2<f32>(f32(uniforms.dims[axes.x]), f32(uniforms.dims[axes.y]));
+    let physical = voxels * spacing;
+    let scale = min(pane.size.x / physical.x, pane.size.y / physical.y); // pixels per world unit
+    let offset = (pane.size - (physical * scale)) / 2.0;
+    let local = pixel - pane.origin;
+    let uv = (local - offset) / (scale * spacing); // voxel coords in the slice plane
 
-}
+    var colour = vec4<f32>(0.0); // MUT, transparent around a single slice
+    if arrangement == SLICES_PANES { colour = vec4<f32>(0.1, 0.1, 0.1, 1.0); }
+
+    if all(uv >= vec2<f32>(0.0)) && all(uv < voxels) {
+        var p = vec3<f32>(0.0); // MUT
+        p[axes.x] = uv.x;
+        p[axes.y] = uv.y;
+        p[pane.axis] = f32(uniforms.slices[pane.axis]) + 0.5; // through the slice's voxel centres
+        colour = vec4<f32>(transfer(sample_voxel(p)).rgb, 1.0);
+        if uniforms.label_flags[0] == 1u { // labels tint slices at half their opacity, so the data stays readable
+            let label = label_colours[label_at(p)];
+            colour = vec4<f32>(mix(colour.rgb, label.rgb, label.a * 0.5), 1.0);
+        }
+
+        // crosshair where the other two slices cut this one
+        let cross = (vec2<f32>(f32(uniforms.slices[axes.x]), f32(uniforms.slices[axes.y])) + vec2<f32>(0.5)) * spacing * scale;
+        let from_cross = abs(local - offset - cross);
+        if min(from_cross.x, from_cross.y) < 0.5 {
+            colour = vec4<f32>(mix(colour.rgb, vec3<f32>(1.0), 0.5), 1.0);
+        }
+    }
 
+    // outline the focused pane
+    let from_edge = min(local, pane.size - local);
+    if arrangement == SLICES_PANES && pane.axis == focus && min(from_edge.x, from_edge.y) < 2.0 {
+        colour = vec4<f32>(1.0);
+    }
 
+    textureStore(output_tex, gid.xy, colour);
+}
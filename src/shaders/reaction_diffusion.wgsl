@@ -0,0 +1,164 @@
+struct Uniforms{
+    mid_window: vec4<u32>,
+    dims: vec4<u32>, // i, j, k, k stride
+    bounding_box: vec4<i32>,
+    cam_pos: vec4<f32>,
+    forward: vec4<f32>,
+    centre: vec4<f32>, // some k*forward
+    up: vec4<f32>,
+    right: vec4<f32>, // [3] horizontal scaling factor (not needed for up, 1:1)
+    timestep: vec4<f32>, // [0] substep dt in simulated seconds
+    seed: vec4<f32>,
+    flags: vec4<u32>, // [0] bit 0 reada flag 1 true, 0 false, bits 8-15 render mode, bits 16-23 interpolation, [1..3] x, y, z boundary conditions (low face bits 0-7, high face bits 8-15)
+    boundary_low: vec4<f32>, // Dirichlet values on the index 0 face of x, y, z
+    boundary_high: vec4<f32>, // Dirichlet values on the index dims - 1 face of x, y, z
+    diffusion: vec4<f32>, // [0] D, [1..3] 1/dx^2, 1/dy^2, 1/dz^2
+    reaction: vec4<f32>, // reaction model parameters (see ReactionModel::params())
+    species_diffusion: vec4<f32>, // D per species
+    reaction_flags: vec4<u32>, // [0] reaction model, [1] species count, [2] channel shown by the raymarch, [3] channel count
+    transfer: vec4<f32>, // [0..1] voxel values at either end of the transfer function
+    raymarch: vec4<f32>, // [0] step size in voxels, [1] early exit alpha, [2] jitter 1 on 0 off, [3] frame
+    render_params: vec4<f32>, // [0] threshold of the first hit mode or iso value of the isosurface mode
+    lighting: vec4<f32>, // isosurface Blinn–Phong: [0] ambient, [1] diffuse, [2] specular, [3] shininess
+    slices: vec4<u32>, // [0..2] sagittal, coronal, axial slice index, [3] layout bits 0-7, single axis bits 8-15, focus bits 16-23
+    clip_planes: array<vec4<f32>, 6>, // keeps dot(xyz, p) + w >= 0, p in voxel coords, unused planes keep everything
+    crop_min: vec4<f32>, // crop box in voxel coords
+    crop_max: vec4<f32>,
+    composite_flags: vec4<u32>, // [0] channel blend, [1] composited channel count
+    composite_channels: vec4<u32>, // channel index of each composited slot
+    composite_ranges: array<vec4<f32>, 4>, // [0..1] values at either end of each slot's lookup table
+    label_flags: vec4<u32>, // [0] label volume loaded, [1] label slots (see LabelTable::slot())
+    world_from_voxel: array<vec4<f32>, 3>, // rows of the voxel -> world affine, translation in w
+    voxel_from_world: array<vec4<f32>, 3>, // its inverse
+    spacing: vec4<f32> // voxel edge length along x, y, z in world units
+}
+// BINDINGS
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+@group(0) @binding(1)
+var<storage, read_write> grid_a: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read_write> grid_b: array<f32>;
+
+@group(0) @binding(3)
+var output_tex: texture_storage_2d<rgba16float, write>; 
+
+// CONSTS
+const group_x: u32 = 8;
+const group_y: u32 = 4;
+const group_z: u32 = 8;
+
+const MAX_SPECIES: u32 = 4;
+
+// BOUNDARY CONDITIONS, must match BoundaryCondition::code() Rust-side
+const NEUMANN: u32 = 0;
+const DIRICHLET: u32 = 1;
+const PERIODIC: u32 = 2;
+const ABSORBING: u32 = 3;
+
+// REACTION MODELS, must match ReactionModel::code() Rust-side
+const NONE: u32 = 0;
+const GRAY_SCOTT: u32 = 1;
+const FITZHUGH_NAGUMO: u32 = 2;
+const SCHNAKENBERG: u32 = 3;
+
+// reads whichever grid holds the current field
+fn read_current(idx: u32) -> f32 {
+    if (uniforms.flags[0] & 1) == 1 { return grid_a[idx]; }
+    return grid_b[idx];
+}
+
+// neighbour one voxel away along axis, or the ghost value beyond a face of the grid
+// same rules as ghost() in laplacian.wgsl, offset selects the species block
+fn neighbour(axis: u32, high: bool, coord: vec3<u32>, c_i: f32, offset: u32) -> f32 {
+    let strides = vec3<u32>(1, uniforms.dims[0], uniforms.dims[3]);
+    let idx = offset + dot(coord, strides);
+    let last = uniforms.dims[axis] - 1;
+
+    if (high && coord[axis] < last) { return read_current(idx + strides[axis]); }
+    if (!high && coord[axis] > 0) { return read_current(idx - strides[axis]); }
+
+    let packed = uniforms.flags[axis + 1];
+    let code = select(packed & 0xff, (packed >> 8) & 0xff, high);
+    let value = select(uniforms.boundary_low[axis], uniforms.boundary_high[axis], high);
+    switch code {
+        case DIRICHLET: { return (2.0 * value) - c_i; } // value held on the face itself
+        case PERIODIC: { 
+            if high { return read_current(idx - (last * strides[axis])); }
+            return read_current(idx + (last * strides[axis]));
+        }
+        case ABSORBING: { return 0.0; }
+        default: { return c_i; } // NEUMANN, zero flux
+    }
+}
+
+// reaction rates at one voxel, u = c[0], v = c[1], further species only diffuse
+// must match ReactionModel::rates() Rust-side
+fn rates(c: array<f32, MAX_SPECIES>) -> array<f32, MAX_SPECIES> {
+    let u = c[0];
+    let v = c[1];
+    let p = uniforms.reaction;
+    var r = array<f32, MAX_SPECIES>(0.0, 0.0, 0.0, 0.0);
+    switch uniforms.reaction_flags[0] {
+        case GRAY_SCOTT: { // feed, kill
+            let uvv = u * v * v;
+            r[0] = -uvv + (p[0] * (1.0 - u));
+            r[1] = uvv - ((p[0] + p[1]) * v);
+        }
+        case FITZHUGH_NAGUMO: { // a, b, epsilon, current
+            r[0] = u - ((u * u * u) / 3.0) - v + p[3];
+            r[1] = p[2] * (u + p[0] - (p[1] * v));
+        }
+        case SCHNAKENBERG: { // a, b, gamma
+            let uuv = u * u * v;
+            r[0] = p[2] * (p[0] - u + uuv);
+            r[1] = p[2] * (p[1] - uuv);
+        }
+        default: {} // NONE
+    }
+    return r;
+}
+
+// ONE THREAD PER VOXEL, ALL SPECIES
+// Species are stored species-major: species s of voxel idx at s * voxels + idx
+// Diffusion per species with its own D (uniforms.diffusion[0] for all without a model), plus the reaction term, explicit Euler
+@compute @workgroup_size(group_x, group_y, group_z)
+fn reaction_diffusion(@builtin(global_invocation_id) gid: vec3<u32>){
+    if gid.x >= uniforms.dims[0] || gid.y >= uniforms.dims[1] || gid.z >= uniforms.dims[2] { return; }
+
+    let voxels = uniforms.dims[2] * uniforms.dims[3];
+    let species = min(uniforms.reaction_flags[1], MAX_SPECIES);
+    let idx = gid.x + (gid.y * uniforms.dims[0]) + (gid.z * uniforms.dims[3]);
+    let dt = uniforms.timestep[0];
+
+    var c = array<f32, MAX_SPECIES>(0.0, 0.0, 0.0, 0.0);
+    for (var s: u32 = 0; s < species; s++) {
+        c[s] = read_current((s * voxels) + idx);
+    }
+    let r = rates(c);
+
+    for (var s: u32 = 0; s < species; s++) {
+        let offset = s * voxels;
+        let c_i = c[s];
+
+        // LAPLACIAN, per-axis spacing
+        var laplacian: f32 = 0.0;
+        for (var axis: u32 = 0; axis < 3; axis++) {
+            let low = neighbour(axis, false, gid, c_i, offset);
+            let high = neighbour(axis, true, gid, c_i, offset);
+            laplacian += (low + high - (2.0 * c_i)) * uniforms.diffusion[axis + 1];
+        }
+
+        let d = select(uniforms.species_diffusion[s], uniforms.diffusion[0], uniforms.reaction_flags[0] == NONE); // plain diffusion's D without a model
+        let next_c_i = c_i + (dt * ((d * laplacian) + r[s]));
+        if (uniforms.flags[0] & 1) == 1 {
+            grid_b[offset + idx] = next_c_i;
+        }
+        else {
+            grid_a[offset + idx] = next_c_i;
+        }
+    }
+}
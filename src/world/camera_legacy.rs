@@ -4,6 +4,18 @@ use crate::world::voxel_grid::{P2i, P3};
 const DY_SENS: f32 = 0.00005;// half as sensitive y given typical display aspect ratio
 const DX_SENS: f32 = 0.0001; 
 
+const DAMPING: f32 = 4.0; // 1/s, velocity falls to 1/e in a quarter second without input
+const REST_FRACTION: f32 = 0.001; // below this fraction of max_speed the camera snaps to rest
+const DEFAULT_SPEED: f32 = 100.0;
+const MAX_MOVE_DT: f32 = 0.1; // s, a stalled frame shouldn't fling the camera across the grid
+
+const ORBIT_SENS: f32 = 0.005; // radians per pixel of mouse travel
+const PAN_SENS: f32 = 0.001; // fraction of orbit distance per pixel of mouse travel
+const DOLLY_SENS: f32 = 0.1; // fraction of orbit distance per scroll line
+const MAX_ELEVATION: f32 = std::f32::consts::FRAC_PI_2 - 0.01; // keeps f off the world up axis (r = up x f degenerates)
+const WORLD_UP: P3 = [0.0, 1.0, 0.0];
+
+#[derive(Debug, Copy, Clone)]
 pub struct FPVCamera {
     pub c: P3, // where c is camera pos in world space, 
     pub f: P3, // where f is unit vector from c to world space origin, orthogonal to u and r (u X r)
@@ -12,6 +24,9 @@ pub struct FPVCamera {
     pub centre: P3, // ASSUMES WINDOW EXISTS ON CAMERA INIT (check state.rs new())
     kf: f32,
 
+    pub velocity: P3, // world units per second
+    pub max_speed: f32, // terminal speed with a key held, world units per second (see World::fly_speed())
+
     dx_sens: f32,
     dy_sens: f32
 }
@@ -93,34 +108,27 @@ impl FPVCamera {
 
 
 
-    /// should add velocity and inertia in the Forward direction 
-    pub fn handle_w(&mut self) {
-        let step_size = 2.0;
-        let max_decay = 1.0; // heavier = smaller decay
-
-        let f_coeff = (step_size - (self.weight.exp())).clamp(0.0, 2.0);
-
-        let step = FPVCamera::scale(&self.f, &f_coeff); // scale F
-        FPVCamera::add(&self.inertia, &step); // add F direction to inertia
-
-        let decay = (max_decay - (self.weight.exp())).clamp(0.0, 1.0); // decay inertia
-        self.inertia = FPVCamera::decay(&self.inertia, &decay);
-
-        FPVCamera::add(&self.c,&self.inertia); // add inertia to camera pos
-
-        // recompute RUF 
-
-    }
-
-    pub fn handle_s(&mut self) {
+    /// Integrates one frame of translation: held keys accelerate along f and r,
+    /// damping bleeds velocity off so the camera glides to a stop once keys are released
+    /// Exact for constant thrust over dt, so motion doesn't depend on frame rate
+    pub fn update(&mut self, dt: f32, input: &MoveInput) {
+        let dt = dt.min(MAX_MOVE_DT);
+        let thrust = input.direction(&self.f, &self.r);
+        let terminal = FPVCamera::scale(&thrust, &self.max_speed); // velocity the thrust settles at
+        let decay = (-DAMPING * dt).exp();
 
-    }
-    pub fn handle_a(&mut self) {
+        // v(t + dt) = terminal + (v(t) - terminal) * e^(-k dt)
+        // c(t + dt) = c(t) + terminal * dt + (v(t) - terminal) * (1 - e^(-k dt)) / k
+        let offset = FPVCamera::add(&self.velocity, &FPVCamera::negate(&terminal));
+        let glide = FPVCamera::scale(&offset, &((1.0 - decay) / DAMPING));
+        self.c = FPVCamera::add(&self.c, &FPVCamera::add(&FPVCamera::scale(&terminal, &dt), &glide));
 
+        self.velocity = FPVCamera::add(&terminal, &FPVCamera::scale(&offset, &decay));
+        if !input.any() && FPVCamera::magnitude(&self.velocity) < self.max_speed * REST_FRACTION {
+            self.velocity = [0.0; 3];
+        }
     }
-    pub fn handle_d(&mut self) {
 
-    }
     /// Order of rotation matters, so only this function is exposed externally to handle camera rotations
     /// to ensure order is always preserved
     pub fn handle_rotate(&mut self, dx: f32, dy: f32){
@@ -166,6 +174,12 @@ impl FPVCamera {
     }
 
 
+    /// Near plane distance follows the window: fixes 90 FOV in the smaller dimension
+    pub fn on_resize(&mut self, size: &PhysicalSize<u32>) {
+        self.kf = (size.width.min(size.height)) as f32 / 2.0;
+        self.centre = FPVCamera::scale(&self.f, &self.kf);
+    }
+
     pub fn new(p: P3, size: &PhysicalSize<u32>) -> Self {
         
 
@@ -188,6 +202,8 @@ impl FPVCamera {
         r: right,
         centre: centre,
         kf: kf,
+        velocity: [0.0; 3],
+        max_speed: DEFAULT_SPEED,
         dx_sens: DX_SENS,
         dy_sens: DY_SENS,
         }
@@ -195,3 +211,193 @@ impl FPVCamera {
     }
     
 }
+
+/// Movement keys held down, tracked across frames by State::handle_key()
+/// so translation continues between key repeat events
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MoveInput {
+    pub forward: bool,
+    pub back: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl MoveInput {
+    pub fn any(&self) -> bool {
+        self.forward || self.back || self.left || self.right
+    }
+
+    /// Unit thrust direction in world space from the held keys, zero when they cancel out
+    pub fn direction(&self, f: &P3, r: &P3) -> P3 {
+        let kf = (self.forward as i32 - self.back as i32) as f32;
+        let kr = (self.right as i32 - self.left as i32) as f32;
+        let dir = FPVCamera::add(&FPVCamera::scale(f, &kf), &FPVCamera::scale(r, &kr));
+        let mag = FPVCamera::magnitude(&dir);
+        if mag > 0.0 { FPVCamera::normalise(&dir, &mag) } else { dir }
+    }
+}
+
+/// Arcball style camera that always faces its target (by default the VoxelGrid centre)
+/// Position is kept in spherical coordinates about the target, the r/u/f/centre basis
+/// is rebuilt from them into view after every change so Resources and World read it like an FPVCamera's
+pub struct OrbitalCamera {
+    pub view: FPVCamera, // basis handed to the raymarch and the bounding box projection
+    pub target: P3,
+    pub distance: f32, // target to c
+    pub azimuth: f32, // about world up, 0 looks down +z
+    pub elevation: f32, // above the xz plane, clamped to +-MAX_ELEVATION
+
+    min_distance: f32,
+    max_distance: f32
+}
+
+impl OrbitalCamera {
+    /// Starts on the -z side of target looking down +z, the same basis FPVCamera starts with
+    pub fn new(target: P3, distance: f32, size: &PhysicalSize<u32>) -> Self {
+        assert!(distance > 0.0);
+        let mut orbital = OrbitalCamera {
+            view: FPVCamera::new(target, size),
+            target: target,
+            distance: distance,
+            azimuth: 0.0,
+            elevation: 0.0,
+            min_distance: distance / 100.0,
+            max_distance: distance * 10.0
+        };
+        orbital.update_view();
+        orbital
+    }
+
+    /// Orbits around target looking from wherever view currently is, e.g. when switching from an FPVCamera
+    pub fn from_view(view: FPVCamera, target: P3, default_distance: f32) -> Self {
+        let offset = FPVCamera::add(&view.c, &FPVCamera::negate(&target)); // target -> c
+        let distance = FPVCamera::magnitude(&offset);
+        let mut orbital = OrbitalCamera {
+            view: view,
+            target: target,
+            distance: distance,
+            azimuth: 0.0,
+            elevation: 0.0,
+            min_distance: default_distance / 100.0,
+            max_distance: default_distance * 10.0
+        };
+        if distance > 0.0 {
+            let d = FPVCamera::normalise(&offset, &distance);
+            orbital.elevation = d[1].clamp(-1.0, 1.0).asin();
+            orbital.azimuth = d[0].atan2(-d[2]);
+        }
+        else { orbital.distance = default_distance; }
+        orbital.distance = orbital.distance.clamp(orbital.min_distance, orbital.max_distance);
+        orbital.elevation = orbital.elevation.clamp(-MAX_ELEVATION, MAX_ELEVATION);
+        orbital.update_view();
+        orbital
+    }
+
+    /// Mouse x deltas orbit about world up, y deltas tilt over the target
+    pub fn handle_rotate(&mut self, dx: f32, dy: f32) {
+        self.azimuth = (self.azimuth - (dx * ORBIT_SENS)) % std::f32::consts::TAU;
+        self.elevation = (self.elevation + (dy * ORBIT_SENS)).clamp(-MAX_ELEVATION, MAX_ELEVATION);
+        self.update_view();
+    }
+
+    /// Slides target (and c with it) across the view plane, scaled by distance so the grid tracks the cursor
+    pub fn handle_pan(&mut self, dx: f32, dy: f32) {
+        let k = self.distance * PAN_SENS;
+        let right = FPVCamera::scale(&self.view.r, &(-dx * k));
+        let up = FPVCamera::scale(&self.view.u, &(dy * k));
+        self.target = FPVCamera::add(&self.target, &FPVCamera::add(&right, &up));
+        self.update_view();
+    }
+
+    /// Dolly towards (positive lines) or away from target, geometric so zoom feels the same at any distance
+    pub fn handle_dolly(&mut self, lines: f32) {
+        self.distance = (self.distance * (1.0 - DOLLY_SENS).powf(lines)).clamp(self.min_distance, self.max_distance);
+        self.update_view();
+    }
+
+    /// Jumps straight to an orbit (e.g. along a CameraPath), clamped like the mouse controls
+    pub fn set_orbit(&mut self, azimuth: f32, elevation: f32, distance: f32) {
+        self.azimuth = azimuth % std::f32::consts::TAU;
+        self.elevation = elevation.clamp(-MAX_ELEVATION, MAX_ELEVATION);
+        self.distance = distance.clamp(self.min_distance, self.max_distance);
+        self.update_view();
+    }
+
+    /// Rebuilds c and the r/u/f/centre basis from target, distance, azimuth and elevation
+    fn update_view(&mut self) {
+        let (sin_az, cos_az) = self.azimuth.sin_cos();
+        let (sin_el, cos_el) = self.elevation.sin_cos();
+        let to_camera: P3 = [cos_el * sin_az, sin_el, -cos_el * cos_az]; // unit, target -> c
+
+        self.view.c = FPVCamera::add(&self.target, &FPVCamera::scale(&to_camera, &self.distance));
+        self.view.f = FPVCamera::negate(&to_camera);
+        let r = FPVCamera::cross(&WORLD_UP, &self.view.f);
+        self.view.r = FPVCamera::normalise(&r, &FPVCamera::magnitude(&r));
+        self.view.u = FPVCamera::cross(&self.view.f, &self.view.r);
+        self.view.centre = FPVCamera::scale(&self.view.f, &self.view.kf);
+    }
+}
+
+/// Camera driving the raymarch, switchable at runtime (see Camera::toggle())
+/// FPV: free flying, mouse looks around from c
+/// Orbital: mouse orbits target, scroll dollies, right drag pans
+pub enum Camera {
+    FPV(FPVCamera),
+    Orbital(OrbitalCamera),
+}
+
+impl Camera {
+    /// r/u/f/centre basis and position uploaded by Resources::uniforms_refresh()
+    pub fn view(&self) -> &FPVCamera {
+        match self {
+            Camera::FPV(cam) => cam,
+            Camera::Orbital(cam) => &cam.view,
+        }
+    }
+
+    pub fn view_mut(&mut self) -> &mut FPVCamera {
+        match self {
+            Camera::FPV(cam) => cam,
+            Camera::Orbital(cam) => &mut cam.view,
+        }
+    }
+
+    pub fn handle_rotate(&mut self, dx: f32, dy: f32) {
+        match self {
+            Camera::FPV(cam) => cam.handle_rotate(dx, dy),
+            Camera::Orbital(cam) => cam.handle_rotate(dx, dy),
+        }
+    }
+
+    /// FPV only, called once per frame with the wall clock dt
+    pub fn update(&mut self, dt: f32, input: &MoveInput) {
+        if let Camera::FPV(cam) = self { cam.update(dt, input); }
+    }
+
+    /// Orbital only
+    pub fn handle_pan(&mut self, dx: f32, dy: f32) {
+        if let Camera::Orbital(cam) = self { cam.handle_pan(dx, dy); }
+    }
+
+    /// Orbital only
+    pub fn handle_dolly(&mut self, lines: f32) {
+        if let Camera::Orbital(cam) = self { cam.handle_dolly(lines); }
+    }
+
+    pub fn on_resize(&mut self, size: &PhysicalSize<u32>) {
+        match self {
+            Camera::FPV(cam) => cam.on_resize(size),
+            Camera::Orbital(cam) => cam.view.on_resize(size),
+        }
+    }
+
+    /// FPV -> Orbital about target, Orbital -> FPV, both keeping the current view
+    pub fn toggle(&mut self, target: P3, default_distance: f32) {
+        let mut view = *self.view();
+        view.velocity = [0.0; 3];
+        *self = match self {
+            Camera::FPV(_) => Camera::Orbital(OrbitalCamera::from_view(view, target, default_distance)),
+            Camera::Orbital(_) => Camera::FPV(view),
+        };
+    }
+}
@@ -0,0 +1,125 @@
+use std::{error::Error, fs::File, io::BufWriter, path::{Path, PathBuf}};
+use crate::world::camera::{Camera, OrbitalCamera};
+
+/// Image files State::capture_frame() writes, picked from the path's extension
+/// Png: 8-bit sRGB
+/// Tiff16: 16-bit sRGB, from the half float storage texture (about 11 bits of real precision)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Tiff16,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "tif" | "tiff" => Some(ImageFormat::Tiff16),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Tiff16 => "tiff",
+        }
+    }
+}
+
+/// Where an OrbitalCamera sits, see OrbitalCamera's fields
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraKey {
+    pub azimuth: f32,
+    pub elevation: f32,
+    pub distance: f32,
+}
+
+impl CameraKey {
+    pub fn of(orbital: &OrbitalCamera) -> Self {
+        CameraKey { azimuth: orbital.azimuth, elevation: orbital.elevation, distance: orbital.distance }
+    }
+}
+
+/// Camera motion over a FrameSequence, about the current orbital target
+/// Still: camera stays where it is (FPV or orbital)
+/// Turntable: turns full orbits about world up from the current view, the last frame stopping one step short
+/// Keyframes: linear between keys spread evenly over the sequence, first and last frames on the end keys
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraPath {
+    Still,
+    Turntable { turns: f32 },
+    Keyframes(Vec<CameraKey>),
+}
+
+impl CameraPath {
+    /// Places the camera for frame i of frames, start is the orbit when the sequence began
+    /// Moving paths need an orbital camera
+    pub fn apply(&self, camera: &mut Camera, start: CameraKey, i: u32, frames: u32) {
+        let Camera::Orbital(orbital) = camera else { return; };
+        match self {
+            CameraPath::Still => {},
+            CameraPath::Turntable { turns } => {
+                let t = i as f32 / frames as f32;
+                orbital.set_orbit(start.azimuth + (turns * std::f32::consts::TAU * t), start.elevation, start.distance);
+            },
+            CameraPath::Keyframes(keys) => {
+                if keys.is_empty() { return; }
+                let t = if frames > 1 { i as f32 / (frames - 1) as f32 } else { 0.0 };
+                let x = t * (keys.len() - 1) as f32;
+                let k = (x.floor() as usize).min(keys.len().saturating_sub(2));
+                let (a, b) = (keys[k], keys[(k + 1).min(keys.len() - 1)]);
+                let w = x - k as f32;
+                let lerp = |p: f32, q: f32| p + ((q - p) * w);
+                orbital.set_orbit(lerp(a.azimuth, b.azimuth), lerp(a.elevation, b.elevation), lerp(a.distance, b.distance));
+            },
+        }
+    }
+}
+
+/// Numbered frames for State::render_sequence(), written as <directory>/<prefix>_00000.<format>
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSequence {
+    pub frames: u32,
+    pub width: u32,
+    pub height: u32,
+    pub directory: PathBuf,
+    pub prefix: String,
+    pub format: ImageFormat,
+    pub simulate: bool, // advance the simulation between frames
+    pub frame_dt: f32, // simulated seconds per frame under TimeStep::Adaptive (TimeStep::Fixed uses its own)
+    pub camera: CameraPath,
+}
+
+impl FrameSequence {
+    pub fn path(&self, i: u32) -> PathBuf {
+        self.directory.join(format!("{}_{:05}.{}", self.prefix, i, self.format.extension()))
+    }
+}
+
+/// Premultiplied RGBA rows, bottom row first as the raymarch writes them, composited over an opaque
+/// sRGB background and saved top row first
+pub fn save_image(path: &Path, format: ImageFormat, width: u32, height: u32, pixels: &[[f32; 4]], background: [f32; 3]) -> Result<(), Box<dyn Error>> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+    let rgb: Vec<f32> = (0..height).rev()
+        .flat_map(|row| pixels[(row * width) as usize..((row + 1) * width) as usize].iter())
+        .flat_map(|p| [0, 1, 2].map(|c| (p[c] + (background[c] * (1.0 - p[3]))).clamp(0.0, 1.0)))
+        .collect();
+
+    let file = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(file, width, height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+            let bytes: Vec<u8> = rgb.iter().map(|c| (c * 255.0).round() as u8).collect();
+            encoder.write_header()?.write_image_data(&bytes)?;
+        },
+        ImageFormat::Tiff16 => {
+            let words: Vec<u16> = rgb.iter().map(|c| (c * 65535.0).round() as u16).collect();
+            tiff::encoder::TiffEncoder::new(file)?.write_image::<tiff::encoder::colortype::RGB16>(width, height, &words)?;
+        },
+    }
+    Ok(())
+}
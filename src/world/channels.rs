@@ -0,0 +1,365 @@
+use std::{collections::BTreeMap, error::Error};
+use crate::world::{reaction::MAX_SPECIES, transfer_function::{TransferFunction, TF_RESOLUTION}};
+
+/// Channels the raymarch blends at once (see ChannelBlend), the first visible ones in channel order
+pub const MAX_COMPOSITE: usize = 4;
+
+/// sRGB colours for channels without their own, by channel index: green, magenta, cyan, yellow, red, blue
+pub const CHANNEL_PALETTE: [[f32; 3]; 6] = [
+    [0.0, 1.0, 0.0], [1.0, 0.0, 1.0], [0.0, 1.0, 1.0], [1.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]
+];
+
+/// Element type a channel's data came in as
+/// The voxel buffers hold every channel as f32 (exact for integers up to 2^24), dtype records the source
+/// and sets the default value range
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChannelType {
+    U8,
+    U16,
+    U32,
+    F32,
+}
+
+impl ChannelType {
+    /// Full range of the type, [0, 1] for F32
+    pub fn range(&self) -> [f32; 2] {
+        match self {
+            ChannelType::U8 => [0.0, u8::MAX as f32],
+            ChannelType::U16 => [0.0, u16::MAX as f32],
+            ChannelType::U32 => [0.0, u32::MAX as f32],
+            ChannelType::F32 => [0.0, 1.0],
+        }
+    }
+}
+
+/// How a channel looks when blended with others, as in a fluorescence viewer
+/// Values normalised over Channel::range take the colour scaled by the normalised value, with the
+/// transfer function's opacity (its colormap and range are not used) times weight
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelDisplay {
+    pub colour: Option<[f32; 3]>, // sRGB, None takes CHANNEL_PALETTE by channel index
+    pub transfer: TransferFunction,
+    pub visible: bool,
+    pub weight: f32, // opacity multiplier
+}
+
+impl Default for ChannelDisplay {
+    fn default() -> Self {
+        ChannelDisplay {
+            colour: None,
+            transfer: TransferFunction::default(),
+            visible: true,
+            weight: 1.0
+        }
+    }
+}
+
+impl ChannelDisplay {
+    /// [r, g, b, opacity] at TF_RESOLUTION evenly spaced positions over [0, 1], see TransferFunction::lut()
+    pub fn lut(&self, index: usize) -> Vec<[f32; 4]> {
+        let colour = self.colour.unwrap_or(CHANNEL_PALETTE[index % CHANNEL_PALETTE.len()]);
+        (0..TF_RESOLUTION).map(|i| {
+            let t = i as f32 / (TF_RESOLUTION - 1) as f32;
+            [colour[0] * t, colour[1] * t, colour[2] * t, (self.transfer.opacity_at(t) * self.weight).clamp(0.0, 1.0)]
+        }).collect()
+    }
+}
+
+/// One co-registered layer of the grid, e.g. a gene, protein or metabolite, or a reaction–diffusion species
+/// Simulated channels are stepped by the diffusion passes, the rest are static data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub name: String,
+    pub dtype: ChannelType,
+    pub range: [f32; 2], // values at either end of the transfer function while shown
+    pub metadata: BTreeMap<String, String>, // free-form, e.g. units, gene id, antibody
+    pub simulated: bool,
+    pub values: Vec<f32>, // one per voxel (x fastest, as the voxel buffers), empty until data is loaded
+    pub display: ChannelDisplay,
+}
+
+impl Channel {
+    /// Empty channel with dtype's full range
+    pub fn new(name: &str, dtype: ChannelType) -> Self {
+        Channel {
+            name: name.to_string(),
+            dtype: dtype,
+            range: dtype.range(),
+            metadata: BTreeMap::new(),
+            simulated: false,
+            values: Vec::new(),
+            display: ChannelDisplay::default()
+        }
+    }
+
+    /// Data channel ranging over its values' min and max
+    pub fn from_values(name: &str, dtype: ChannelType, values: Vec<f32>) -> Self {
+        let (low, high) = values.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(l, h), &v| (l.min(v), h.max(v)));
+        let mut channel = Channel::new(name, dtype);
+        if low < high { channel.range = [low, high]; }
+        channel.values = values;
+        channel
+    }
+
+    /// Simulated f32 channel, starting from the reaction model's initial state
+    pub fn species(name: &str) -> Self {
+        Channel { simulated: true, ..Channel::new(name, ChannelType::F32) }
+    }
+}
+
+/// Named channels of a VoxelGrid, laid out channel-major in the voxel buffers:
+/// channel c of voxel idx lives at c * voxels + idx
+/// Simulated channels always come first, so they are the species blocks the diffusion passes step
+/// (species s is the s-th simulated channel, see ReactionModel), the static channels follow
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channels {
+    list: Vec<Channel>,
+    shown: usize, // channel the raymarch, slices and mesh extraction read
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels::new()
+    }
+}
+
+impl Channels {
+    /// A single simulated channel, as plain diffusion expects
+    pub fn new() -> Self {
+        Channels { list: vec![Channel::species("field")], shown: 0 }
+    }
+
+    /// Just channel, which becomes the simulated one (e.g. a loaded volume, see State::load_volume())
+    pub fn from_channel(channel: Channel) -> Self {
+        Channels { list: vec![Channel { simulated: true, ..channel }], shown: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// True when there are no channels
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Channel> {
+        self.list.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Channel> {
+        self.list.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Channel> {
+        self.list.get_mut(index)
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.list.iter().position(|c| c.name == name)
+    }
+
+    /// Number of simulated channels, the species count
+    pub fn species(&self) -> usize {
+        self.list.iter().take_while(|c| c.simulated).count()
+    }
+
+    pub fn shown(&self) -> usize {
+        self.shown
+    }
+
+    /// Adds a channel (after the other simulated ones if simulated, last otherwise), returns its index
+    /// voxels: per channel, checked against non-empty values
+    pub fn add(&mut self, channel: Channel, voxels: usize) -> Result<usize, Box<dyn Error>> {
+        if self.index_of(&channel.name).is_some() {
+            return Err(format!("Channel {} already exists", channel.name).into());
+        }
+        if !channel.values.is_empty() && channel.values.len() != voxels {
+            return Err(format!("Channel {} has {} values, expected {}", channel.name, channel.values.len(), voxels).into());
+        }
+        if channel.simulated && self.species() >= MAX_SPECIES {
+            return Err(format!("Already simulating {} channels", MAX_SPECIES).into());
+        }
+        let index = if channel.simulated { self.species() } else { self.list.len() };
+        if index <= self.shown && index < self.list.len() { self.shown += 1; } // keep showing the same channel
+        self.list.insert(index, channel);
+        Ok(index)
+    }
+
+    /// Removes a channel, the last one can't be removed
+    pub fn remove(&mut self, name: &str) -> Result<Channel, Box<dyn Error>> {
+        let index = self.index_of(name).ok_or(format!("No channel named {}", name))?;
+        if self.list.len() == 1 {
+            return Err("Can't remove the only channel".into());
+        }
+        if index < self.shown || (index == self.shown && index == self.list.len() - 1) { self.shown -= 1; }
+        Ok(self.list.remove(index))
+    }
+
+    /// Channel the raymarch, slices and mesh extraction read
+    pub fn show(&mut self, name: &str) -> Result<usize, Box<dyn Error>> {
+        self.shown = self.index_of(name).ok_or(format!("No channel named {}", name))?;
+        Ok(self.shown)
+    }
+
+    /// Moves a channel in or out of the simulated ones (keeping its values as a starting field)
+    /// Changes the species count, so the simulation has to restart (see State::set_simulated())
+    pub fn set_simulated(&mut self, name: &str, simulated: bool) -> Result<(), Box<dyn Error>> {
+        let index = self.index_of(name).ok_or(format!("No channel named {}", name))?;
+        if self.list[index].simulated == simulated { return Ok(()); }
+        if simulated && self.species() >= MAX_SPECIES {
+            return Err(format!("Already simulating {} channels", MAX_SPECIES).into());
+        }
+        if !simulated && self.species() == 1 {
+            return Err("At least one channel has to be simulated".into());
+        }
+        let shown_name = self.list[self.shown].name.clone();
+        let mut channel = self.list.remove(index);
+        channel.simulated = simulated;
+        let to = if simulated { self.species() } else { self.list.len() };
+        self.list.insert(to, channel);
+        self.shown = self.index_of(&shown_name).unwrap();
+        Ok(())
+    }
+
+    /// Makes exactly count channels simulated, for a reaction model with count species
+    /// Extra simulated channels without data are dropped, the others become static; simulated channels
+    /// without data take their species' name, missing species are added
+    pub fn fit_species(&mut self, count: usize, name: impl Fn(usize) -> String) {
+        assert!((1..=MAX_SPECIES).contains(&count));
+        while self.species() > count {
+            let last = self.list[self.species() - 1].name.clone();
+            if self.list[self.species() - 1].values.is_empty() { let _ = self.remove(&last); }
+            else { let _ = self.set_simulated(&last, false); }
+        }
+        for s in 0..self.species() {
+            if self.list[s].values.is_empty() && self.index_of(&name(s)).is_none() { self.list[s].name = name(s); }
+        }
+        while self.species() < count {
+            let mut channel = Channel::species(&name(self.species()));
+            if self.index_of(&channel.name).is_some() { channel.name = format!("{}_{}", channel.name, self.list.len()); }
+            let _ = self.add(channel, 0);
+        }
+    }
+
+    /// Indices of the channels the raymarch blends, the first MAX_COMPOSITE visible ones
+    pub fn composited(&self) -> Vec<usize> {
+        (0..self.list.len()).filter(|&c| self.list[c].display.visible).take(MAX_COMPOSITE).collect()
+    }
+
+    /// Uniforms.composite_channels and composite_ranges: channel indices, and [range min, range max, 0, 0] each
+    pub fn composite_uniform(&self) -> ([u32; 4], [[f32; 4]; MAX_COMPOSITE]) {
+        let mut indices = [0; 4];
+        let mut ranges = [[0.0, 1.0, 0.0, 0.0]; MAX_COMPOSITE];
+        for (slot, &c) in self.composited().iter().enumerate() {
+            indices[slot] = c as u32;
+            ranges[slot] = [self.list[c].range[0], self.list[c].range[1], 0.0, 0.0];
+        }
+        (indices, ranges)
+    }
+
+    /// Lookup tables of the composited channels one after another, MAX_COMPOSITE * TF_RESOLUTION (unused slots transparent)
+    pub fn composite_luts(&self) -> Vec<[f32; 4]> {
+        let mut luts = vec![[0.0; 4]; MAX_COMPOSITE * TF_RESOLUTION];
+        for (slot, &c) in self.composited().iter().enumerate() {
+            luts[slot * TF_RESOLUTION..(slot + 1) * TF_RESOLUTION].copy_from_slice(&self.list[c].display.lut(c));
+        }
+        luts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(channels: &Channels) -> Vec<&str> {
+        channels.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    fn data(name: &str) -> Channel {
+        Channel::from_values(name, ChannelType::U8, vec![0.0, 1.0])
+    }
+
+    #[test]
+    fn add_keeps_shown_channel() {
+        let mut channels = Channels::new();
+        channels.add(data("dapi"), 2).unwrap();
+        channels.show("dapi").unwrap();
+        assert_eq!(channels.add(Channel::species("v"), 2).unwrap(), 1); // simulated go before static
+        assert_eq!(names(&channels), ["field", "v", "dapi"]);
+        assert_eq!(channels.shown(), 2);
+        assert!(channels.add(data("dapi"), 2).is_err());
+        assert!(channels.add(data("short"), 3).is_err());
+    }
+
+    #[test]
+    fn remove_keeps_shown_channel() {
+        let mut channels = Channels::new();
+        channels.add(data("a"), 2).unwrap();
+        channels.add(data("b"), 2).unwrap();
+        channels.show("b").unwrap();
+        channels.remove("a").unwrap();
+        assert_eq!(channels.shown(), 1);
+        assert_eq!(channels.get(channels.shown()).unwrap().name, "b");
+
+        // removing the shown channel shows its neighbour, the last one steps back
+        channels.remove("b").unwrap();
+        assert_eq!(channels.shown(), 0);
+        assert!(channels.remove("field").is_err());
+        assert_eq!(channels.len(), 1);
+        assert!(!channels.is_empty());
+    }
+
+    #[test]
+    fn set_simulated_keeps_shown_channel() {
+        let mut channels = Channels::new();
+        channels.add(data("a"), 2).unwrap();
+        channels.add(data("b"), 2).unwrap();
+        channels.show("b").unwrap();
+        channels.set_simulated("b", true).unwrap();
+        assert_eq!(names(&channels), ["field", "b", "a"]);
+        assert_eq!(channels.species(), 2);
+        assert_eq!(channels.get(channels.shown()).unwrap().name, "b");
+
+        channels.set_simulated("field", false).unwrap();
+        assert_eq!(names(&channels), ["b", "a", "field"]);
+        assert_eq!(channels.get(channels.shown()).unwrap().name, "b");
+        assert!(channels.set_simulated("b", false).is_err());
+    }
+
+    #[test]
+    fn fit_species_one_two_one() {
+        let species = |s: usize| ["u", "v"][s].to_string();
+        let mut channels = Channels::new();
+        channels.add(data("dapi"), 2).unwrap();
+        channels.show("dapi").unwrap();
+
+        channels.fit_species(2, species);
+        assert_eq!(names(&channels), ["u", "v", "dapi"]);
+        assert_eq!(channels.species(), 2);
+        assert_eq!(channels.get(channels.shown()).unwrap().name, "dapi");
+
+        channels.fit_species(1, species);
+        assert_eq!(names(&channels), ["u", "dapi"]);
+        assert_eq!(channels.species(), 1);
+        assert_eq!(channels.get(channels.shown()).unwrap().name, "dapi");
+    }
+
+    #[test]
+    fn fit_species_keeps_data_as_static() {
+        let mut channels = Channels::from_channel(data("volume"));
+        channels.fit_species(2, |s| format!("s{}", s));
+        assert_eq!(names(&channels), ["volume", "s1"]);
+
+        channels.show("s1").unwrap();
+        channels.fit_species(1, |s| format!("s{}", s));
+        assert_eq!(names(&channels), ["volume"]); // the shown, empty species goes
+        assert_eq!(channels.shown(), 0);
+
+        channels.set_simulated("volume", true).unwrap();
+        channels.add(Channel { simulated: true, ..data("second") }, 2).unwrap();
+        channels.fit_species(1, |s| format!("s{}", s));
+        assert_eq!(names(&channels), ["volume", "second"]);
+        assert_eq!(channels.species(), 1);
+    }
+}
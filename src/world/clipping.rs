@@ -0,0 +1,191 @@
+use crate::world::voxel_grid::{Dims3, P3, VoxelTransform};
+
+/// Clip planes the raymarch tests every ray against, must match Uniforms.clip_planes in raymarch.wgsl
+pub const MAX_CLIP_PLANES: usize = 6;
+/// Thinnest the crop box gets along any axis, in voxels
+const MIN_CROP: f32 = 1.0;
+
+/// Half-space the raymarch keeps, in voxel coordinates (grid corner at the origin, voxel i covers [i, i + 1))
+/// Points p with dot(normal, p - point) >= 0 are kept, so the normal points into the part still drawn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClipPlane {
+    pub point: P3,
+    pub normal: P3,
+}
+
+impl ClipPlane {
+    /// Uniforms.clip_planes entry: unit normal, w such that dot(normal, p) + w >= 0 is kept
+    pub fn uniform(&self) -> [f32; 4] {
+        let n = self.normal;
+        let length = ((n[0] * n[0]) + (n[1] * n[1]) + (n[2] * n[2])).sqrt().max(f32::EPSILON);
+        let unit = [n[0] / length, n[1] / length, n[2] / length];
+        let w = -((unit[0] * self.point[0]) + (unit[1] * self.point[1]) + (unit[2] * self.point[2]));
+        [unit[0], unit[1], unit[2], w]
+    }
+}
+
+/// Face of the crop box moved by Clipping::nudge_crop(), by axis (0 x, 1 y, 2 z)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CropFace {
+    Low(usize),
+    High(usize),
+}
+
+impl CropFace {
+    /// x low -> x high -> y low -> y high -> z low -> z high -> x low
+    pub fn next(&self) -> Self {
+        match *self {
+            CropFace::Low(axis) => CropFace::High(axis),
+            CropFace::High(axis) => CropFace::Low((axis + 1) % 3),
+        }
+    }
+}
+
+/// Axis-aligned box in voxel coordinates, the only part of the grid the raymarch visits
+/// World::generate_bb_projection() projects its corners, so cropping also shrinks the raymarch dispatch
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CropBox {
+    pub min: P3,
+    pub max: P3,
+}
+
+impl CropBox {
+    /// Whole grid
+    pub fn full(dims: &Dims3) -> Self {
+        CropBox { min: [0.0; 3], max: [dims[0] as f32, dims[1] as f32, dims[2] as f32] }
+    }
+
+    /// Corners in world coordinates (see VoxelTransform)
+    /// ordered like VoxelGrid's vertices: near face (low z) then far face, each CCW from bottom left
+    pub fn world_corners(&self, transform: &VoxelTransform) -> [P3; 8] {
+        let (lo, hi) = (self.min, self.max);
+        let corner = |x: f32, y: f32, z: f32| transform.to_world([x, y, z]);
+        [
+            corner(lo[0], lo[1], lo[2]), corner(lo[0], hi[1], lo[2]), corner(hi[0], hi[1], lo[2]), corner(hi[0], lo[1], lo[2]),
+            corner(lo[0], lo[1], hi[2]), corner(lo[0], hi[1], hi[2]), corner(hi[0], hi[1], hi[2]), corner(hi[0], lo[1], hi[2])
+        ]
+    }
+}
+
+/// Clip planes and crop box cutting into the volume, so internal structure can be seen
+/// The raymarch intersects each ray with the crop box instead of the whole grid, then with every clip plane
+/// Slices and mesh extraction ignore clipping
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clipping {
+    pub planes: Vec<ClipPlane>, // at most MAX_CLIP_PLANES
+    pub crop: CropBox,
+    pub focus: CropFace, // face moved by nudge_crop()
+}
+
+impl Clipping {
+    /// No planes, crop box around the whole grid
+    pub fn new(dims: &Dims3) -> Self {
+        Clipping {
+            planes: Vec::new(),
+            crop: CropBox::full(dims),
+            focus: CropFace::Low(0)
+        }
+    }
+
+    /// False (and nothing added) once MAX_CLIP_PLANES are set
+    pub fn add_plane(&mut self, plane: ClipPlane) -> bool {
+        if self.planes.len() >= MAX_CLIP_PLANES { return false; }
+        self.planes.push(plane);
+        true
+    }
+
+    /// Removes the most recently added plane
+    pub fn remove_plane(&mut self) -> Option<ClipPlane> {
+        self.planes.pop()
+    }
+
+    /// Moves the focused crop face outwards by delta voxels (inwards when negative), clamped to the grid
+    /// and never closer than MIN_CROP to the opposite face, returns the face's new position
+    pub fn nudge_crop(&mut self, delta: f32, dims: &Dims3) -> f32 {
+        match self.focus {
+            CropFace::Low(axis) => {
+                self.crop.min[axis] = (self.crop.min[axis] - delta).clamp(0.0, self.crop.max[axis] - MIN_CROP);
+                self.crop.min[axis]
+            },
+            CropFace::High(axis) => {
+                self.crop.max[axis] = (self.crop.max[axis] + delta).clamp(self.crop.min[axis] + MIN_CROP, dims[axis] as f32);
+                self.crop.max[axis]
+            },
+        }
+    }
+
+    /// Carries planes and crop box over to the same grid at other dims (e.g. another OmeZarr level),
+    /// voxel coordinates scale by to / from along each axis
+    pub fn rescale(&mut self, from: &Dims3, to: &Dims3) {
+        let ratio = [0, 1, 2].map(|a| to[a] as f32 / from[a] as f32);
+        for plane in self.planes.iter_mut() {
+            plane.point = [0, 1, 2].map(|a| plane.point[a] * ratio[a]);
+            plane.normal = [0, 1, 2].map(|a| plane.normal[a] / ratio[a]); // normals take the inverse transpose
+        }
+        self.crop.min = [0, 1, 2].map(|a| self.crop.min[a] * ratio[a]);
+        self.crop.max = [0, 1, 2].map(|a| (self.crop.max[a] * ratio[a]).min(to[a] as f32));
+    }
+
+    /// Uniforms.clip_planes: see ClipPlane::uniform(), unused slots keep everything
+    pub fn planes_uniform(&self) -> [[f32; 4]; MAX_CLIP_PLANES] {
+        let mut planes = [[0.0, 0.0, 0.0, 1.0]; MAX_CLIP_PLANES];
+        for (slot, plane) in planes.iter_mut().zip(self.planes.iter()) {
+            *slot = plane.uniform();
+        }
+        planes
+    }
+
+    /// Uniforms.crop_min and crop_max
+    pub fn crop_uniform(&self) -> [[f32; 4]; 2] {
+        let (lo, hi) = (self.crop.min, self.crop.max);
+        [[lo[0], lo[1], lo[2], 0.0], [hi[0], hi[1], hi[2], 0.0]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_faces_stop_at_the_grid() {
+        let dims = [10, 20, 30];
+        let mut clipping = Clipping::new(&dims);
+        clipping.focus = CropFace::Low(1);
+        assert_eq!(clipping.nudge_crop(5.0, &dims), 0.0); // already on the grid face
+        clipping.focus = CropFace::High(2);
+        assert_eq!(clipping.nudge_crop(5.0, &dims), 30.0);
+        assert_eq!(clipping.nudge_crop(-4.0, &dims), 26.0);
+        assert_eq!(clipping.crop.max, [10.0, 20.0, 26.0]);
+    }
+
+    #[test]
+    fn crop_faces_keep_min_crop_apart() {
+        let dims = [10, 20, 30];
+        let mut clipping = Clipping::new(&dims);
+        clipping.focus = CropFace::Low(0);
+        assert_eq!(clipping.nudge_crop(-100.0, &dims), 10.0 - MIN_CROP);
+        clipping.focus = clipping.focus.next();
+        assert_eq!(clipping.focus, CropFace::High(0));
+        assert_eq!(clipping.nudge_crop(-100.0, &dims), 10.0);
+        assert_eq!(clipping.crop.max[0] - clipping.crop.min[0], MIN_CROP);
+    }
+
+    #[test]
+    fn rescale_keeps_planes_and_crop_on_the_same_voxels() {
+        let (from, to) = ([64, 64, 32], [32, 16, 32]);
+        let mut clipping = Clipping::new(&from);
+        clipping.add_plane(ClipPlane { point: [32.0, 16.0, 8.0], normal: [1.0, 1.0, 0.0] });
+        clipping.crop = CropBox { min: [8.0, 0.0, 4.0], max: [64.0, 48.0, 32.0] };
+        let before = clipping.planes[0].uniform();
+        let on_plane = [40.0, 8.0, 20.0]; // dot(normal, p - point) = 0
+        assert!((before[0] * on_plane[0] + before[1] * on_plane[1] + before[2] * on_plane[2] + before[3]).abs() < 1e-4);
+
+        clipping.rescale(&from, &to);
+        assert_eq!(clipping.crop, CropBox { min: [4.0, 0.0, 4.0], max: [32.0, 12.0, 32.0] });
+        let after = clipping.planes[0].uniform();
+        let scaled = [20.0, 2.0, 20.0];
+        assert!((after[0] * scaled[0] + after[1] * scaled[1] + after[2] * scaled[2] + after[3]).abs() < 1e-4);
+        let inside = [30.0, 15.0, 0.0]; // kept before and after
+        assert!(after[0] * inside[0] + after[1] * inside[1] + after[2] * inside[2] + after[3] > 0.0);
+    }
+}
@@ -0,0 +1,473 @@
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rayon::prelude::*;
+use crate::world::{reaction::{ReactionDiffusion, MAX_SPECIES}, voxel_grid::{Dims3, P3}};
+
+/// Concentration used when cycling an axis onto Dirichlet at runtime (see State::handle_key)
+pub const DIRICHLET_DEFAULT: f32 = 1.0;
+
+/// What the stencil sees one voxel beyond a face of the grid (the halo cell)
+/// Neumann: zero flux, halo mirrors the boundary voxel
+/// Dirichlet: concentration held at the given value on the face itself (halo = 2 * value - boundary voxel)
+/// Periodic: halo is the voxel on the opposite face, so the axis wraps around
+/// Absorbing: empty sink outside the grid, halo = 0 so mass drains through the face
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BoundaryCondition {
+    Neumann,
+    Dirichlet(f32),
+    Periodic,
+    Absorbing,
+}
+
+impl BoundaryCondition {
+    /// Code packed into Uniforms.flags, must match the consts in laplacian.wgsl
+    pub fn code(&self) -> u32 {
+        match self {
+            BoundaryCondition::Neumann => 0,
+            BoundaryCondition::Dirichlet(_) => 1,
+            BoundaryCondition::Periodic => 2,
+            BoundaryCondition::Absorbing => 3,
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        match self {
+            BoundaryCondition::Dirichlet(v) => *v,
+            _ => 0.0
+        }
+    }
+
+    /// Halo value given the boundary voxel c_i and the voxel on the opposite face
+    pub fn ghost(&self, c_i: f32, wrapped: f32) -> f32 {
+        match self {
+            BoundaryCondition::Neumann => c_i,
+            BoundaryCondition::Dirichlet(v) => (2.0 * v) - c_i,
+            BoundaryCondition::Periodic => wrapped,
+            BoundaryCondition::Absorbing => 0.0,
+        }
+    }
+}
+
+/// Boundary conditions for all six faces
+/// faces[axis][0] is the low (index 0) face, faces[axis][1] the high (index dims - 1) face
+/// Periodic always applies to both faces of an axis
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Boundaries {
+    pub faces: [[BoundaryCondition; 2]; 3],
+}
+
+impl Default for Boundaries {
+    fn default() -> Self {
+        Boundaries { faces: [[BoundaryCondition::Neumann; 2]; 3] }
+    }
+}
+
+impl Boundaries {
+    pub fn set_axis(&mut self, axis: usize, bc: BoundaryCondition) {
+        assert!(axis < 3);
+        self.faces[axis] = [bc; 2];
+    }
+
+    /// high == false for the low face
+    pub fn set_face(&mut self, axis: usize, high: bool, bc: BoundaryCondition) {
+        assert!(axis < 3);
+        if bc == BoundaryCondition::Periodic || self.faces[axis][!high as usize] == BoundaryCondition::Periodic {
+            // a lone periodic face has nothing to wrap onto
+            self.faces[axis] = [bc; 2];
+        }
+        else { self.faces[axis][high as usize] = bc; }
+    }
+
+    /// Neumann -> Periodic -> Dirichlet -> Absorbing -> Neumann, applied to both faces of the axis
+    pub fn cycle_axis(&mut self, axis: usize) -> BoundaryCondition {
+        assert!(axis < 3);
+        let next = match self.faces[axis][0] {
+            BoundaryCondition::Neumann => BoundaryCondition::Periodic,
+            BoundaryCondition::Periodic => BoundaryCondition::Dirichlet(DIRICHLET_DEFAULT),
+            BoundaryCondition::Dirichlet(_) => BoundaryCondition::Absorbing,
+            BoundaryCondition::Absorbing => BoundaryCondition::Neumann,
+        };
+        self.set_axis(axis, next);
+        next
+    }
+
+    /// Uniforms.flags[1..4]: per axis, low face code in bits 0-7, high face code in bits 8-15
+    pub fn flags(&self) -> [u32; 3] {
+        let pack = |a: usize| self.faces[a][0].code() | (self.faces[a][1].code() << 8);
+        [pack(0), pack(1), pack(2)]
+    }
+
+    /// Dirichlet values per axis for the low ([0]) and high ([1]) faces, padded to vec4
+    pub fn values(&self) -> [[f32; 4]; 2] {
+        let face = |h: usize| [self.faces[0][h].value(), self.faces[1][h].value(), self.faces[2][h].value(), 0.0];
+        [face(0), face(1)]
+    }
+}
+
+/// Fraction of the explicit Euler stability limit each substep is allowed to use
+pub const CFL_SAFETY: f32 = 0.9;
+
+/// How much simulated time a rendered frame advances
+/// Fixed: the same dt every frame, independent of the display's frame rate (reproducible runs)
+/// Adaptive: the wall-clock frame duration, i.e. the simulation runs in real time
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TimeStep {
+    Fixed(f32),
+    Adaptive,
+}
+
+/// Physical parameters of the diffusion step, uploaded through Uniforms
+/// Either dt is split into substeps that each respect the CFL limit (see substeps())
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SimulationParams {
+    pub diffusion: f32, // D, single species (reaction.model == ReactionModel::None)
+    pub spacing: P3, // voxel edge length along x, y, z (same length units as D)
+    pub timestep: TimeStep,
+    pub boundaries: Boundaries,
+    pub reaction: ReactionDiffusion, // species count, per-species D and reaction term
+}
+
+impl Default for SimulationParams {
+    /// D = 1.0, unit voxels, real time: the parameters laplacian.wgsl used to hard-code
+    fn default() -> Self {
+        SimulationParams {
+            diffusion: 1.0,
+            spacing: [1.0, 1.0, 1.0],
+            timestep: TimeStep::Adaptive,
+            boundaries: Boundaries::default(),
+            reaction: ReactionDiffusion::default(),
+        }
+    }
+}
+
+impl SimulationParams {
+    /// [1/dx^2, 1/dy^2, 1/dz^2]
+    pub fn inv_spacing_sq(&self) -> P3 {
+        [1.0 / (self.spacing[0] * self.spacing[0]),
+        1.0 / (self.spacing[1] * self.spacing[1]),
+        1.0 / (self.spacing[2] * self.spacing[2])]
+    }
+
+    /// D of the fastest diffusing species
+    pub fn max_diffusion(&self) -> f32 {
+        if self.reaction.is_active() { self.reaction.max_diffusion() } else { self.diffusion }
+    }
+
+    /// Largest stable explicit Euler dt for the 7 point stencil: 1 / (2D * (1/dx^2 + 1/dy^2 + 1/dz^2))
+    /// using the fastest diffusing species (reaction terms are assumed non-stiff)
+    pub fn cfl_limit(&self) -> f32 {
+        assert!(self.max_diffusion() >= 0.0 && self.spacing.iter().all(|s| *s > 0.0));
+        let [ix, iy, iz] = self.inv_spacing_sq();
+        1.0 / (2.0 * self.max_diffusion() * (ix + iy + iz)) // infinite when nothing diffuses
+    }
+
+    pub fn is_stable(&self, dt: f32) -> bool {
+        dt <= self.cfl_limit()
+    }
+
+    /// Simulated time for a frame that took wall_dt seconds
+    pub fn frame_dt(&self, wall_dt: f32) -> f32 {
+        match self.timestep {
+            TimeStep::Fixed(dt) => dt,
+            TimeStep::Adaptive => wall_dt,
+        }
+    }
+
+    /// Splits frame_dt into (count, dt) substeps, each at most CFL_SAFETY * cfl_limit()
+    pub fn substeps(&self, frame_dt: f32) -> (u32, f32) {
+        if frame_dt <= 0.0 { return (0, 0.0); }
+        let max_dt = CFL_SAFETY * self.cfl_limit();
+        let count = (frame_dt / max_dt).ceil().clamp(1.0, u32::MAX as f32) as u32;
+        (count, frame_dt / count as f32)
+    }
+
+    /// Uniforms.diffusion: [D, 1/dx^2, 1/dy^2, 1/dz^2]
+    pub fn uniform(&self) -> [f32; 4] {
+        let [ix, iy, iz] = self.inv_spacing_sq();
+        [self.diffusion, ix, iy, iz]
+    }
+}
+
+/// CPU reference for the laplacian.wgsl diffusion step
+/// Same flattening (x + y*dims[0] + z*dims[0]*dims[1]), same halo handling
+/// (see BoundaryCondition, Neumann by default) and same explicit Euler update,
+/// so GPU output can be cross-checked against it within a tolerance
+/// Steps every species of params.reaction, adding the reaction term as reaction_diffusion.wgsl does
+/// Also serves as the Compute backend on machines without a usable adapter (see compute::ComputeBackend)
+pub struct CpuDiffusion {
+    pub dims: Dims3,
+    pub params: SimulationParams,
+    ping: Vec<f32>,
+    pong: Vec<f32>,
+    read_ping: bool, // true: ping holds the current field
+}
+
+impl CpuDiffusion {
+    /// Zero-filled field
+    pub fn new(dims: Dims3) -> Self {
+        assert!(dims[0] > 0 && dims[1] > 0 && dims[2] > 0);
+        let len = (dims[0] * dims[1] * dims[2]) as usize;
+        CpuDiffusion {
+            dims: dims,
+            params: SimulationParams::default(),
+            ping: vec![0.0; len],
+            pong: vec![0.0; len],
+            read_ping: true
+        }
+    }
+
+    /// Takes ownership of an existing single species field, e.g. one read back from the GPU
+    pub fn from_field(dims: Dims3, field: Vec<f32>) -> Self {
+        assert_eq!(field.len(), (dims[0] * dims[1] * dims[2]) as usize);
+        let len = field.len();
+        CpuDiffusion {
+            dims: dims,
+            params: SimulationParams::default(),
+            ping: field,
+            pong: vec![0.0; len],
+            read_ping: true
+        }
+    }
+
+    /// Adopts new parameters, reallocating (zero-filled) when the species count changes
+    pub fn set_params(&mut self, params: SimulationParams) {
+        if params.reaction.species != self.params.reaction.species {
+            let len = (self.dims[0] * self.dims[1] * self.dims[2]) as usize * params.reaction.species;
+            self.ping = vec![0.0; len];
+            self.pong = vec![0.0; len];
+            self.read_ping = true;
+        }
+        self.params = params;
+    }
+
+    /// Replaces the current field, species-major (see ReactionDiffusion::initial_state())
+    pub fn load(&mut self, field: &[f32]) {
+        let current = if self.read_ping { &mut self.ping } else { &mut self.pong };
+        assert_eq!(field.len(), current.len());
+        current.copy_from_slice(field);
+    }
+
+    /// Uniform [0, 1) noise, the CPU counterpart of init.wgsl
+    /// Not bit-identical to the GPU init (sin() precision differs between devices),
+    /// so cross-checks should start both solvers from the same uploaded field
+    pub fn init_random(&mut self, seed: u32) {
+        let mut rng = StdRng::seed_from_u64(seed as u64);
+        let current = if self.read_ping { &mut self.ping } else { &mut self.pong };
+        for v in current.iter_mut() {
+            *v = rng.random::<f32>();
+        }
+    }
+
+    /// Field as of the last step
+    pub fn current(&self) -> &[f32] {
+        if self.read_ping { &self.ping } else { &self.pong }
+    }
+
+    /// One explicit Euler step of dt (callers keep dt within params.cfl_limit()), then swaps ping and pong
+    pub fn step(&mut self, dt: f32) {
+        if self.read_ping {
+            reaction_diffusion_step(&self.ping, &mut self.pong, &self.dims, &self.params, dt);
+        }
+        else {
+            reaction_diffusion_step(&self.pong, &mut self.ping, &self.dims, &self.params, dt);
+        }
+        self.read_ping = !self.read_ping;
+    }
+
+    /// Largest absolute per-voxel difference against another field of the same dims (e.g. GPU output)
+    pub fn max_abs_diff(&self, other: &[f32]) -> f32 {
+        assert_eq!(other.len(), self.current().len());
+        self.current().par_iter()
+            .zip(other.par_iter())
+            .map(|(a, b)| (a - b).abs())
+            .reduce(|| 0.0, f32::max)
+    }
+
+    pub fn matches(&self, other: &[f32], tolerance: f32) -> bool {
+        self.max_abs_diff(other) <= tolerance
+    }
+}
+
+/// Species-major src advanced by dt into dst: each species diffuses with its own D (params.diffusion for all of them
+/// without a reaction model), then the reaction term is added per voxel (explicit Euler on both)
+pub fn reaction_diffusion_step(src: &[f32], dst: &mut [f32], dims: &Dims3, params: &SimulationParams, dt: f32) {
+    let voxels = (dims[0] * dims[1] * dims[2]) as usize;
+    let rd = &params.reaction;
+    assert!(src.len() == voxels * rd.species && dst.len() == src.len());
+
+    for s in 0..rd.species {
+        let species_params = SimulationParams { diffusion: if rd.is_active() { rd.diffusion[s] } else { params.diffusion }, ..*params };
+        laplacian_step(&src[s * voxels..(s + 1) * voxels], &mut dst[s * voxels..(s + 1) * voxels], dims, &species_params, dt);
+    }
+    if !rd.is_active() { return; }
+
+    for (s, dst_s) in dst.chunks_mut(voxels).enumerate() {
+        dst_s.par_iter_mut().enumerate().for_each(|(idx, out)| {
+            let mut c = [0.0; MAX_SPECIES];
+            for t in 0..rd.species { c[t] = src[(t * voxels) + idx]; }
+            *out += dt * rd.model.rates(&c)[s];
+        });
+    }
+}
+
+/// Writes src advanced by dt into dst (one species)
+/// D and per-axis spacing from params, as in laplacian.wgsl
+/// Parallel over z slices
+pub fn laplacian_step(src: &[f32], dst: &mut [f32], dims: &Dims3, params: &SimulationParams, dt: f32) {
+    let [nx, ny, nz] = [dims[0] as usize, dims[1] as usize, dims[2] as usize];
+    let y_stride = nx;
+    let z_stride = nx * ny; // dims[3] Rust-side
+    assert!(src.len() == z_stride * nz && dst.len() == src.len());
+    let [ix, iy, iz] = params.inv_spacing_sq();
+    let d = params.diffusion;
+
+    dst.par_chunks_mut(z_stride).enumerate().for_each(|(z, slice)| {
+        for y in 0..ny {
+            for x in 0..nx {
+                let idx = x + (y * y_stride) + (z * z_stride);
+                let c_i = src[idx];
+
+                // a missing neighbour is the halo value of that face, wrapped indices only matter for Periodic
+                let [bx, by, bz] = &params.boundaries.faces;
+                let c_i_xmin = if x > 0 { src[idx - 1] } else { bx[0].ghost(c_i, src[idx + nx - 1]) };
+                let c_i_xplus = if x + 1 < nx { src[idx + 1] } else { bx[1].ghost(c_i, src[idx + 1 - nx]) };
+                let c_i_ymin = if y > 0 { src[idx - y_stride] } else { by[0].ghost(c_i, src[idx + (ny - 1) * y_stride]) };
+                let c_i_yplus = if y + 1 < ny { src[idx + y_stride] } else { by[1].ghost(c_i, src[idx - (ny - 1) * y_stride]) };
+                let c_i_zmin = if z > 0 { src[idx - z_stride] } else { bz[0].ghost(c_i, src[idx + (nz - 1) * z_stride]) };
+                let c_i_zplus = if z + 1 < nz { src[idx + z_stride] } else { bz[1].ghost(c_i, src[idx - (nz - 1) * z_stride]) };
+
+                let laplacian = ((c_i_xmin + c_i_xplus - (2.0 * c_i)) * ix)
+                    + ((c_i_ymin + c_i_yplus - (2.0 * c_i)) * iy)
+                    + ((c_i_zmin + c_i_zplus - (2.0 * c_i)) * iz);
+                slice[x + (y * y_stride)] = c_i + (d * dt * laplacian);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::reaction::ReactionDiffusion;
+
+    #[test]
+    fn neumann_conserves_mass() {
+        let dims = [6, 5, 4];
+        let mut cpu = CpuDiffusion::new(dims);
+        cpu.init_random(7);
+        let params = SimulationParams { spacing: [1.0, 0.5, 2.0], ..SimulationParams::default() };
+        cpu.set_params(params);
+        let before: f64 = cpu.current().iter().map(|&v| v as f64).sum();
+        for _ in 0..20 { cpu.step(CFL_SAFETY * params.cfl_limit()); }
+        let after: f64 = cpu.current().iter().map(|&v| v as f64).sum();
+        assert!((before - after).abs() < 1e-3, "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn periodic_wraps_around() {
+        // an impulse on the low x face reaches the high x face in one step, which Neumann keeps out
+        let dims = [5, 3, 3];
+        let dt = 0.1;
+        let at = |x: usize| x + 5 + 15; // y = 1, z = 1
+        let mut src = vec![0.0; 45];
+        src[at(0)] = 1.0;
+        let mut dst = vec![0.0; 45];
+
+        let mut params = SimulationParams::default();
+        params.boundaries.set_axis(0, BoundaryCondition::Periodic);
+        laplacian_step(&src, &mut dst, &dims, &params, dt);
+        assert!((dst[at(4)] - params.diffusion * dt).abs() < 1e-6);
+        assert!((dst[at(1)] - dst[at(4)]).abs() < 1e-6);
+        let total: f32 = dst.iter().sum();
+        assert!((total - 1.0).abs() < 1e-5);
+
+        laplacian_step(&src, &mut dst, &dims, &SimulationParams::default(), dt);
+        assert_eq!(dst[at(4)], 0.0);
+    }
+
+    #[test]
+    fn dirichlet_settles_at_its_value() {
+        let dims = [4, 4, 4];
+        let mut cpu = CpuDiffusion::new(dims);
+        let mut params = SimulationParams::default();
+        for axis in 0..3 { params.boundaries.set_axis(axis, BoundaryCondition::Dirichlet(2.0)); }
+        cpu.set_params(params);
+        for _ in 0..500 { cpu.step(CFL_SAFETY * params.cfl_limit()); }
+        for v in cpu.current() { assert!((v - 2.0).abs() < 1e-3, "{}", v); }
+    }
+
+    #[test]
+    fn absorbing_face_loses_mass() {
+        let dims = [4, 4, 4];
+        let mut cpu = CpuDiffusion::new(dims);
+        let mut params = SimulationParams::default();
+        params.boundaries.set_face(2, true, BoundaryCondition::Absorbing);
+        cpu.set_params(params);
+        cpu.load(&[1.0; 64]);
+        let mut before = 64.0; // MUT
+        for _ in 0..20 {
+            cpu.step(CFL_SAFETY * params.cfl_limit());
+            let after: f32 = cpu.current().iter().sum();
+            assert!(after < before, "{} -> {}", before, after);
+            before = after;
+        }
+        // only the absorbing face drains, the opposite face is still the fullest
+        let z_sum = |z: usize| -> f32 { cpu.current()[z * 16..(z + 1) * 16].iter().sum() };
+        assert!(z_sum(0) > z_sum(3));
+    }
+
+    #[test]
+    fn impulse_spreads_to_neighbours() {
+        // one explicit Euler step of a unit impulse: centre loses 2D dt per axis, each neighbour gains D dt / dx^2
+        let dims = [5, 5, 5];
+        let params = SimulationParams { diffusion: 1.0, spacing: [1.0, 1.0, 2.0], ..SimulationParams::default() };
+        let dt = 0.1;
+        let centre = 2 + (2 * 5) + (2 * 25);
+        let mut src = vec![0.0; 125];
+        src[centre] = 1.0;
+        let mut dst = vec![0.0; 125];
+        laplacian_step(&src, &mut dst, &dims, &params, dt);
+
+        assert!((dst[centre] - (1.0 - dt * (2.0 + 2.0 + 0.5))).abs() < 1e-6);
+        assert!((dst[centre + 1] - dt).abs() < 1e-6);
+        assert!((dst[centre - 5] - dt).abs() < 1e-6);
+        assert!((dst[centre + 25] - dt * 0.25).abs() < 1e-6);
+        assert_eq!(dst[0], 0.0);
+    }
+
+    #[test]
+    fn uniform_field_only_reacts() {
+        // no gradients, so each voxel takes exactly one Euler step of the reaction term
+        let dims = [3, 3, 3];
+        let reaction = ReactionDiffusion::gray_scott();
+        let params = SimulationParams { reaction: reaction, ..SimulationParams::default() };
+        let c = [0.8, 0.3, 0.0, 0.0];
+        let src: Vec<f32> = (0..reaction.species).flat_map(|s| vec![c[s]; 27]).collect();
+        let mut dst = vec![0.0; src.len()];
+        let dt = 0.5;
+        reaction_diffusion_step(&src, &mut dst, &dims, &params, dt);
+
+        let rates = reaction.model.rates(&c);
+        for s in 0..reaction.species {
+            for v in &dst[s * 27..(s + 1) * 27] { assert!((v - (c[s] + dt * rates[s])).abs() < 1e-6); }
+        }
+    }
+
+    #[test]
+    fn species_without_a_model_each_diffuse() {
+        // e.g. a second channel made simulated under ReactionModel::None: every block diffuses with params.diffusion
+        let dims = [4, 3, 5];
+        let params = SimulationParams { diffusion: 0.5, reaction: ReactionDiffusion { species: 2, ..ReactionDiffusion::default() }, ..SimulationParams::default() };
+        let mut cpu = CpuDiffusion::new(dims);
+        cpu.set_params(params);
+        let field: Vec<f32> = (0..120).map(|i| ((i * 7) % 11) as f32).collect();
+        cpu.load(&field);
+        let dt = CFL_SAFETY * params.cfl_limit();
+        cpu.step(dt);
+
+        for s in 0..2 {
+            let mut expected = vec![0.0; 60];
+            laplacian_step(&field[s * 60..(s + 1) * 60], &mut expected, &dims, &params, dt);
+            assert_eq!(&cpu.current()[s * 60..(s + 1) * 60], &expected[..]);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::{collections::BTreeMap, error::Error};
+use crate::world::channels::ChannelType;
+
+/// Id of unlabelled voxels, never drawn
+pub const BACKGROUND: u32 = 0;
+
+/// One entry of a LabelTable, e.g. a cell or region of a segmentation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub name: String,
+    pub colour: [f32; 3], // sRGB
+    pub visible: bool,
+}
+
+impl Label {
+    /// Named label_<id> with a colour spread around the hue circle by id, so neighbouring ids differ
+    pub fn new(id: u32) -> Self {
+        Label {
+            name: format!("label_{}", id),
+            colour: Label::colour_of(id),
+            visible: true
+        }
+    }
+
+    /// Golden angle steps of hue at full saturation and value
+    fn colour_of(id: u32) -> [f32; 3] {
+        let h = ((id as f32 * 0.618_034) % 1.0) * 6.0;
+        let x = 1.0 - ((h % 2.0) - 1.0).abs();
+        match h as u32 {
+            0 => [1.0, x, 0.0],
+            1 => [x, 1.0, 0.0],
+            2 => [0.0, 1.0, x],
+            3 => [0.0, x, 1.0],
+            4 => [x, 0.0, 1.0],
+            _ => [1.0, 0.0, x],
+        }
+    }
+}
+
+/// Labels of a LabelVolume by id, each drawn at the table's opacity while visible
+/// The raymarch reads labels by slot rather than id: slot 0 is BACKGROUND, then one per label in id order
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelTable {
+    labels: BTreeMap<u32, Label>,
+    pub opacity: f32, // 1 draws opaque shaded regions, below 1 semi-transparent ones
+}
+
+impl LabelTable {
+    /// Default Label for every id but BACKGROUND, opaque
+    pub fn from_ids(ids: impl IntoIterator<Item = u32>) -> Self {
+        let labels = ids.into_iter().filter(|&id| id != BACKGROUND).map(|id| (id, Label::new(id))).collect();
+        LabelTable { labels: labels, opacity: 1.0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// True for a label volume holding only BACKGROUND
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, u32, Label> {
+        self.labels.iter()
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Label> {
+        self.labels.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Label> {
+        self.labels.get_mut(&id)
+    }
+
+    /// Adds or replaces a label, BACKGROUND can't be labelled
+    pub fn insert(&mut self, id: u32, label: Label) -> Result<(), Box<dyn Error>> {
+        if id == BACKGROUND {
+            return Err(format!("Label {} is reserved for the background", BACKGROUND).into());
+        }
+        self.labels.insert(id, label);
+        Ok(())
+    }
+
+    /// Slot the raymarch reads id by, BACKGROUND (0) for ids not in the table
+    pub fn slot(&self, id: u32) -> u32 {
+        if !self.labels.contains_key(&id) { return 0; }
+        self.labels.range(..id).count() as u32 + 1
+    }
+
+    /// [r, g, b, opacity] per slot, transparent for the background and hidden labels
+    pub fn colours(&self) -> Vec<[f32; 4]> {
+        let mut colours = vec![[0.0; 4]];
+        colours.extend(self.labels.values().map(|l| {
+            let alpha = if l.visible { self.opacity.clamp(0.0, 1.0) } else { 0.0 };
+            [l.colour[0], l.colour[1], l.colour[2], alpha]
+        }));
+        colours
+    }
+}
+
+/// Integer labels on the VoxelGrid's voxels, e.g. cell or region ids from a segmentation
+/// Drawn over the scalar render as coloured regions, sampled at the nearest voxel so labels never blend across boundaries
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelVolume {
+    pub name: String,
+    pub dtype: ChannelType, // U16 or U32
+    pub ids: Vec<u32>, // one per voxel, x fastest as the voxel buffers
+    pub table: LabelTable,
+}
+
+impl LabelVolume {
+    /// Label volume with a default table entry for every id present
+    /// voxels: the grid's voxel count, checked against ids
+    pub fn new(name: &str, dtype: ChannelType, ids: Vec<u32>, voxels: usize) -> Result<Self, Box<dyn Error>> {
+        match dtype {
+            ChannelType::U16 => if let Some(id) = ids.iter().find(|&&id| id > u16::MAX as u32) {
+                return Err(format!("Label {} doesn't fit in u16", id).into());
+            },
+            ChannelType::U32 => {},
+            _ => return Err(format!("Labels are U16 or U32, not {:?}", dtype).into()),
+        }
+        if ids.len() != voxels {
+            return Err(format!("Label volume {} has {} voxels, expected {}", name, ids.len(), voxels).into());
+        }
+        let mut present: Vec<u32> = ids.clone();
+        present.sort_unstable();
+        present.dedup();
+        Ok(LabelVolume {
+            name: name.to_string(),
+            dtype: dtype,
+            ids: ids,
+            table: LabelTable::from_ids(present)
+        })
+    }
+
+    /// u16 labels, as most segmentation tools save them
+    pub fn from_u16(name: &str, ids: &[u16], voxels: usize) -> Result<Self, Box<dyn Error>> {
+        LabelVolume::new(name, ChannelType::U16, ids.iter().map(|&id| id as u32).collect(), voxels)
+    }
+
+    /// Every voxel's table slot (see LabelTable::slot()), as the raymarch reads them
+    pub fn slots(&self) -> Vec<u32> {
+        let slots: BTreeMap<u32, u32> = self.table.iter().enumerate().map(|(i, (&id, _))| (id, i as u32 + 1)).collect();
+        self.ids.iter().map(|id| slots.get(id).copied().unwrap_or(0)).collect()
+    }
+
+    /// Uniforms.label_flags: [1 (loaded), slot count, 0, 0]
+    pub fn uniform(&self) -> [u32; 4] {
+        [1, self.table.len() as u32 + 1, 0, 0]
+    }
+
+    /// Label of voxel idx, None for the background and ids missing from the table
+    pub fn label_at(&self, idx: usize) -> Option<&Label> {
+        self.ids.get(idx).and_then(|&id| self.table.get(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_follow_id_order() {
+        let ids = vec![0, 70, 3, 3, 900, 0, 70, 12];
+        let volume = LabelVolume::new("cells", ChannelType::U32, ids.clone(), ids.len()).unwrap();
+        assert_eq!(volume.table.len(), 4); // background isn't a label
+        assert_eq!(volume.slots(), ids.iter().map(|&id| volume.table.slot(id)).collect::<Vec<_>>());
+        assert_eq!(volume.slots(), [0, 3, 1, 1, 4, 0, 3, 2]);
+        assert_eq!(volume.table.slot(5), 0); // not in the table
+        assert_eq!(volume.uniform(), [1, 5, 0, 0]);
+        assert_eq!(volume.table.colours().len(), 5);
+    }
+
+    #[test]
+    fn inserted_labels_shift_later_slots() {
+        let mut volume = LabelVolume::from_u16("cells", &[4, 8, 8], 3).unwrap();
+        volume.table.insert(6, Label::new(6)).unwrap();
+        assert_eq!(volume.slots(), [1, 3, 3]);
+        assert_eq!(volume.table.slot(6), 2);
+        assert!(volume.table.insert(BACKGROUND, Label::new(BACKGROUND)).is_err());
+    }
+
+    #[test]
+    fn u16_labels_must_fit() {
+        assert!(LabelVolume::new("cells", ChannelType::U16, vec![1, 65536], 2).is_err());
+        assert!(LabelVolume::new("cells", ChannelType::U16, vec![1, 65535], 2).is_ok());
+        assert!(LabelVolume::new("cells", ChannelType::U32, vec![1, 65536], 2).is_ok());
+        assert!(LabelVolume::new("cells", ChannelType::F32, vec![1, 2], 2).is_err());
+        assert!(LabelVolume::new("cells", ChannelType::U32, vec![1, 2], 3).is_err());
+    }
+
+    #[test]
+    fn hidden_labels_are_transparent() {
+        let mut table = LabelTable::from_ids([2, 5, 9]);
+        table.opacity = 0.4;
+        table.get_mut(5).unwrap().visible = false;
+        let colours = table.colours();
+        assert_eq!(colours[0], [0.0; 4]); // background
+        assert_eq!(colours[table.slot(2) as usize][3], 0.4);
+        assert_eq!(colours[table.slot(5) as usize][3], 0.0);
+        assert_eq!(colours[table.slot(9) as usize][3], 0.4);
+        assert_eq!(&colours[table.slot(9) as usize][..3], &table.get(9).unwrap().colour);
+    }
+}
@@ -0,0 +1,496 @@
+use std::{collections::HashMap, error::Error, io::Write, path::Path, sync::OnceLock};
+use crate::world::voxel_grid::{Dims3, P3, VoxelTransform};
+
+/// File formats Mesh::save() writes, picked from the path's extension
+/// Obj: text, positions and normals only (OBJ has no slot for per-vertex scalars)
+/// Ply: binary little endian, scalars become extra float vertex properties
+/// Gltf: binary glTF (.glb), scalars become custom "_NAME" vertex attributes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MeshFormat {
+    Obj,
+    Ply,
+    Gltf,
+}
+
+impl MeshFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "obj" => Some(MeshFormat::Obj),
+            "ply" => Some(MeshFormat::Ply),
+            "glb" | "gltf" => Some(MeshFormat::Gltf),
+            _ => None,
+        }
+    }
+}
+
+/// Named per-vertex value carried into PLY and glTF exports (e.g. another species at each vertex)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalarAttribute {
+    pub name: String,
+    pub values: Vec<f32>, // one per vertex
+}
+
+/// Indexed triangle mesh in voxel space (voxel centres at integer coordinates, as the voxel buffers index them) until to_world()
+/// Triangles wind counter-clockwise seen from the side the normals point to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<P3>,
+    pub normals: Vec<P3>, // unit length, towards lower field values (out of the region above the iso value)
+    pub indices: Vec<u32>, // three per triangle
+    pub scalars: Vec<ScalarAttribute>,
+}
+
+// CUBE CONVENTIONS //
+// corner c sits at (c & 1, (c >> 1) & 1, (c >> 2) & 1) from the cube's lowest voxel
+// edge (axis * 4) + k joins the k-th corner without the axis bit to that corner plus the axis bit
+fn edge_corners(edge: usize) -> (usize, usize) {
+    let axis = edge / 4;
+    let low_bits = edge % 4; // remaining two bits, in order, with the axis bit left out
+    let mut c = 0; // MUT
+    let mut bit = 0; // MUT
+    for b in 0..3 {
+        if b == axis { continue; }
+        if low_bits & (1 << bit) != 0 { c |= 1 << b; }
+        bit += 1;
+    }
+    (c, c | (1 << axis))
+}
+
+fn edge_between(c0: usize, c1: usize) -> usize {
+    (0..12).find(|&e| { let (a, b) = edge_corners(e); (a, b) == (c0, c1) || (a, b) == (c1, c0) }).unwrap()
+}
+
+/// True when both edges lie on one face of the cube
+fn share_face(e0: usize, e1: usize) -> bool {
+    let (a, b) = edge_corners(e0);
+    let (c, d) = edge_corners(e1);
+    (0..3).any(|axis| [b, c, d].iter().all(|&k| (k >> axis) & 1 == (a >> axis) & 1))
+}
+
+/// Loop of cube edges the surface crosses, triangulated as a fan from edges[0]
+/// centre: no fan start keeps every diagonal off the cube's faces, so fan from an added centre vertex instead
+/// (a diagonal lying on a face would overlap the neighbouring cube's triangles)
+struct Polygon {
+    edges: Vec<u8>,
+    centre: bool,
+}
+
+/// Polygons for each of the 256 inside/outside corner configurations
+/// Built once instead of the usual hand-written triangle table: on each cube face the contour cuts off every
+/// inside corner separately, so ambiguous faces are resolved the same way by both cubes sharing them and the
+/// surface stays watertight. Segments run from the edge where the face's outward counter-clockwise boundary
+/// leaves the inside region to the edge where it enters, so reversed loops wind counter-clockwise seen from outside
+fn polygon_table() -> &'static Vec<Vec<Polygon>> {
+    static TABLE: OnceLock<Vec<Vec<Polygon>>> = OnceLock::new();
+    TABLE.get_or_init(|| (0..256).map(|config| {
+        let inside = |c: usize| config & (1 << c) != 0;
+        let mut next = [usize::MAX; 12]; // MUT, directed segments edge -> edge
+
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for side in 0..2 {
+                // counter-clockwise about +axis, reversed on the low face so it's counter-clockwise seen from outside
+                let mut ring: Vec<usize> = [(0, 0), (1, 0), (1, 1), (0, 1)].iter()
+                    .map(|&(a, b)| (side << axis) | (a << u) | (b << v))
+                    .collect();
+                if side == 0 { ring.reverse(); }
+
+                for i in 0..4 {
+                    let (c, succ) = (ring[i], ring[(i + 1) % 4]);
+                    if inside(c) && !inside(succ) {
+                        // walk back through the inside run to where the boundary entered it
+                        let mut j = i; // MUT
+                        while inside(ring[(j + 3) % 4]) { j = (j + 3) % 4; }
+                        let entry = edge_between(ring[(j + 3) % 4], ring[j]);
+                        next[edge_between(c, succ)] = entry;
+                    }
+                }
+            }
+        }
+
+        // every crossed edge has one outgoing and one incoming segment, so they form closed loops
+        let mut loops = Vec::new();
+        let mut visited = [false; 12];
+        for start in 0..12 {
+            if next[start] == usize::MAX || visited[start] { continue; }
+            let mut polygon = Vec::new();
+            let mut e = start; // MUT
+            while !visited[e] {
+                visited[e] = true;
+                polygon.push(e as u8);
+                e = next[e];
+            }
+            polygon.reverse();
+
+            let n = polygon.len();
+            let fan_start = (0..n).find(|&r| (2..n - 1).all(|i| !share_face(polygon[r] as usize, polygon[(r + i) % n] as usize)));
+            match fan_start {
+                Some(r) => {
+                    polygon.rotate_left(r);
+                    loops.push(Polygon { edges: polygon, centre: false });
+                },
+                None => loops.push(Polygon { edges: polygon, centre: true }),
+            }
+        }
+        loops
+    }).collect())
+}
+
+/// Central difference gradient at a grid point, one sided on the grid's faces
+fn grid_gradient(field: &[f32], dims: &Dims3, p: [u32; 3]) -> P3 {
+    let idx = |q: [u32; 3]| (q[0] + (q[1] * dims[0]) + (q[2] * dims[0] * dims[1])) as usize;
+    let mut g = [0.0; 3];
+    for axis in 0..3 {
+        let (mut lo, mut hi) = (p, p);
+        lo[axis] = p[axis].saturating_sub(1);
+        hi[axis] = (p[axis] + 1).min(dims[axis] - 1);
+        let span = (hi[axis] - lo[axis]).max(1) as f32;
+        g[axis] = (field[idx(hi)] - field[idx(lo)]) / span;
+    }
+    g
+}
+
+fn normalised(v: P3) -> P3 {
+    let len = ((v[0] * v[0]) + (v[1] * v[1]) + (v[2] * v[2])).sqrt();
+    if len > f32::EPSILON { [v[0] / len, v[1] / len, v[2] / len] } else { [0.0, 0.0, 0.0] }
+}
+
+impl Mesh {
+    /// Marching cubes over one species' field (dims[0] * dims[1] * dims[2] values, x fastest)
+    /// Vertices sit where the field crosses iso along each grid edge and are shared between neighbouring cubes
+    /// Normals come from central difference gradients interpolated along the same edges
+    pub fn marching_cubes(field: &[f32], dims: &Dims3, iso: f32) -> Self {
+        assert_eq!(field.len(), (dims[0] * dims[1] * dims[2]) as usize);
+        let table = polygon_table();
+        let idx = |x: u32, y: u32, z: u32| (x + (y * dims[0]) + (z * dims[0] * dims[1])) as usize;
+
+        let mut mesh = Mesh::default();
+        let mut vertex_of: HashMap<(usize, usize), u32> = HashMap::new(); // (lowest voxel index, axis) -> vertex
+
+        for z in 0..dims[2].saturating_sub(1) {
+            for y in 0..dims[1].saturating_sub(1) {
+                for x in 0..dims[0].saturating_sub(1) {
+                    let corner = |c: usize| [x + (c & 1) as u32, y + ((c >> 1) & 1) as u32, z + ((c >> 2) & 1) as u32];
+                    let mut config = 0; // MUT
+                    for c in 0..8 {
+                        let [cx, cy, cz] = corner(c);
+                        if field[idx(cx, cy, cz)] >= iso { config |= 1 << c; }
+                    }
+                    if config == 0 || config == 255 { continue; }
+
+                    for polygon in table[config].iter() {
+                        let ring: Vec<u32> = polygon.edges.iter().map(|&e| {
+                            let (c0, c1) = edge_corners(e as usize);
+                            let (p0, p1) = (corner(c0), corner(c1));
+                            let key = (idx(p0[0], p0[1], p0[2]), e as usize / 4);
+                            *vertex_of.entry(key).or_insert_with(|| mesh.push_edge_vertex(field, dims, p0, p1, iso))
+                        }).collect();
+
+                        if polygon.centre {
+                            let c = mesh.push_centre_vertex(&ring);
+                            for i in 0..ring.len() {
+                                mesh.indices.extend_from_slice(&[c, ring[i], ring[(i + 1) % ring.len()]]);
+                            }
+                        } else {
+                            for i in 1..ring.len() - 1 {
+                                mesh.indices.extend_from_slice(&[ring[0], ring[i], ring[i + 1]]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        mesh
+    }
+
+    fn push_edge_vertex(&mut self, field: &[f32], dims: &Dims3, p0: [u32; 3], p1: [u32; 3], iso: f32) -> u32 {
+        let idx = |q: [u32; 3]| (q[0] + (q[1] * dims[0]) + (q[2] * dims[0] * dims[1])) as usize;
+        let (v0, v1) = (field[idx(p0)], field[idx(p1)]);
+        let t = if v1 != v0 { ((iso - v0) / (v1 - v0)).clamp(0.0, 1.0) } else { 0.5 };
+        let (g0, g1) = (grid_gradient(field, dims, p0), grid_gradient(field, dims, p1));
+
+        let lerp = |a: f32, b: f32| a + ((b - a) * t);
+        self.positions.push([0, 1, 2].map(|i| lerp(p0[i] as f32, p1[i] as f32)));
+        self.normals.push(normalised([0, 1, 2].map(|i| -lerp(g0[i], g1[i]))));
+        (self.positions.len() - 1) as u32
+    }
+
+    /// Mean of a polygon's vertices, not shared with other cubes
+    fn push_centre_vertex(&mut self, ring: &[u32]) -> u32 {
+        let k = ring.len() as f32;
+        let mean = |of: &Vec<P3>| [0, 1, 2].map(|i| ring.iter().map(|&v| of[v as usize][i]).sum::<f32>() / k);
+        let (position, normal) = (mean(&self.positions), normalised(mean(&self.normals)));
+        self.positions.push(position);
+        self.normals.push(normal);
+        (self.positions.len() - 1) as u32
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Samples another field (same dims) at every vertex with trilinear interpolation and keeps it as a named attribute
+    pub fn add_scalar(&mut self, name: &str, field: &[f32], dims: &Dims3) {
+        assert_eq!(field.len(), (dims[0] * dims[1] * dims[2]) as usize);
+        let idx = |q: [u32; 3]| (q[0] + (q[1] * dims[0]) + (q[2] * dims[0] * dims[1])) as usize;
+        let values = self.positions.iter().map(|p| {
+            let lo = [0, 1, 2].map(|i| (p[i].floor().max(0.0) as u32).min(dims[i] - 1));
+            let hi = [0, 1, 2].map(|i| (lo[i] + 1).min(dims[i] - 1));
+            let w = [0, 1, 2].map(|i| p[i] - lo[i] as f32);
+            let mut value = 0.0; // MUT
+            for c in 0..8 {
+                let q = [0, 1, 2].map(|i| if c & (1 << i) != 0 { hi[i] } else { lo[i] });
+                let weight: f32 = (0..3).map(|i| if c & (1 << i) != 0 { w[i] } else { 1.0 - w[i] }).product();
+                value += weight * field[idx(q)];
+            }
+            value
+        }).collect();
+        self.scalars.push(ScalarAttribute { name: name.to_string(), values: values });
+    }
+
+    /// Voxel space -> world (physical units), for export after add_scalar() has sampled the fields
+    /// Voxel centres sit half a voxel in from the grid corner, see VoxelTransform
+    pub fn to_world(&mut self, transform: &VoxelTransform) {
+        for p in self.positions.iter_mut() {
+            *p = transform.to_world([p[0] + 0.5, p[1] + 0.5, p[2] + 0.5]);
+        }
+        for n in self.normals.iter_mut() {
+            *n = normalised(transform.normal_to_world(*n));
+        }
+        if transform.mirrors() { // keep triangles counter-clockwise seen from their normals
+            for triangle in self.indices.chunks_exact_mut(3) { triangle.swap(1, 2); }
+        }
+    }
+
+    /// Writes the mesh in the format matching the path's extension (.obj, .ply, .glb or .gltf)
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let bytes = match MeshFormat::from_path(path) {
+            Some(MeshFormat::Obj) => self.to_obj(),
+            Some(MeshFormat::Ply) => self.to_ply(),
+            Some(MeshFormat::Gltf) => self.to_glb()?,
+            None => return Err(format!("No mesh format for {}, expected .obj, .ply, .glb or .gltf", path.display()).into()),
+        };
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    pub fn to_obj(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        writeln!(out, "# bocs marching cubes, {} vertices, {} triangles", self.positions.len(), self.triangle_count()).unwrap();
+        for p in self.positions.iter() { writeln!(out, "v {} {} {}", p[0], p[1], p[2]).unwrap(); }
+        for n in self.normals.iter() { writeln!(out, "vn {} {} {}", n[0], n[1], n[2]).unwrap(); }
+        for tri in self.indices.chunks_exact(3) {
+            let [a, b, c] = [tri[0] + 1, tri[1] + 1, tri[2] + 1]; // OBJ indices start at 1
+            writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}").unwrap();
+        }
+        out
+    }
+
+    pub fn to_ply(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        writeln!(out, "ply\nformat binary_little_endian 1.0\ncomment bocs marching cubes").unwrap();
+        writeln!(out, "element vertex {}", self.positions.len()).unwrap();
+        for p in ["x", "y", "z", "nx", "ny", "nz"] { writeln!(out, "property float {p}").unwrap(); }
+        for s in self.scalars.iter() { writeln!(out, "property float {}", s.name.replace(char::is_whitespace, "_")).unwrap(); }
+        writeln!(out, "element face {}\nproperty list uchar uint vertex_indices\nend_header", self.triangle_count()).unwrap();
+
+        for (i, (p, n)) in self.positions.iter().zip(self.normals.iter()).enumerate() {
+            for v in p.iter().chain(n.iter()) { out.extend_from_slice(&v.to_le_bytes()); }
+            for s in self.scalars.iter() { out.extend_from_slice(&s.values[i].to_le_bytes()); }
+        }
+        for tri in self.indices.chunks_exact(3) {
+            out.push(3);
+            for i in tri { out.extend_from_slice(&i.to_le_bytes()); }
+        }
+        out
+    }
+
+    /// Binary glTF 2.0: one triangle primitive, buffer views laid out as positions, normals, scalars, indices
+    pub fn to_glb(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+
+        let count = self.positions.len();
+        let mut bin: Vec<u8> = Vec::new();
+        let mut views = Vec::new();
+        let mut accessors = Vec::new();
+        let mut attributes = serde_json::Map::new();
+
+        // every block is a multiple of 4 bytes, so views stay aligned
+        let mut push_view = |bin: &mut Vec<u8>, bytes: Vec<u8>, target: u32| {
+            views.push(serde_json::json!({ "buffer": 0, "byteOffset": bin.len(), "byteLength": bytes.len(), "target": target }));
+            bin.extend_from_slice(&bytes);
+            views.len() - 1
+        };
+
+        let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]); // glTF requires POSITION bounds
+        for p in self.positions.iter() {
+            for i in 0..3 { min[i] = min[i].min(p[i]); max[i] = max[i].max(p[i]); }
+        }
+        if count == 0 { (min, max) = ([0.0; 3], [0.0; 3]); }
+
+        let v = push_view(&mut bin, self.positions.iter().flatten().flat_map(|f| f.to_le_bytes()).collect(), ARRAY_BUFFER);
+        accessors.push(serde_json::json!({ "bufferView": v, "componentType": FLOAT, "count": count, "type": "VEC3", "min": min, "max": max }));
+        attributes.insert("POSITION".into(), (accessors.len() - 1).into());
+
+        let v = push_view(&mut bin, self.normals.iter().flatten().flat_map(|f| f.to_le_bytes()).collect(), ARRAY_BUFFER);
+        accessors.push(serde_json::json!({ "bufferView": v, "componentType": FLOAT, "count": count, "type": "VEC3" }));
+        attributes.insert("NORMAL".into(), (accessors.len() - 1).into());
+
+        for s in self.scalars.iter() {
+            let v = push_view(&mut bin, s.values.iter().flat_map(|f| f.to_le_bytes()).collect(), ARRAY_BUFFER);
+            accessors.push(serde_json::json!({ "bufferView": v, "componentType": FLOAT, "count": count, "type": "SCALAR" }));
+            // application specific attributes must start with an underscore
+            attributes.insert(format!("_{}", s.name.to_uppercase().replace(char::is_whitespace, "_")), (accessors.len() - 1).into());
+        }
+
+        let v = push_view(&mut bin, self.indices.iter().flat_map(|i| i.to_le_bytes()).collect(), ELEMENT_ARRAY_BUFFER);
+        accessors.push(serde_json::json!({ "bufferView": v, "componentType": UNSIGNED_INT, "count": self.indices.len(), "type": "SCALAR" }));
+        let indices = accessors.len() - 1;
+
+        let gltf = serde_json::json!({
+            "asset": { "version": "2.0", "generator": "bocs" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{ "primitives": [{ "attributes": attributes, "indices": indices, "mode": 4 }] }],
+            "buffers": [{ "byteLength": bin.len() }],
+            "bufferViews": views,
+            "accessors": accessors,
+        });
+        let mut json = serde_json::to_vec(&gltf)?;
+        while json.len() % 4 != 0 { json.push(b' '); } // chunks are 4 byte aligned, JSON pads with spaces
+
+        // header, JSON chunk, BIN chunk
+        let total = 12 + 8 + json.len() + 8 + bin.len();
+        let mut out = Vec::with_capacity(total);
+        out.extend_from_slice(b"glTF");
+        out.extend_from_slice(&2u32.to_le_bytes());
+        out.extend_from_slice(&(total as u32).to_le_bytes());
+        out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"JSON");
+        out.extend_from_slice(&json);
+        out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"BIN\0");
+        out.extend_from_slice(&bin);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn sphere(n: u32, radius: f32) -> Vec<f32> {
+        let c = (n - 1) as f32 / 2.0;
+        (0..n * n * n).map(|i| {
+            let p = [i % n, (i / n) % n, i / (n * n)].map(|v| v as f32 - c);
+            radius - (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt()
+        }).collect()
+    }
+
+    /// Closed and consistently wound: every directed edge once, and its reverse once in the neighbouring triangle
+    fn assert_watertight(mesh: &Mesh) {
+        assert!(mesh.triangle_count() > 0);
+        let mut directed: HashMap<(u32, u32), usize> = HashMap::new();
+        for tri in mesh.indices.chunks_exact(3) {
+            for i in 0..3 { *directed.entry((tri[i], tri[(i + 1) % 3])).or_insert(0) += 1; }
+        }
+        for (&(a, b), &n) in directed.iter() {
+            assert_eq!(n, 1, "edge {}->{} used {} times", a, b, n);
+            assert_eq!(directed.get(&(b, a)), Some(&1), "edge {}->{} has no reverse", a, b);
+        }
+    }
+
+    #[test]
+    fn sphere_is_closed_and_wound_outwards() {
+        let n = 12;
+        let mesh = Mesh::marching_cubes(&sphere(n, 4.0), &[n; 3], 0.0);
+        assert_watertight(&mesh);
+
+        let c = (n - 1) as f32 / 2.0;
+        for tri in mesh.indices.chunks_exact(3) {
+            let [a, b, d] = [0, 1, 2].map(|i| mesh.positions[tri[i] as usize]);
+            let (u, v) = ([0, 1, 2].map(|i| b[i] - a[i]), [0, 1, 2].map(|i| d[i] - a[i]));
+            let cross = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+            let out: f32 = (0..3).map(|i| cross[i] * ((a[i] + b[i] + d[i]) / 3.0 - c)).sum();
+            assert!(out > 0.0); // counter-clockwise seen from outside
+        }
+        for normal in mesh.normals.iter() {
+            assert!((normal.iter().map(|x| x * x).sum::<f32>() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn random_field_is_closed() {
+        // every corner configuration, including the ambiguous faces, shows up; a zero border keeps the surface inside the grid
+        let n = 10;
+        let mut rng = StdRng::seed_from_u64(3);
+        let field: Vec<f32> = (0..n * n * n).map(|i| {
+            let p = [i % n, (i / n) % n, i / (n * n)];
+            if p.iter().any(|&v| v == 0 || v == n - 1) { 0.0 } else { rng.random::<f32>() }
+        }).collect();
+        assert_watertight(&Mesh::marching_cubes(&field, &[n; 3], 0.5));
+    }
+
+    fn exported() -> Mesh {
+        let n = 6;
+        let field = sphere(n, 2.0);
+        let mut mesh = Mesh::marching_cubes(&field, &[n; 3], 0.0);
+        mesh.add_scalar("other species", &field, &[n; 3]);
+        mesh
+    }
+
+    #[test]
+    fn obj_lists_every_element() {
+        let mesh = exported();
+        let obj = String::from_utf8(mesh.to_obj()).unwrap();
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(count("v "), mesh.positions.len());
+        assert_eq!(count("vn "), mesh.normals.len());
+        assert_eq!(count("f "), mesh.triangle_count());
+        assert!(obj.lines().filter(|l| l.starts_with("f ")).all(|l| !l.contains(" 0/"))); // 1-based
+    }
+
+    #[test]
+    fn ply_header_matches_body() {
+        let mesh = exported();
+        let ply = mesh.to_ply();
+        let end = ply.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+        let header = std::str::from_utf8(&ply[..end]).unwrap();
+        assert!(header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+        assert!(header.contains(&format!("element vertex {}\n", mesh.positions.len())));
+        assert!(header.contains("property float other_species\n"));
+        assert!(header.contains(&format!("element face {}\n", mesh.triangle_count())));
+        assert_eq!(ply.len() - end, (mesh.positions.len() * 7 * 4) + (mesh.triangle_count() * (1 + 3 * 4)));
+    }
+
+    #[test]
+    fn glb_chunks_match_their_lengths() {
+        let mesh = exported();
+        let glb = mesh.to_glb().unwrap();
+        let word = |at: usize| u32::from_le_bytes([glb[at], glb[at + 1], glb[at + 2], glb[at + 3]]) as usize;
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(word(4), 2);
+        assert_eq!(word(8), glb.len());
+
+        let json_len = word(12);
+        assert_eq!(&glb[16..20], b"JSON");
+        assert_eq!(json_len % 4, 0);
+        let gltf: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        let bin_len = word(20 + json_len);
+        assert_eq!(&glb[24 + json_len..28 + json_len], b"BIN\0");
+        assert_eq!(28 + json_len + bin_len, glb.len());
+        assert_eq!(gltf["buffers"][0]["byteLength"].as_u64(), Some(bin_len as u64));
+
+        let attributes = &gltf["meshes"][0]["primitives"][0]["attributes"];
+        assert!(attributes["_OTHER_SPECIES"].is_u64());
+        let indices = gltf["meshes"][0]["primitives"][0]["indices"].as_u64().unwrap() as usize;
+        assert_eq!(gltf["accessors"][indices]["count"].as_u64(), Some(mesh.indices.len() as u64));
+        let viewed: u64 = gltf["bufferViews"].as_array().unwrap().iter().map(|v| v["byteLength"].as_u64().unwrap()).sum();
+        assert_eq!(viewed, bin_len as u64);
+    }
+}
@@ -1,3 +1,16 @@
 pub mod voxel_grid;
 pub mod camera;
-pub mod world;
\ No newline at end of file
+pub mod world;
+pub mod diffusion;
+pub mod reaction;
+pub mod transfer_function;
+pub mod rendering;
+pub mod mesh;
+pub mod slices;
+pub mod capture;
+pub mod clipping;
+pub mod channels;
+pub mod labels;
+pub mod volume;
+pub mod tiff_stack;
+pub mod zarr;
\ No newline at end of file
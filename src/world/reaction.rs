@@ -0,0 +1,206 @@
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use crate::world::{diffusion::TimeStep, voxel_grid::Dims3};
+
+/// Species slots in the voxel buffers (and vec4 lanes in Uniforms)
+pub const MAX_SPECIES: usize = 4;
+
+/// Reaction term added to the diffusion of each species
+/// Species are stored species-major in the voxel buffers: species s of voxel idx lives at s * voxels + idx
+/// Two-species models use species 0 as u and species 1 as v
+/// None: plain diffusion (laplacian.wgsl)
+/// GrayScott: du = -uv^2 + feed(1 - u), dv = uv^2 - (feed + kill)v
+/// FitzHughNagumo: du = u - u^3/3 - v + current, dv = epsilon(u + a - bv)
+/// Schnakenberg: du = gamma(a - u + u^2 v), dv = gamma(b - u^2 v)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReactionModel {
+    None,
+    GrayScott { feed: f32, kill: f32 },
+    FitzHughNagumo { a: f32, b: f32, epsilon: f32, current: f32 },
+    Schnakenberg { a: f32, b: f32, gamma: f32 },
+}
+
+impl ReactionModel {
+    /// Code packed into Uniforms.reaction_flags[0], must match the consts in reaction_diffusion.wgsl
+    pub fn code(&self) -> u32 {
+        match self {
+            ReactionModel::None => 0,
+            ReactionModel::GrayScott { .. } => 1,
+            ReactionModel::FitzHughNagumo { .. } => 2,
+            ReactionModel::Schnakenberg { .. } => 3,
+        }
+    }
+
+    /// Minimum number of species the model reacts
+    pub fn species(&self) -> usize {
+        match self {
+            ReactionModel::None => 1,
+            _ => 2
+        }
+    }
+
+    /// Uniforms.reaction, model parameters in declaration order
+    pub fn params(&self) -> [f32; 4] {
+        match *self {
+            ReactionModel::None => [0.0; 4],
+            ReactionModel::GrayScott { feed, kill } => [feed, kill, 0.0, 0.0],
+            ReactionModel::FitzHughNagumo { a, b, epsilon, current } => [a, b, epsilon, current],
+            ReactionModel::Schnakenberg { a, b, gamma } => [a, b, gamma, 0.0],
+        }
+    }
+
+    /// Reaction rates at one voxel, species beyond the model's own only diffuse
+    pub fn rates(&self, c: &[f32; MAX_SPECIES]) -> [f32; MAX_SPECIES] {
+        let (u, v) = (c[0], c[1]);
+        match *self {
+            ReactionModel::None => [0.0; MAX_SPECIES],
+            ReactionModel::GrayScott { feed, kill } => {
+                let uvv = u * v * v;
+                [-uvv + (feed * (1.0 - u)), uvv - ((feed + kill) * v), 0.0, 0.0]
+            },
+            ReactionModel::FitzHughNagumo { a, b, epsilon, current } => {
+                [u - ((u * u * u) / 3.0) - v + current, epsilon * (u + a - (b * v)), 0.0, 0.0]
+            },
+            ReactionModel::Schnakenberg { a, b, gamma } => {
+                let uuv = u * u * v;
+                [gamma * (a - u + uuv), gamma * (b - uuv), 0.0, 0.0]
+            },
+        }
+    }
+}
+
+/// Species count, per-species diffusion coefficients and reaction term
+/// Lives in SimulationParams; with ReactionModel::None, SimulationParams::diffusion is used instead
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReactionDiffusion {
+    pub model: ReactionModel,
+    pub species: usize, // 1..=MAX_SPECIES, at least model.species()
+    pub diffusion: [f32; MAX_SPECIES], // D per species
+}
+
+impl Default for ReactionDiffusion {
+    fn default() -> Self {
+        ReactionDiffusion {
+            model: ReactionModel::None,
+            species: 1,
+            diffusion: [1.0; MAX_SPECIES]
+        }
+    }
+}
+
+impl ReactionDiffusion {
+    /// Spots and stripes with unit voxels
+    pub fn gray_scott() -> Self {
+        ReactionDiffusion {
+            model: ReactionModel::GrayScott { feed: 0.037, kill: 0.06 },
+            species: 2,
+            diffusion: [0.2, 0.1, 0.0, 0.0]
+        }
+    }
+
+    /// Excitable medium, inhibitor does not diffuse
+    pub fn fitzhugh_nagumo() -> Self {
+        ReactionDiffusion {
+            model: ReactionModel::FitzHughNagumo { a: 0.7, b: 0.8, epsilon: 0.08, current: 0.0 },
+            species: 2,
+            diffusion: [1.0, 0.0, 0.0, 0.0]
+        }
+    }
+
+    /// Turing patterns, needs Dv >> Du
+    pub fn schnakenberg() -> Self {
+        ReactionDiffusion {
+            model: ReactionModel::Schnakenberg { a: 0.1, b: 0.9, gamma: 1.0 },
+            species: 2,
+            diffusion: [1.0, 20.0, 0.0, 0.0]
+        }
+    }
+
+    /// Simulated time per frame that suits each preset when stepped with TimeStep::Fixed
+    pub fn preset_timestep(&self) -> TimeStep {
+        match self.model {
+            ReactionModel::None => TimeStep::Adaptive,
+            ReactionModel::GrayScott { .. } => TimeStep::Fixed(1.0),
+            ReactionModel::FitzHughNagumo { .. } => TimeStep::Fixed(0.1),
+            ReactionModel::Schnakenberg { .. } => TimeStep::Fixed(0.05),
+        }
+    }
+
+    /// None -> GrayScott -> FitzHughNagumo -> Schnakenberg -> None
+    pub fn next_preset(&self) -> Self {
+        match self.model {
+            ReactionModel::None => ReactionDiffusion::gray_scott(),
+            ReactionModel::GrayScott { .. } => ReactionDiffusion::fitzhugh_nagumo(),
+            ReactionModel::FitzHughNagumo { .. } => ReactionDiffusion::schnakenberg(),
+            ReactionModel::Schnakenberg { .. } => ReactionDiffusion::default(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.model != ReactionModel::None
+    }
+
+    pub fn max_diffusion(&self) -> f32 {
+        self.diffusion[..self.species].iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Uniforms.reaction_flags: [model code, species, shown channel, channel count] (see Channels)
+    pub fn flags(&self, shown: usize, channels: usize) -> [u32; 4] {
+        assert!(self.species >= self.model.species() && self.species <= MAX_SPECIES && shown < channels);
+        [self.model.code(), self.species as u32, shown as u32, channels as u32]
+    }
+
+    /// Channel name of species s (see Channels::fit_species())
+    pub fn species_name(&self, s: usize) -> String {
+        match (self.model, s) {
+            (ReactionModel::None, 0) => "field".to_string(),
+            (ReactionModel::None, _) => format!("species_{}", s),
+            (_, 0) => "u".to_string(),
+            (_, 1) => "v".to_string(),
+            _ => format!("species_{}", s),
+        }
+    }
+
+    /// Species shown when the preset is applied, the one with the clearest pattern
+    pub fn preset_shown(&self) -> usize {
+        match self.model {
+            ReactionModel::GrayScott { .. } => 1,
+            _ => 0,
+        }
+    }
+
+    /// Species-major starting field: each model's rest state, seeded centre cube and a little noise
+    pub fn initial_state(&self, dims: &Dims3, seed: u32) -> Vec<f32> {
+        let voxels = (dims[0] * dims[1] * dims[2]) as usize;
+        let mut rng = StdRng::seed_from_u64(seed as u64);
+        let mut field = vec![0.0; voxels * self.species];
+
+        // (u, v) outside and inside the seeded cube
+        let (rest, seeded) = match self.model {
+            ReactionModel::None => {
+                for c in field.iter_mut() { *c = rng.random::<f32>(); }
+                return field;
+            },
+            ReactionModel::GrayScott { .. } => ((1.0, 0.0), (0.5, 0.25)),
+            ReactionModel::FitzHughNagumo { .. } => ((-1.2, -0.62), (2.0, -0.62)),
+            ReactionModel::Schnakenberg { a, b, .. } => {
+                let steady = (a + b, b / ((a + b) * (a + b)));
+                (steady, steady)
+            },
+        };
+
+        let half = [dims[0] / 2, dims[1] / 2, dims[2] / 2];
+        let radius = [(dims[0] / 10).max(1), (dims[1] / 10).max(1), (dims[2] / 10).max(1)];
+        for z in 0..dims[2] {
+            for y in 0..dims[1] {
+                for x in 0..dims[0] {
+                    let idx = (x + (y * dims[0]) + (z * dims[0] * dims[1])) as usize;
+                    let in_cube = x.abs_diff(half[0]) < radius[0] && y.abs_diff(half[1]) < radius[1] && z.abs_diff(half[2]) < radius[2];
+                    let (u0, v0) = if in_cube { seeded } else { rest };
+                    field[idx] = u0 + (rng.random::<f32>() - 0.5) * 0.02;
+                    field[voxels + idx] = v0 + (rng.random::<f32>() - 0.5) * 0.02;
+                }
+            }
+        }
+        field
+    }
+}
@@ -0,0 +1,194 @@
+/// Default distance between raymarch samples, in voxels
+pub const DEFAULT_STEP: f32 = 0.5;
+/// Accumulated alpha past which a ray stops marching
+pub const DEFAULT_EARLY_EXIT: f32 = 0.99;
+
+/// What each ray reports, packed into Uniforms.flags[0] bits 8-15
+/// EmissionAbsorption: front to back compositing through the TransferFunction
+/// MaximumIntensity / MinimumIntensity: extreme value along the ray (MIP / MinIP)
+/// Average: mean value along the ray
+/// FirstHit: first sample at or above threshold, opaque
+/// Isosurface: first crossing of iso refined by bisection, Blinn–Phong shaded with gradient normals
+/// Projection modes colour the reported value with the TransferFunction's colormap and are opaque inside the grid
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RenderMode {
+    EmissionAbsorption,
+    MaximumIntensity,
+    MinimumIntensity,
+    Average,
+    FirstHit { threshold: f32 },
+    Isosurface { iso: f32 },
+}
+
+impl RenderMode {
+    /// Must match the consts in raymarch.wgsl
+    pub fn code(&self) -> u32 {
+        match self {
+            RenderMode::EmissionAbsorption => 0,
+            RenderMode::MaximumIntensity => 1,
+            RenderMode::MinimumIntensity => 2,
+            RenderMode::Average => 3,
+            RenderMode::FirstHit { .. } => 4,
+            RenderMode::Isosurface { .. } => 5,
+        }
+    }
+
+    /// EA -> MIP -> MinIP -> Average -> FirstHit -> Isosurface -> EA, threshold and iso value both start at threshold
+    pub fn next(&self, threshold: f32) -> Self {
+        match self {
+            RenderMode::EmissionAbsorption => RenderMode::MaximumIntensity,
+            RenderMode::MaximumIntensity => RenderMode::MinimumIntensity,
+            RenderMode::MinimumIntensity => RenderMode::Average,
+            RenderMode::Average => RenderMode::FirstHit { threshold: threshold },
+            RenderMode::FirstHit { .. } => RenderMode::Isosurface { iso: threshold },
+            RenderMode::Isosurface { .. } => RenderMode::EmissionAbsorption,
+        }
+    }
+}
+
+/// How the raymarch reads the field between voxel centres (voxel i covers [i, i + 1), centred at i + 0.5, as in VoxelGrid)
+/// Packed into Uniforms.flags[0] bits 16-23
+/// Nearest: the voxel containing the sample, blocky
+/// Trilinear: 8 voxels, continuous but with creased gradients
+/// Tricubic: Catmull–Rom over 64 voxels, smooth gradients for isosurface shading at 8x the reads
+/// Interpolation is done by hand in raymarch.wgsl since the field lives in the ping-pong storage buffers
+/// (and r32float textures can't be linearly filtered without FLOAT32_FILTERABLE)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    Nearest,
+    Trilinear,
+    Tricubic,
+}
+
+impl Interpolation {
+    /// Must match the consts in raymarch.wgsl
+    pub fn code(&self) -> u32 {
+        match self {
+            Interpolation::Nearest => 0,
+            Interpolation::Trilinear => 1,
+            Interpolation::Tricubic => 2,
+        }
+    }
+
+    /// Nearest -> Trilinear -> Tricubic -> Nearest
+    pub fn next(&self) -> Self {
+        match self {
+            Interpolation::Nearest => Interpolation::Trilinear,
+            Interpolation::Trilinear => Interpolation::Tricubic,
+            Interpolation::Tricubic => Interpolation::Nearest,
+        }
+    }
+}
+
+/// How the visible channels (see Channels::composited()) combine at each sample, packed into Uniforms.composite_flags[0]
+/// Shown: only the shown channel, through the TransferFunction
+/// Additive: colours and opacities summed (clamped to 1), overlapping green and magenta turn white as in a fluorescence viewer
+/// Maximum: per component maximum
+/// Blending applies to the emission–absorption and maximum intensity modes (per-channel MIP then blended),
+/// the other modes keep reading the shown channel
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChannelBlend {
+    Shown,
+    Additive,
+    Maximum,
+}
+
+impl ChannelBlend {
+    /// Must match the consts in raymarch.wgsl
+    pub fn code(&self) -> u32 {
+        match self {
+            ChannelBlend::Shown => 0,
+            ChannelBlend::Additive => 1,
+            ChannelBlend::Maximum => 2,
+        }
+    }
+
+    /// Shown -> Additive -> Maximum -> Shown
+    pub fn next(&self) -> Self {
+        match self {
+            ChannelBlend::Shown => ChannelBlend::Additive,
+            ChannelBlend::Additive => ChannelBlend::Maximum,
+            ChannelBlend::Maximum => ChannelBlend::Shown,
+        }
+    }
+}
+
+/// Blinn–Phong terms for the isosurface mode, lit by a headlight at the camera
+/// Surface colour is the TransferFunction's colormap at the iso value
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lighting {
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub shininess: f32, // Blinn–Phong exponent
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Lighting {
+            ambient: 0.15,
+            diffuse: 0.75,
+            specular: 0.3,
+            shininess: 32.0
+        }
+    }
+}
+
+impl Lighting {
+    /// Uniforms.lighting: [ambient, diffuse, specular, shininess]
+    pub fn uniform(&self) -> [f32; 4] {
+        [self.ambient, self.diffuse, self.specular, self.shininess]
+    }
+}
+
+/// How rays are sampled and composited in raymarch.wgsl
+/// Opacity in the TransferFunction is per voxel of travel, each sample is corrected
+/// for step_size so the image brightness doesn't depend on it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RenderSettings {
+    pub mode: RenderMode,
+    pub step_size: f32, // voxels between samples
+    pub early_exit: f32, // alpha in (0, 1]
+    pub jitter: bool, // offsets each ray's first sample by a random fraction of a step, per frame
+    pub interpolation: Interpolation,
+    pub lighting: Lighting,
+    pub background: [f32; 3], // sRGB, behind the volume on screen and in captures
+    pub blend: ChannelBlend,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            mode: RenderMode::EmissionAbsorption,
+            step_size: DEFAULT_STEP,
+            early_exit: DEFAULT_EARLY_EXIT,
+            jitter: true,
+            interpolation: Interpolation::Trilinear,
+            lighting: Lighting::default(),
+            background: [0.75, 0.75, 0.75],
+            blend: ChannelBlend::Shown
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Uniforms.raymarch: [step size, early exit alpha, jitter, frame] (frame seeds the jitter)
+    pub fn uniform(&self, frame: u32) -> [f32; 4] {
+        assert!(self.step_size > 0.0 && self.early_exit > 0.0 && self.early_exit <= 1.0);
+        [self.step_size, self.early_exit, self.jitter as u32 as f32, (frame % (1 << 16)) as f32]
+    }
+
+    /// Uniforms.render_params: [first hit threshold or iso value, 0, 0, 0]
+    pub fn params(&self) -> [f32; 4] {
+        match self.mode {
+            RenderMode::FirstHit { threshold } => [threshold, 0.0, 0.0, 0.0],
+            RenderMode::Isosurface { iso } => [iso, 0.0, 0.0, 0.0],
+            _ => [0.0; 4],
+        }
+    }
+
+    /// Uniforms.flags[0] with the read ping flag in bit 0, the mode in bits 8-15 and interpolation in bits 16-23
+    pub fn flags(&self, read_ping: bool) -> u32 {
+        (read_ping as u32) | (self.mode.code() << 8) | (self.interpolation.code() << 16)
+    }
+}
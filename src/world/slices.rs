@@ -0,0 +1,173 @@
+use crate::world::voxel_grid::Dims3;
+
+/// Orthogonal slice orientations, named by the axis they cut across
+/// Sagittal: constant x, shows y (right) by z (up)
+/// Coronal: constant y, shows x (right) by z (up)
+/// Axial: constant z, shows x (right) by y (up)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SliceAxis {
+    Sagittal,
+    Coronal,
+    Axial,
+}
+
+impl SliceAxis {
+    /// Index of the axis the slice cuts across, also the code slice() in raymarch.wgsl expects
+    pub fn normal(&self) -> usize {
+        match self {
+            SliceAxis::Sagittal => 0,
+            SliceAxis::Coronal => 1,
+            SliceAxis::Axial => 2,
+        }
+    }
+
+    /// Axial -> Coronal -> Sagittal -> Axial (pane order, top to bottom)
+    pub fn next(&self) -> Self {
+        match self {
+            SliceAxis::Axial => SliceAxis::Coronal,
+            SliceAxis::Coronal => SliceAxis::Sagittal,
+            SliceAxis::Sagittal => SliceAxis::Axial,
+        }
+    }
+}
+
+/// Where slices are drawn, packed into Uniforms.slices[3] bits 0-7
+/// Hidden: raymarched volume only
+/// Panes: volume with axial, coronal and sagittal panes stacked down the right edge of the window
+/// Single: one slice filling the window (no raymarch)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SliceLayout {
+    Hidden,
+    Panes,
+    Single(SliceAxis),
+}
+
+impl SliceLayout {
+    /// Must match the consts in raymarch.wgsl
+    pub fn code(&self) -> u32 {
+        match self {
+            SliceLayout::Hidden => 0,
+            SliceLayout::Panes => 1,
+            SliceLayout::Single(_) => 2,
+        }
+    }
+}
+
+/// Orthogonal slice viewer state
+/// Slices are drawn by slice() in raymarch.wgsl into the same storage texture as the raymarch, coloured by the
+/// TransferFunction's colormap and presented by Render's full-screen quad
+/// Crosshairs mark where the other two slices cut each pane
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Slices {
+    pub layout: SliceLayout,
+    pub index: [u32; 3], // voxel index of the sagittal, coronal and axial slice
+    pub focus: SliceAxis, // slice moved by move_focus(), outlined in Panes
+}
+
+impl Slices {
+    /// Hidden, every slice through the middle of the grid
+    pub fn new(dims: &Dims3) -> Self {
+        Slices {
+            layout: SliceLayout::Hidden,
+            index: [dims[0] / 2, dims[1] / 2, dims[2] / 2],
+            focus: SliceAxis::Axial
+        }
+    }
+
+    /// Hidden -> Panes -> Single(Axial) -> Single(Coronal) -> Single(Sagittal) -> Hidden
+    /// A single slice takes the focus
+    pub fn next_layout(&mut self) {
+        self.layout = match self.layout {
+            SliceLayout::Hidden => SliceLayout::Panes,
+            SliceLayout::Panes => SliceLayout::Single(SliceAxis::Axial),
+            SliceLayout::Single(SliceAxis::Axial) => SliceLayout::Single(SliceAxis::Coronal),
+            SliceLayout::Single(SliceAxis::Coronal) => SliceLayout::Single(SliceAxis::Sagittal),
+            SliceLayout::Single(SliceAxis::Sagittal) => SliceLayout::Hidden,
+        };
+        if let SliceLayout::Single(axis) = self.layout { self.focus = axis; }
+    }
+
+    /// Steps the focused slice by delta voxels, clamped to the grid
+    pub fn move_focus(&mut self, delta: i32, dims: &Dims3) -> u32 {
+        let n = self.focus.normal();
+        self.index[n] = (self.index[n] as i64 + delta as i64).clamp(0, dims[n] as i64 - 1) as u32;
+        self.index[n]
+    }
+
+    /// Keeps each slice at the same fraction through the grid at other dims (e.g. another OmeZarr level)
+    pub fn rescale(&mut self, from: &Dims3, to: &Dims3) {
+        self.index = [0, 1, 2].map(|a| ((self.index[a] as u64 * to[a] as u64) / from[a] as u64).min(to[a] as u64 - 1) as u32);
+    }
+
+    /// False when a single slice covers the window
+    pub fn shows_volume(&self) -> bool {
+        !matches!(self.layout, SliceLayout::Single(_))
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.layout != SliceLayout::Hidden
+    }
+
+    /// Pixels slice() draws into for a width x height storage texture, [x0, y0, x1, y1) with rows counting up
+    /// Must match the pane layout in raymarch.wgsl
+    pub fn region(&self, width: u32, height: u32) -> Option<[u32; 4]> {
+        match self.layout {
+            SliceLayout::Hidden => None,
+            SliceLayout::Panes => {
+                let side = height / 3;
+                Some([width.saturating_sub(side), height - (3 * side), width, height])
+            },
+            SliceLayout::Single(_) => Some([0, 0, width, height]),
+        }
+    }
+
+    /// Uniforms.slices: [sagittal, coronal, axial index, layout | single axis << 8 | focus << 16]
+    pub fn uniform(&self) -> [u32; 4] {
+        let single = match self.layout {
+            SliceLayout::Single(axis) => axis.normal() as u32,
+            _ => 0,
+        };
+        [self.index[0], self.index[1], self.index[2], self.layout.code() | (single << 8) | ((self.focus.normal() as u32) << 16)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn focus_stops_at_the_grid_edges() {
+        let dims = [8, 16, 4];
+        let mut slices = Slices::new(&dims);
+        assert_eq!(slices.index, [4, 8, 2]);
+        assert_eq!(slices.move_focus(1, &dims), 3); // axial
+        assert_eq!(slices.move_focus(10, &dims), 3);
+        assert_eq!(slices.move_focus(-10, &dims), 0);
+        slices.focus = SliceAxis::Coronal;
+        assert_eq!(slices.move_focus(i32::MAX, &dims), 15);
+        assert_eq!(slices.index, [4, 15, 0]);
+    }
+
+    #[test]
+    fn rescale_keeps_the_fraction_through_the_grid() {
+        let mut slices = Slices::new(&[64, 64, 64]);
+        slices.index = [63, 16, 0];
+        slices.rescale(&[64, 64, 64], &[32, 16, 8]);
+        assert_eq!(slices.index, [31, 4, 0]);
+        slices.rescale(&[32, 16, 8], &[64, 64, 64]);
+        assert_eq!(slices.index, [62, 16, 0]);
+    }
+
+    #[test]
+    fn region_by_layout() {
+        let mut slices = Slices::new(&[8, 8, 8]);
+        assert_eq!(slices.region(800, 600), None);
+        slices.next_layout();
+        assert_eq!(slices.region(800, 600), Some([600, 0, 800, 600]));
+        assert_eq!(slices.region(800, 601), Some([600, 1, 800, 601])); // whole panes, the spare row at the bottom
+        assert_eq!(slices.region(100, 600), Some([0, 0, 100, 600])); // narrower than a pane
+        slices.next_layout();
+        assert_eq!(slices.layout, SliceLayout::Single(SliceAxis::Axial));
+        assert_eq!(slices.region(800, 600), Some([0, 0, 800, 600]));
+    }
+}
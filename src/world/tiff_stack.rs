@@ -0,0 +1,297 @@
+use std::{error::Error, fs::File, io::BufReader, path::{Path, PathBuf}};
+use tiff::{decoder::{ifd::Value, Decoder, DecodingResult}, tags::Tag, ColorType};
+use crate::world::{channels::ChannelType, voxel_grid::{P3, VoxelTransform}, volume::Volume};
+
+/// Where a stack's layout came from, in order of preference
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StackMetadata {
+    Ome, // OME-XML in the first page's ImageDescription
+    ImageJ, // ImageJ hyperstack description (images=, channels=, slices=, frames=, spacing=)
+    Plain, // every page a z slice of one channel
+}
+
+/// How the pages of a multi-page TIFF split into z slices, channels and time points
+/// Pages follow order from the first, fastest varying first: e.g. "ZCT" steps z, then channel, then time
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackLayout {
+    pub metadata: StackMetadata,
+    pub size: [usize; 5], // x, y, z, channels, time points
+    pub order: [char; 3], // 'Z', 'C' and 'T', fastest first (OME DimensionOrder without its XY)
+    pub spacing: P3, // physical voxel size, microns unless the file says otherwise
+    pub channel_names: Vec<String>,
+}
+
+impl StackLayout {
+    /// Page holding slice z of channel c at time point t
+    pub fn page(&self, z: usize, c: usize, t: usize) -> usize {
+        let (mut page, mut stride) = (0, 1); // MUT
+        for axis in self.order {
+            let (coord, size) = match axis { 'Z' => (z, self.size[2]), 'C' => (c, self.size[3]), _ => (t, self.size[4]) };
+            page += coord * stride;
+            stride *= size;
+        }
+        page
+    }
+}
+
+/// Multi-page TIFF or OME-TIFF z-stack, opened lazily: open() reads the layout only,
+/// load_time_point() decodes just that time point's pages
+/// 8, 16 and 32-bit greyscale pages, one sample per pixel; OME TiffData mappings are not followed,
+/// pages are taken in DimensionOrder from the first
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiffStack {
+    pub path: PathBuf,
+    pub layout: StackLayout,
+}
+
+impl TiffStack {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+        let (width, height) = decoder.dimensions()?;
+        let description = decoder.get_tag_ascii_string(Tag::ImageDescription).ok();
+        let resolution = match decoder.get_tag(Tag::XResolution) { // pixels per unit, a rational
+            Ok(Value::Rational(n, d)) if d != 0 => Some(n as f32 / d as f32),
+            Ok(Value::Float(r)) => Some(r),
+            _ => None,
+        };
+        let mut pages = 1; // MUT
+        while decoder.more_images() {
+            decoder.next_image()?;
+            pages += 1;
+        }
+
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or("stack".to_string());
+        let layout = match description.as_deref() {
+            Some(d) if d.contains("<OME") => TiffStack::ome_layout(d, &stem)?,
+            Some(d) if d.starts_with("ImageJ=") => TiffStack::imagej_layout(d, width, height, pages, resolution, &stem)?,
+            _ => StackLayout {
+                metadata: StackMetadata::Plain,
+                size: [width as usize, height as usize, pages, 1, 1],
+                order: ['Z', 'C', 'T'],
+                spacing: [1.0; 3],
+                channel_names: vec![stem]
+            },
+        };
+        if (layout.size[0], layout.size[1]) != (width as usize, height as usize) {
+            return Err(format!("{} pages are {}x{}, metadata says {}x{}", path.display(), width, height, layout.size[0], layout.size[1]).into());
+        }
+        if layout.size.contains(&0) {
+            return Err(format!("{} metadata gives an empty stack, {:?}", path.display(), layout.size).into());
+        }
+        if layout.size[2] * layout.size[3] * layout.size[4] > pages {
+            return Err(format!("{} has {} pages, metadata needs {}", path.display(), pages, layout.size[2] * layout.size[3] * layout.size[4]).into());
+        }
+        Ok(TiffStack { path: path.to_path_buf(), layout: layout })
+    }
+
+    pub fn time_points(&self) -> usize {
+        self.layout.size[4]
+    }
+
+    /// Every channel of time point t, one Volume each, sharing dims and the layout's spacing
+    /// Image rows run top down, so the first row lands at the top of the grid (highest y)
+    pub fn load_time_point(&self, t: usize) -> Result<Vec<Volume>, Box<dyn Error>> {
+        let [w, h, d, channels, times] = self.layout.size;
+        if t >= times {
+            return Err(format!("Time point {} out of range, {} has {}", t, self.path.display(), times).into());
+        }
+        let dims = [w as u32, h as u32, d as u32];
+        let mut decoder = Decoder::new(BufReader::new(File::open(&self.path)?))?;
+        let mut values = vec![vec![0.0; w * h * d]; channels];
+        let mut dtypes = vec![ChannelType::U8; channels];
+
+        // in page order, the decoder only learns where pages are by walking forward
+        let mut pages: Vec<(usize, usize, usize)> = (0..channels).flat_map(|c| (0..d).map(move |z| (c, z))).map(|(c, z)| (self.layout.page(z, c, t), c, z)).collect();
+        pages.sort_unstable();
+        for (page, c, z) in pages {
+            decoder.seek_to_image(page)?;
+            let (page_w, page_h) = decoder.dimensions()?;
+            if (page_w as usize, page_h as usize) != (w, h) {
+                return Err(format!("{} page {} is {}x{}, the stack is {}x{}", self.path.display(), page, page_w, page_h, w, h).into());
+            }
+            let colour = decoder.colortype()?;
+            if !matches!(colour, ColorType::Gray(8) | ColorType::Gray(16) | ColorType::Gray(32)) {
+                return Err(format!("Unsupported TIFF page type {:?}, expected 8, 16 or 32-bit greyscale", colour).into());
+            }
+            let (image, dtype) = match decoder.read_image()? {
+                DecodingResult::U8(p) => (p.into_iter().map(|v| v as f32).collect::<Vec<f32>>(), ChannelType::U8),
+                DecodingResult::U16(p) => (p.into_iter().map(|v| v as f32).collect(), ChannelType::U16),
+                DecodingResult::U32(p) => (p.into_iter().map(|v| v as f32).collect(), ChannelType::U32),
+                DecodingResult::F32(p) => (p, ChannelType::F32),
+                _ => return Err("Unsupported TIFF sample format, expected unsigned 8, 16, 32-bit or float".into()),
+            };
+            dtypes[c] = dtype;
+            for row in 0..h {
+                let y = h - 1 - row;
+                values[c][(z * w * h) + (y * w)..(z * w * h) + ((y + 1) * w)].copy_from_slice(&image[row * w..(row + 1) * w]);
+            }
+        }
+
+        Ok(values.into_iter().zip(dtypes).enumerate().map(|(c, (values, dtype))| Volume {
+            name: self.layout.channel_names.get(c).cloned().unwrap_or(format!("channel_{}", c)),
+            dims: dims,
+            dtype: dtype,
+            values: values,
+            transform: VoxelTransform::centred(&dims, self.layout.spacing)
+        }).collect())
+    }
+
+    /// Pixels element attributes and Channel names from OME-XML
+    fn ome_layout(xml: &str, stem: &str) -> Result<StackLayout, Box<dyn Error>> {
+        let pixels = *TiffStack::xml_elements(xml, "Pixels").first().ok_or("OME-XML has no Pixels element")?;
+        let attribute = |name: &str| TiffStack::xml_attribute(pixels, name);
+        let size = |name: &str| -> Result<usize, Box<dyn Error>> {
+            Ok(attribute(name).ok_or(format!("OME Pixels is missing {}", name))?.parse()?)
+        };
+        let physical = |axis: &str| -> f32 {
+            let value = attribute(&format!("PhysicalSize{}", axis)).and_then(|v| v.parse::<f32>().ok()).unwrap_or(1.0);
+            let microns = value * match attribute(&format!("PhysicalSize{}Unit", axis)).as_deref() {
+                Some("nm") => 0.001,
+                Some("mm") => 1000.0,
+                Some("cm") => 10000.0,
+                Some("m") => 1.0e6,
+                _ => 1.0, // µm, OME's default
+            };
+            if microns.is_finite() && microns > 0.0 { microns } else { 1.0 } // a zero spacing can't be inverted
+        };
+        let order: Vec<char> = attribute("DimensionOrder").unwrap_or("XYZCT".to_string()).chars().skip(2).collect();
+        let channels = size("SizeC")?;
+
+        let mut names: Vec<String> = TiffStack::xml_elements(xml, "Channel").iter().enumerate()
+            .map(|(c, channel)| TiffStack::xml_attribute(channel, "Name").unwrap_or(format!("{}_{}", stem, c))).collect();
+        names.truncate(channels); // later Images' channels
+        while names.len() < channels { names.push(format!("{}_{}", stem, names.len())); }
+
+        Ok(StackLayout {
+            metadata: StackMetadata::Ome,
+            size: [size("SizeX")?, size("SizeY")?, size("SizeZ")?, channels, size("SizeT")?],
+            order: order.try_into().map_err(|_| "OME DimensionOrder needs 5 axes")?,
+            spacing: [physical("X"), physical("Y"), physical("Z")],
+            channel_names: names
+        })
+    }
+
+    /// ImageJ hyperstacks are XYCZT, spacing= is z, x and y come from XResolution (pixels per unit)
+    fn imagej_layout(description: &str, width: u32, height: u32, pages: usize, resolution: Option<f32>, stem: &str) -> Result<StackLayout, Box<dyn Error>> {
+        let value = |key: &str| description.lines().find_map(|l| l.strip_prefix(&format!("{}=", key)).map(|v| v.trim().to_string()));
+        let count = |key: &str| -> Result<Option<usize>, Box<dyn Error>> {
+            match value(key) {
+                None => Ok(None),
+                Some(v) => v.parse::<usize>().ok().filter(|&n| n > 0).map(Some)
+                    .ok_or(format!("ImageJ {}={} isn't a positive count", key, v).into()),
+            }
+        };
+        let channels = count("channels")?.unwrap_or(1);
+        let frames = count("frames")?.unwrap_or(1);
+        let slices = count("slices")?.unwrap_or(count("images")?.unwrap_or(pages) / (channels * frames));
+        let positive = |v: f32| v.is_finite() && v > 0.0;
+        let xy = resolution.map(|r| 1.0 / r).filter(|&s| positive(s)).unwrap_or(1.0);
+        let z = value("spacing").and_then(|v| v.parse::<f32>().ok()).filter(|&s| positive(s)).unwrap_or(xy);
+        Ok(StackLayout {
+            metadata: StackMetadata::ImageJ,
+            size: [width as usize, height as usize, slices, channels, frames],
+            order: ['C', 'Z', 'T'],
+            spacing: [xy, xy, z],
+            channel_names: (0..channels).map(|c| if channels == 1 { stem.to_string() } else { format!("{}_{}", stem, c) }).collect()
+        })
+    }
+
+    /// Start tags (without their closing >) of every element named name, e.g. <Pixels SizeX="512" ...
+    fn xml_elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+        let tag = format!("<{}", name);
+        xml.match_indices(&tag).filter_map(|(i, _)| {
+            let after = xml[i + tag.len()..].chars().next()?;
+            if !(after.is_whitespace() || after == '>' || after == '/') { return None; } // a longer name, e.g. ChannelRef
+            xml[i..].find('>').map(|end| &xml[i..i + end])
+        }).collect()
+    }
+
+    /// Value of attribute name="..." in a start tag
+    fn xml_attribute(element: &str, name: &str) -> Option<String> {
+        let key = format!("{}=\"", name);
+        let start = element.match_indices(&key)
+            .find(|(i, _)| element[..*i].ends_with(char::is_whitespace))? // not the tail of a longer name
+            .0 + key.len();
+        let end = element[start..].find('"')? + start;
+        Some(element[start..end].to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiff::encoder::{colortype::Gray8, TiffEncoder};
+
+    #[test]
+    fn zero_imagej_counts_are_an_error() {
+        assert!(TiffStack::imagej_layout("ImageJ=\nchannels=0\n", 4, 4, 2, None, "s").is_err());
+        assert!(TiffStack::imagej_layout("ImageJ=\nframes=0\n", 4, 4, 2, None, "s").is_err());
+        let layout = TiffStack::imagej_layout("ImageJ=\nchannels=2\nslices=3\n", 4, 4, 6, Some(2.0), "s").unwrap();
+        assert_eq!(layout.size, [4, 4, 3, 2, 1]);
+        assert_eq!(layout.spacing, [0.5; 3]);
+    }
+
+    #[test]
+    fn imagej_spacing_must_be_positive() {
+        let layout = TiffStack::imagej_layout("ImageJ=\nspacing=0\n", 4, 4, 2, Some(0.0), "s").unwrap();
+        assert_eq!(layout.spacing, [1.0; 3]);
+        let layout = TiffStack::imagej_layout("ImageJ=\nspacing=-3\n", 4, 4, 2, Some(4.0), "s").unwrap();
+        assert_eq!(layout.spacing, [0.25; 3]);
+    }
+
+    #[test]
+    fn ome_pages_by_dimension_order() {
+        // XYCZT: channel fastest, then z, then time; page p is filled with p, its first row with p + 100
+        let xml = r#"<?xml version="1.0"?><OME><Image ID="Image:0"><Pixels DimensionOrder="XYCZT" Type="uint8"
+            SizeX="3" SizeY="2" SizeZ="2" SizeC="2" SizeT="3"
+            PhysicalSizeX="500" PhysicalSizeXUnit="nm" PhysicalSizeY="0" PhysicalSizeZ="0.002" PhysicalSizeZUnit="mm">
+            <Channel ID="Channel:0:0" Name="DAPI"/><Channel ID="Channel:0:1" Name="GFP"/></Pixels></Image></OME>"#;
+        let path = std::env::temp_dir().join("bocs_tiff_stack_ome_test.ome.tif");
+        let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+        for page in 0..12u8 {
+            let mut image = encoder.new_image::<Gray8>(3, 2).unwrap();
+            if page == 0 { image.encoder().write_tag(Tag::ImageDescription, xml).unwrap(); }
+            image.write_data(&[page + 100, page + 100, page + 100, page, page, page]).unwrap();
+        }
+        drop(encoder);
+
+        let stack = TiffStack::open(&path).unwrap();
+        let volumes = stack.load_time_point(2);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(stack.layout.metadata, StackMetadata::Ome);
+        assert_eq!(stack.layout.size, [3, 2, 2, 2, 3]);
+        assert_eq!(stack.layout.order, ['C', 'Z', 'T']);
+        assert_eq!(stack.layout.spacing, [0.5, 1.0, 2.0]); // microns, the zero y size falls back to 1
+        assert_eq!(stack.time_points(), 3);
+        assert_eq!(stack.layout.page(1, 1, 2), 1 + 2 + (2 * 4)); // c + z * SizeC + t * SizeC * SizeZ
+
+        let volumes = volumes.unwrap();
+        assert_eq!(volumes.iter().map(|v| v.name.as_str()).collect::<Vec<_>>(), ["DAPI", "GFP"]);
+        for (c, volume) in volumes.iter().enumerate() {
+            assert_eq!(volume.dims, [3, 2, 2]);
+            assert_eq!(volume.transform.spacing, [0.5, 1.0, 2.0]);
+            for z in 0..2 {
+                let page = (c + (z * 2) + (2 * 4)) as f32;
+                let slice = &volume.values[z * 6..(z + 1) * 6];
+                assert_eq!(slice, [page, page, page, page + 100.0, page + 100.0, page + 100.0]); // first row on top
+            }
+        }
+        assert!(stack.load_time_point(3).is_err());
+    }
+
+    #[test]
+    fn mismatched_page_sizes_are_an_error() {
+        let path = std::env::temp_dir().join("bocs_tiff_stack_pages_test.tif");
+        let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+        encoder.write_image::<Gray8>(4, 4, &[1; 16]).unwrap();
+        encoder.write_image::<Gray8>(2, 2, &[2; 4]).unwrap();
+        drop(encoder);
+
+        let stack = TiffStack::open(&path).unwrap();
+        assert_eq!(stack.layout.size, [4, 4, 2, 1, 1]);
+        let loaded = stack.load_time_point(0);
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::error::Error;
+
+/// Entries in the lookup table uploaded to the raymarch
+pub const TF_RESOLUTION: usize = 256;
+
+/// Colour half of a TransferFunction
+/// Stops are sRGB, evenly spaced over [0, 1] and linearly interpolated
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Cividis,
+    Greys,
+    Diverging, // cool to warm through grey, for fields centred on a rest value
+}
+
+const VIRIDIS: [[u8; 3]; 11] = [
+    [0x44, 0x01, 0x54], [0x48, 0x25, 0x76], [0x41, 0x44, 0x87], [0x35, 0x60, 0x8D],
+    [0x2A, 0x78, 0x8E], [0x21, 0x90, 0x8C], [0x22, 0xA8, 0x84], [0x43, 0xBF, 0x71],
+    [0x7A, 0xD1, 0x51], [0xBB, 0xDF, 0x27], [0xFD, 0xE7, 0x25]
+];
+const MAGMA: [[u8; 3]; 11] = [
+    [0x00, 0x00, 0x04], [0x14, 0x0E, 0x36], [0x3B, 0x0F, 0x70], [0x64, 0x1A, 0x80],
+    [0x8C, 0x29, 0x81], [0xB7, 0x37, 0x79], [0xDE, 0x49, 0x68], [0xF7, 0x70, 0x5C],
+    [0xFE, 0x9F, 0x6D], [0xFE, 0xCF, 0x92], [0xFC, 0xFD, 0xBF]
+];
+const CIVIDIS: [[u8; 3]; 10] = [
+    [0x00, 0x20, 0x4D], [0x00, 0x33, 0x6F], [0x39, 0x48, 0x6B], [0x57, 0x5C, 0x6D],
+    [0x70, 0x71, 0x73], [0x8A, 0x87, 0x79], [0xA6, 0x9D, 0x75], [0xC4, 0xB5, 0x6C],
+    [0xE4, 0xCF, 0x5B], [0xFF, 0xEA, 0x46]
+];
+const GREYS: [[u8; 3]; 2] = [
+    [0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]
+];
+const DIVERGING: [[u8; 3]; 5] = [
+    [0x3B, 0x4C, 0xC0], [0x8D, 0xB0, 0xFE], [0xDD, 0xDD, 0xDD], [0xF4, 0x9A, 0x7B], [0xB4, 0x04, 0x26]
+];
+
+impl Colormap {
+    fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Magma => &MAGMA,
+            Colormap::Cividis => &CIVIDIS,
+            Colormap::Greys => &GREYS,
+            Colormap::Diverging => &DIVERGING,
+        }
+    }
+
+    /// sRGB colour at t in [0, 1] (clamped)
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let stops = self.stops();
+        let x = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (x.floor() as usize).min(stops.len() - 2);
+        let w = x - i as f32;
+        let (a, b) = (stops[i], stops[i + 1]);
+        [0, 1, 2].map(|c| ((a[c] as f32 * (1.0 - w)) + (b[c] as f32 * w)) / 255.0)
+    }
+
+    /// Viridis -> Magma -> Cividis -> Greys -> Diverging -> Viridis
+    pub fn next(&self) -> Self {
+        match self {
+            Colormap::Viridis => Colormap::Magma,
+            Colormap::Magma => Colormap::Cividis,
+            Colormap::Cividis => Colormap::Greys,
+            Colormap::Greys => Colormap::Diverging,
+            Colormap::Diverging => Colormap::Viridis,
+        }
+    }
+}
+
+/// Opacity control point, position is normalised over TransferFunction::range
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpacityPoint {
+    pub position: f32, // [0, 1]
+    pub opacity: f32, // [0, 1], per unit step length along the ray
+}
+
+/// Maps voxel values to colour and opacity for the raymarch
+/// Values are normalised over range, coloured by colormap, and given the piecewise-linear
+/// opacity through the control points (held flat beyond the first and last point)
+/// Baked into a TF_RESOLUTION lookup table by lut() and uploaded with Resources::write_transfer_function()
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferFunction {
+    pub colormap: Colormap,
+    pub range: [f32; 2], // voxel values mapped to 0 and 1
+    pub opacity: Vec<OpacityPoint>, // sorted by position
+}
+
+impl Default for TransferFunction {
+    /// Viridis over [0, 1], transparent below 0.2 then ramping up
+    fn default() -> Self {
+        TransferFunction {
+            colormap: Colormap::Viridis,
+            range: [0.0, 1.0],
+            opacity: vec![
+                OpacityPoint { position: 0.0, opacity: 0.0 },
+                OpacityPoint { position: 0.2, opacity: 0.0 },
+                OpacityPoint { position: 1.0, opacity: 0.05 },
+            ]
+        }
+    }
+}
+
+impl TransferFunction {
+    /// Piecewise-linear opacity at normalised position t
+    pub fn opacity_at(&self, t: f32) -> f32 {
+        match self.opacity.iter().position(|p| p.position >= t) {
+            None => self.opacity.last().map_or(0.0, |p| p.opacity),
+            Some(0) => self.opacity[0].opacity,
+            Some(i) => {
+                let (a, b) = (self.opacity[i - 1], self.opacity[i]);
+                let w = (t - a.position) / (b.position - a.position).max(f32::EPSILON);
+                (a.opacity * (1.0 - w)) + (b.opacity * w)
+            }
+        }
+    }
+
+    /// [r, g, b, opacity] at TF_RESOLUTION evenly spaced positions over [0, 1]
+    pub fn lut(&self) -> Vec<[f32; 4]> {
+        (0..TF_RESOLUTION).map(|i| {
+            let t = i as f32 / (TF_RESOLUTION - 1) as f32;
+            let [r, g, b] = self.colormap.sample(t);
+            [r, g, b, self.opacity_at(t)]
+        }).collect()
+    }
+
+    /// Uniforms.transfer: [range min, range max, 0, 0]
+    pub fn uniform(&self) -> [f32; 4] {
+        [self.range[0], self.range[1], 0.0, 0.0]
+    }
+
+    /// Adds a control point, keeping them sorted (replaces one already at position)
+    pub fn insert_point(&mut self, position: f32, opacity: f32) {
+        let point = OpacityPoint { position: position.clamp(0.0, 1.0), opacity: opacity.clamp(0.0, 1.0) };
+        match self.opacity.iter().position(|p| p.position >= point.position) {
+            Some(i) if self.opacity[i].position == point.position => self.opacity[i] = point,
+            Some(i) => self.opacity.insert(i, point),
+            None => self.opacity.push(point),
+        }
+    }
+
+    pub fn remove_point(&mut self, index: usize) -> Option<OpacityPoint> {
+        if index < self.opacity.len() { Some(self.opacity.remove(index)) } else { None }
+    }
+
+    /// Multiplies every control point's opacity by k (clamped to [0, 1])
+    pub fn scale_opacity(&mut self, k: f32) {
+        for p in self.opacity.iter_mut() {
+            p.opacity = (p.opacity * k).clamp(0.0, 1.0);
+        }
+    }
+
+    pub fn save_json(&self, path: &std::path::Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load_json(path: &std::path::Path) -> Result<Self, Box<dyn Error>> {
+        let mut tf: TransferFunction = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if tf.range[0].partial_cmp(&tf.range[1]) != Some(Ordering::Less) { // NaN bounds are empty too
+            return Err(format!("Transfer function range {:?} is empty", tf.range).into());
+        }
+        if tf.opacity.is_empty() {
+            return Err("Transfer function has no opacity points".into());
+        }
+        if let Some(p) = tf.opacity.iter().find(|p| !p.position.is_finite() || !p.opacity.is_finite()) {
+            return Err(format!("Transfer function point {:?} isn't finite", p).into());
+        }
+        for p in tf.opacity.iter_mut() { // as insert_point()
+            p.position = p.position.clamp(0.0, 1.0);
+            p.opacity = p.opacity.clamp(0.0, 1.0);
+        }
+        tf.opacity.sort_by(|a, b| a.position.total_cmp(&b.position));
+        Ok(tf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp() -> TransferFunction {
+        TransferFunction {
+            colormap: Colormap::Greys,
+            range: [10.0, 20.0],
+            opacity: vec![
+                OpacityPoint { position: 0.25, opacity: 0.0 },
+                OpacityPoint { position: 0.75, opacity: 1.0 },
+            ]
+        }
+    }
+
+    #[test]
+    fn opacity_interpolates_between_points() {
+        let tf = ramp();
+        assert_eq!(tf.opacity_at(0.0), 0.0); // flat before the first point
+        assert_eq!(tf.opacity_at(0.25), 0.0);
+        assert!((tf.opacity_at(0.5) - 0.5).abs() < 1e-6);
+        assert!((tf.opacity_at(0.625) - 0.75).abs() < 1e-6);
+        assert_eq!(tf.opacity_at(0.75), 1.0);
+        assert_eq!(tf.opacity_at(1.0), 1.0); // and after the last
+    }
+
+    #[test]
+    fn lut_samples_colormap_and_opacity() {
+        let lut = ramp().lut();
+        assert_eq!(lut.len(), TF_RESOLUTION);
+        assert_eq!(lut[0], [0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(lut[TF_RESOLUTION - 1], [1.0, 1.0, 1.0, 1.0]);
+        let mid = TF_RESOLUTION / 2;
+        let t = mid as f32 / (TF_RESOLUTION - 1) as f32;
+        assert!((lut[mid][0] - t).abs() < 1e-6); // greys is linear
+        assert!((lut[mid][3] - (t - 0.25) * 2.0).abs() < 1e-6);
+        assert!(lut.iter().flatten().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let path = std::env::temp_dir().join("bocs_tf_round_trip_test.json");
+        let tf = ramp();
+        tf.save_json(&path).unwrap();
+        let loaded = TransferFunction::load_json(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), tf);
+    }
+
+    #[test]
+    fn load_clamps_points_and_rejects_empty_ranges() {
+        let load = |json: &str, file: &str| {
+            let path = std::env::temp_dir().join(file);
+            std::fs::write(&path, json).unwrap();
+            let loaded = TransferFunction::load_json(&path);
+            std::fs::remove_file(&path).unwrap();
+            loaded
+        };
+        let tf = load(r#"{ "colormap": "Viridis", "range": [0.0, 1.0], "opacity": [
+            { "position": 1.5, "opacity": 2.0 }, { "position": -1.0, "opacity": -0.5 } ] }"#, "bocs_tf_clamp_test.json").unwrap();
+        assert_eq!(tf.opacity, [OpacityPoint { position: 0.0, opacity: 0.0 }, OpacityPoint { position: 1.0, opacity: 1.0 }]);
+
+        assert!(load(r#"{ "colormap": "Viridis", "range": [1.0, 1.0], "opacity": [ { "position": 0.0, "opacity": 0.0 } ] }"#, "bocs_tf_range_test.json").is_err());
+        assert!(load(r#"{ "colormap": "Viridis", "range": [0.0, 1.0], "opacity": [] }"#, "bocs_tf_empty_test.json").is_err());
+    }
+}
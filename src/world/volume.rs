@@ -0,0 +1,333 @@
+use std::{collections::HashMap, error::Error, io::{Read, Write}, path::Path};
+use crate::world::{channels::ChannelType, voxel_grid::{Dims3, P3, VoxelTransform}};
+
+/// Byte order of multi-byte samples on disk
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/// How NRRD data is stored after the header
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Encoding {
+    Raw,
+    Gzip,
+}
+
+/// Describes a headerless .raw file, which carries nothing but samples (x fastest)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RawLayout {
+    pub dims: Dims3,
+    pub dtype: ChannelType,
+    pub endian: Endian,
+    pub header_bytes: usize, // skipped before the first sample
+}
+
+/// Scalar volume read from or written to disk, e.g. a microscopy stack
+/// Values are f32 whatever the file held, as the voxel buffers store them (see ChannelType)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    pub name: String,
+    pub dims: Dims3,
+    pub dtype: ChannelType, // sample type on disk
+    pub values: Vec<f32>, // x fastest, as the voxel buffers
+    pub transform: VoxelTransform, // spacing and placement from the file, centred unit voxels without one
+}
+
+impl Volume {
+    /// Reads a .nrrd (header and data in one file) or .nhdr (header naming a detached data file)
+    /// Supports dimension 3, raw and gzip encodings, u8/u16/u32/f32 samples, spacings or space directions and space origin
+    pub fn load_nrrd(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bytes = std::fs::read(path)?;
+        let header_end = bytes.windows(2).position(|w| w == b"\n\n").map(|i| i + 2).unwrap_or(bytes.len());
+        let header = std::str::from_utf8(&bytes[..header_end])?.replace('\r', "");
+        let mut lines = header.lines();
+        if !lines.next().unwrap_or("").starts_with("NRRD000") {
+            return Err(format!("{} isn't a NRRD file", path.display()).into());
+        }
+
+        // FIELDS, "key: value" (key/value pairs "key:=value" and comments skipped) //
+        let mut fields: HashMap<String, String> = HashMap::new();
+        for line in lines.filter(|l| !l.starts_with('#') && !l.is_empty()) {
+            if line.contains(":=") { continue; }
+            if let Some((key, value)) = line.split_once(": ") {
+                fields.insert(key.trim().to_lowercase().replace(' ', ""), value.trim().to_string());
+            }
+        }
+        let field = |key: &str| fields.get(key).map(|s| s.as_str());
+        let required = |key: &str| field(key).ok_or(format!("NRRD header is missing {}", key));
+
+        let dtype = match required("type")? {
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => ChannelType::U8,
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => ChannelType::U16,
+            "uint" | "unsigned int" | "uint32" | "uint32_t" => ChannelType::U32,
+            "float" => ChannelType::F32,
+            other => return Err(format!("Unsupported NRRD type {}", other).into()),
+        };
+        if required("dimension")? != "3" {
+            return Err(format!("Only 3D NRRDs are supported, not dimension {}", required("dimension")?).into());
+        }
+        let sizes: Vec<u32> = required("sizes")?.split_whitespace().map(|s| s.parse()).collect::<Result<_, _>>()?;
+        let dims: Dims3 = sizes.try_into().map_err(|_| "NRRD sizes need 3 entries")?;
+        if dims.contains(&0) {
+            return Err(format!("NRRD sizes {:?} hold an empty axis", dims).into());
+        }
+        let encoding = match required("encoding")? {
+            "raw" => Encoding::Raw,
+            "gzip" | "gz" => Encoding::Gzip,
+            other => return Err(format!("Unsupported NRRD encoding {}", other).into()),
+        };
+        let endian = if field("endian") == Some("big") { Endian::Big } else { Endian::Little };
+
+        // DATA, after the header or in the detached data file //
+        let data = match field("datafile") {
+            Some(file) => {
+                if file.starts_with("LIST") || file.split_whitespace().count() > 1 {
+                    return Err("Only single detached data files are supported".into());
+                }
+                std::fs::read(path.parent().unwrap_or(Path::new(".")).join(file))?
+            },
+            None => bytes[header_end..].to_vec(),
+        };
+        let line_skip: usize = field("lineskip").map(|s| s.parse()).transpose()?.unwrap_or(0);
+        let mut start = 0; // MUT
+        for _ in 0..line_skip {
+            start += data[start..].iter().position(|&b| b == b'\n').ok_or("NRRD line skip past the end of the data")? + 1;
+        }
+        let mut decoded = match encoding {
+            Encoding::Raw => data[start..].to_vec(),
+            Encoding::Gzip => {
+                let mut out = Vec::new();
+                flate2::read::MultiGzDecoder::new(&data[start..]).read_to_end(&mut out)?;
+                out
+            },
+        };
+        let expected = Volume::byte_count(dims, dtype)?;
+        let byte_skip: i64 = field("byteskip").map(|s| s.parse()).transpose()?.unwrap_or(0);
+        let skip = if byte_skip < 0 { decoded.len().saturating_sub(expected) } else { byte_skip as usize }; // -1: data ends the file
+        decoded.drain(..skip.min(decoded.len()));
+
+        let transform = Volume::nrrd_transform(&dims, field("spacedirections"), field("spacings"), field("spaceorigin"))?;
+        Ok(Volume {
+            name: field("content").map(|s| s.to_string()).unwrap_or(Volume::stem(path)),
+            dims: dims,
+            dtype: dtype,
+            values: Volume::decode(&decoded, dims, dtype, endian)?,
+            transform: transform
+        })
+    }
+
+    /// Reads a headerless volume, layout gives what the file can't
+    pub fn load_raw(path: &Path, layout: &RawLayout) -> Result<Self, Box<dyn Error>> {
+        if layout.dims.contains(&0) {
+            return Err(format!("Raw volume dims {:?} hold an empty axis", layout.dims).into());
+        }
+        let bytes = std::fs::read(path)?;
+        Ok(Volume {
+            name: Volume::stem(path),
+            dims: layout.dims,
+            dtype: layout.dtype,
+            values: Volume::decode(&bytes[layout.header_bytes.min(bytes.len())..], layout.dims, layout.dtype, layout.endian)?,
+            transform: VoxelTransform::centred(&layout.dims, [1.0; 3])
+        })
+    }
+
+    /// Writes an attached NRRD of f32 samples, with space directions and origin from the transform
+    pub fn save_nrrd(&self, path: &Path, encoding: Encoding) -> Result<(), Box<dyn Error>> {
+        let m = self.transform.matrix();
+        let first = self.transform.to_world([0.5; 3]); // NRRD places samples, not voxel corners
+        let vector = |v: P3| format!("({},{},{})", v[0], v[1], v[2]);
+
+        let mut out = Vec::new();
+        writeln!(out, "NRRD0004")?;
+        writeln!(out, "# bocs volume, {}x{}x{}", self.dims[0], self.dims[1], self.dims[2])?;
+        writeln!(out, "content: {}", self.name)?;
+        writeln!(out, "type: float")?;
+        writeln!(out, "dimension: 3")?;
+        writeln!(out, "space dimension: 3")?;
+        writeln!(out, "sizes: {} {} {}", self.dims[0], self.dims[1], self.dims[2])?;
+        writeln!(out, "space directions: {}", [0, 1, 2].map(|c| vector([m[0][c], m[1][c], m[2][c]])).join(" "))?;
+        writeln!(out, "space origin: {}", vector(first))?;
+        writeln!(out, "kinds: domain domain domain")?;
+        writeln!(out, "endian: little")?;
+        writeln!(out, "encoding: {}\n", match encoding { Encoding::Raw => "raw", Encoding::Gzip => "gzip" })?;
+
+        let samples: Vec<u8> = self.values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        match encoding {
+            Encoding::Raw => out.extend_from_slice(&samples),
+            Encoding::Gzip => {
+                let mut gz = flate2::write::GzEncoder::new(out, flate2::Compression::default());
+                gz.write_all(&samples)?;
+                out = gz.finish()?;
+            },
+        }
+        std::fs::write(path, out)?;
+        Ok(())
+    }
+
+    fn stem(path: &Path) -> String {
+        path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or("volume".to_string())
+    }
+
+    fn sample_bytes(dtype: ChannelType) -> usize {
+        match dtype {
+            ChannelType::U8 => 1,
+            ChannelType::U16 => 2,
+            ChannelType::U32 | ChannelType::F32 => 4,
+        }
+    }
+
+    /// Bytes dims samples of dtype take, an error where sizes from a header overflow
+    fn byte_count(dims: Dims3, dtype: ChannelType) -> Result<usize, Box<dyn Error>> {
+        dims.iter().try_fold(Volume::sample_bytes(dtype), |n, &d| n.checked_mul(d as usize))
+            .ok_or(format!("Volume of {}x{}x{} {:?} is too large", dims[0], dims[1], dims[2], dtype).into())
+    }
+
+    /// Samples -> f32, checking there are enough for dims (trailing bytes ignored)
+    fn decode(bytes: &[u8], dims: Dims3, dtype: ChannelType, endian: Endian) -> Result<Vec<f32>, Box<dyn Error>> {
+        let size = Volume::sample_bytes(dtype);
+        let needed = Volume::byte_count(dims, dtype)?;
+        if bytes.len() < needed {
+            return Err(format!("Volume data holds {} bytes, {}x{}x{} {:?} needs {}", bytes.len(), dims[0], dims[1], dims[2], dtype, needed).into());
+        }
+        let word = |b: &[u8]| -> [u8; 4] {
+            let mut w = [0; 4];
+            match endian { // widen to 4 little endian bytes
+                Endian::Little => w[..b.len()].copy_from_slice(b),
+                Endian::Big => for (i, &x) in b.iter().rev().enumerate() { w[i] = x; },
+            }
+            w
+        };
+        Ok(bytes[..needed].chunks_exact(size).map(|b| match dtype {
+            ChannelType::F32 => f32::from_le_bytes(word(b)),
+            _ => u32::from_le_bytes(word(b)) as f32,
+        }).collect())
+    }
+
+    /// Space directions (one world vector per axis) or spacings, and the first sample's position
+    fn nrrd_transform(dims: &Dims3, directions: Option<&str>, spacings: Option<&str>, origin: Option<&str>) -> Result<VoxelTransform, Box<dyn Error>> {
+        let parse_vector = |s: &str| -> Result<P3, Box<dyn Error>> {
+            let v: Vec<f32> = s.trim_matches(|c| c == '(' || c == ')').split(',').map(|x| x.trim().parse()).collect::<Result<_, _>>()?;
+            v.try_into().map_err(|_| format!("NRRD vector {} needs 3 entries", s).into())
+        };
+
+        let mut spacing = [1.0; 3]; // MUT
+        let mut affine = None; // MUT
+        if let Some(directions) = directions {
+            let columns: Vec<P3> = directions.split_whitespace().map(parse_vector).collect::<Result<_, _>>()?;
+            if columns.len() != 3 { return Err("NRRD space directions need 3 vectors".into()); }
+            spacing = [0, 1, 2].map(|c| columns[c].iter().map(|x| x * x).sum::<f32>().sqrt());
+            if spacing.iter().any(|s| !s.is_finite() || *s <= f32::EPSILON) {
+                return Err(format!("NRRD space directions {} hold a zero or non-finite vector", directions).into());
+            }
+            let a = [0, 1, 2].map(|r| [0, 1, 2].map(|c| columns[c][r] / spacing[c]));
+            if VoxelTransform::centred_affine(dims, [1.0; 3], Some(a)).determinant().abs() < 1e-6 {
+                return Err(format!("NRRD space directions {} don't span 3D", directions).into());
+            }
+            let identity = (0..3).all(|r| (0..3).all(|c| (a[r][c] - if r == c { 1.0 } else { 0.0 }).abs() < 1e-6));
+            if !identity { affine = Some(a); }
+        } else if let Some(spacings) = spacings {
+            let s: Vec<f32> = spacings.split_whitespace().map(|x| x.parse::<f32>().map(|v| if v.is_finite() { v } else { 1.0 })).collect::<Result<_, _>>()?;
+            spacing = s.try_into().map_err(|_| "NRRD spacings need 3 entries")?;
+            if spacing.iter().any(|s| *s <= 0.0) { // nan (no spacing) already became 1
+                return Err(format!("NRRD spacings {} need to be positive", spacings).into());
+            }
+        }
+
+        let mut transform = VoxelTransform::centred_affine(dims, spacing, affine);
+        if let Some(origin) = origin {
+            let first = parse_vector(origin)?;
+            let half = transform.to_world([0.5; 3]);
+            let corner = transform.origin;
+            transform.origin = [0, 1, 2].map(|i| first[i] - (half[i] - corner[i])); // grid corner half a voxel before the first sample
+        }
+        Ok(transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume() -> Volume {
+        let dims = [3, 2, 4];
+        Volume {
+            name: "test".to_string(),
+            dims: dims,
+            dtype: ChannelType::F32,
+            values: (0..24).map(|i| i as f32 * 0.5 - 3.0).collect(),
+            transform: VoxelTransform::centred(&dims, [0.5, 1.0, 2.0])
+        }
+    }
+
+    fn round_trip(encoding: Encoding, file: &str) {
+        let path = std::env::temp_dir().join(file);
+        let saved = volume();
+        saved.save_nrrd(&path, encoding).unwrap();
+        let loaded = Volume::load_nrrd(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.name, saved.name);
+        assert_eq!(loaded.dims, saved.dims);
+        assert_eq!(loaded.dtype, ChannelType::F32);
+        assert_eq!(loaded.values, saved.values);
+        for i in 0..3 {
+            assert!((loaded.transform.spacing[i] - saved.transform.spacing[i]).abs() < 1e-6);
+            assert!((loaded.transform.origin[i] - saved.transform.origin[i]).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        round_trip(Encoding::Raw, "bocs_volume_raw_test.nrrd");
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        round_trip(Encoding::Gzip, "bocs_volume_gzip_test.nrrd");
+    }
+
+    fn load_header(header: &str, file: &str) -> Result<Volume, Box<dyn Error>> {
+        let path = std::env::temp_dir().join(file);
+        let mut bytes = format!("NRRD0004\ntype: uchar\ndimension: 3\nencoding: raw\n{}\n", header).into_bytes();
+        bytes.extend_from_slice(&[1; 8]);
+        std::fs::write(&path, bytes).unwrap();
+        let loaded = Volume::load_nrrd(&path);
+        std::fs::remove_file(&path).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn singular_spacings_are_an_error() {
+        assert!(load_header("sizes: 2 2 2\nspacings: 1 1 1\n", "bocs_volume_spacings_ok_test.nrrd").is_ok());
+        assert!(load_header("sizes: 2 2 2\nspacings: nan 2 1\n", "bocs_volume_spacings_nan_test.nrrd").is_ok()); // no spacing given
+        assert!(load_header("sizes: 2 2 2\nspacings: 0 1 1\n", "bocs_volume_spacings_zero_test.nrrd").is_err());
+        assert!(load_header("sizes: 2 2 2\nspacings: 1 -1 1\n", "bocs_volume_spacings_negative_test.nrrd").is_err());
+        assert!(load_header("sizes: 2 2 2\nspace directions: (1,0,0) (0,0,0) (0,0,1)\n", "bocs_volume_directions_zero_test.nrrd").is_err());
+        assert!(load_header("sizes: 2 2 2\nspace directions: (1,0,0) (0,1,0) (1,1,0)\n", "bocs_volume_directions_flat_test.nrrd").is_err());
+        let sheared = load_header("sizes: 2 2 2\nspace directions: (1,0,0) (1,1,0) (0,0,2)\n", "bocs_volume_directions_ok_test.nrrd").unwrap();
+        assert!(sheared.transform.is_invertible());
+    }
+
+    #[test]
+    fn empty_axes_are_an_error() {
+        assert!(load_header("sizes: 0 2 2\n", "bocs_volume_empty_test.nrrd").is_err());
+        let layout = RawLayout { dims: [2, 0, 2], dtype: ChannelType::U8, endian: Endian::Little, header_bytes: 0 };
+        let path = std::env::temp_dir().join("bocs_volume_empty_test.raw");
+        std::fs::write(&path, [1; 8]).unwrap();
+        let loaded = Volume::load_raw(&path, &layout);
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err());
+    }
+
+    #[test]
+    fn huge_sizes_are_an_error() {
+        let path = std::env::temp_dir().join("bocs_volume_huge_test.nrrd");
+        std::fs::write(&path, "NRRD0004\ntype: float\ndimension: 3\nsizes: 65536 65536 2\nencoding: raw\n\n").unwrap();
+        let loaded = Volume::load_nrrd(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_err());
+        assert!(Volume::byte_count([u32::MAX; 3], ChannelType::U16).is_err());
+    }
+}
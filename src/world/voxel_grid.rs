@@ -1,3 +1,5 @@
+use crate::world::channels::Channels;
+
 /// Rust-side representation of Voxel Grid
 /// naming convention agnostic -
 /// not ijk, xyz, can be anything -
@@ -192,13 +194,116 @@ impl Access<usize, P3> for Cuboid {
     }
 }
 
+/// Places voxel coordinates (grid corner at the origin, voxel i spans [i, i + 1)) in the world, in physical units
+/// such as microns: world = origin + affine * (spacing * voxel)
+/// Camera, bounding box projection and raymarch rays all live in the world, the raymarch steps in voxel coordinates
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoxelTransform {
+    pub spacing: P3, // voxel edge length along x, y, z
+    pub origin: P3, // world position of the grid corner
+    pub affine: Option<[[f32; 3]; 3]>, // rotation and/or shear after spacing, row-major, None for identity
+}
+
+impl VoxelTransform {
+    /// Axis-aligned grid of the given spacing centred at the world origin (unit cubes for [1.0; 3])
+    pub fn centred(dims: &Dims3, spacing: P3) -> Self {
+        VoxelTransform::centred_affine(dims, spacing, None)
+    }
+
+    /// As centred(), with the affine applied about the grid centre
+    pub fn centred_affine(dims: &Dims3, spacing: P3, affine: Option<[[f32; 3]; 3]>) -> Self {
+        let mut transform = VoxelTransform { spacing: spacing, origin: [0.0; DIMS], affine: affine };
+        let centre = transform.to_world([dims[0] as f32 / 2.0, dims[1] as f32 / 2.0, dims[2] as f32 / 2.0]);
+        transform.origin = [-centre[0], -centre[1], -centre[2]];
+        transform
+    }
+
+    /// affine * diag(spacing), maps voxel offsets to world offsets
+    pub fn matrix(&self) -> [[f32; 3]; 3] {
+        let a = self.affine.unwrap_or([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        a.map(|row| [row[0] * self.spacing[0], row[1] * self.spacing[1], row[2] * self.spacing[2]])
+    }
+
+    /// matrix()'s inverse, maps world offsets to voxel offsets
+    pub fn inverse(&self) -> [[f32; 3]; 3] {
+        let m = self.matrix();
+        let det = self.determinant();
+        assert!(self.is_invertible(), "Voxel transform is singular\n");
+        [0, 1, 2].map(|r| [0, 1, 2].map(|c| VoxelTransform::cofactor(&m, c, r) / det)) // transposed cofactors
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = self.matrix();
+        (0..3).map(|c| m[0][c] * VoxelTransform::cofactor(&m, 0, c)).sum()
+    }
+
+    /// False for zero or non-finite spacings and degenerate affines, which inverse() can't handle
+    pub fn is_invertible(&self) -> bool {
+        let det = self.determinant();
+        det.is_finite() && det.abs() > f32::EPSILON
+    }
+
+    /// True when the transform mirrors the grid (negative determinant), flipping triangle winding
+    pub fn mirrors(&self) -> bool {
+        self.determinant() < 0.0
+    }
+
+    fn cofactor(m: &[[f32; 3]; 3], r: usize, c: usize) -> f32 {
+        let (r0, r1, c0, c1) = ((r + 1) % 3, (r + 2) % 3, (c + 1) % 3, (c + 2) % 3);
+        (m[r0][c0] * m[r1][c1]) - (m[r0][c1] * m[r1][c0])
+    }
+
+    pub fn to_world(&self, v: P3) -> P3 {
+        let m = self.matrix();
+        [0, 1, 2].map(|r| self.origin[r] + (m[r][0] * v[0]) + (m[r][1] * v[1]) + (m[r][2] * v[2]))
+    }
+
+    pub fn to_voxel(&self, w: P3) -> P3 {
+        let inv = self.inverse();
+        let d = [w[0] - self.origin[0], w[1] - self.origin[1], w[2] - self.origin[2]];
+        [0, 1, 2].map(|r| (inv[r][0] * d[0]) + (inv[r][1] * d[1]) + (inv[r][2] * d[2]))
+    }
+
+    /// Plane normal in the world -> the same plane's normal in voxel coordinates (matrix() transposed)
+    pub fn normal_to_voxel(&self, n: P3) -> P3 {
+        let m = self.matrix();
+        [0, 1, 2].map(|c| (m[0][c] * n[0]) + (m[1][c] * n[1]) + (m[2][c] * n[2]))
+    }
+
+    /// Voxel space gradient or normal -> world normal (inverse() transposed)
+    pub fn normal_to_world(&self, n: P3) -> P3 {
+        let inv = self.inverse();
+        [0, 1, 2].map(|c| (inv[0][c] * n[0]) + (inv[1][c] * n[1]) + (inv[2][c] * n[2]))
+    }
+
+    /// Physical length of the grid's edges along x, y, z
+    pub fn extent(&self, dims: &Dims3) -> P3 {
+        let m = self.matrix();
+        [0, 1, 2].map(|c| ((m[0][c] * m[0][c]) + (m[1][c] * m[1][c]) + (m[2][c] * m[2][c])).sqrt() * dims[c] as f32)
+    }
+
+    /// Uniforms.world_from_voxel and voxel_from_world: rows of matrix() and inverse() with the translation in w
+    pub fn uniform(&self) -> ([[f32; 4]; 3], [[f32; 4]; 3]) {
+        let (m, inv) = (self.matrix(), self.inverse());
+        let shift = self.to_voxel([0.0; DIMS]); // voxel coordinates of the world origin
+        (
+            [0, 1, 2].map(|r| [m[r][0], m[r][1], m[r][2], self.origin[r]]),
+            [0, 1, 2].map(|r| [inv[r][0], inv[r][1], inv[r][2], shift[r]])
+        )
+    }
+}
+
 /// I chose to keep Camera and VoxelGrid totally separately
 /// in future I may implement a builder that takes a Camera instance 
 /// this way ruf_cuboid can be populated and ruf_is_stale is false
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct VoxelGrid {
     pub dims: Dims3,
 
+    pub transform: VoxelTransform, // voxel coordinates -> world, physical spacing, origin and affine
+
+    pub channels: Channels, // named layers, laid out channel-major in the voxel buffers
+
     pub ruf_is_stale: bool, // triggered on any change to VoxelGrid or Camera
 
     pub world_cuboid: Cuboid, // vertices of voxel grid in worldspace
@@ -215,31 +320,22 @@ pub struct VoxelGrid {
 /// VoxelGrid is not purely geometric - the coordinate system matters for simulation
 /// and visualisation logic
 impl VoxelGrid {
+    /// Unit cube voxels, grid centred at the world origin
     pub fn new_centered_at_origin(dims: Dims3) -> Self {
-        let offset_dims = [(dims[0]/2) as f32, (dims[1]/2) as f32, (dims[2]/2) as f32];
-
-        let near_face: CuboidFace = CuboidFace {
-            p1: [-offset_dims[0], -offset_dims[1], -offset_dims[2]],
-            p2: [-offset_dims[0], offset_dims[1], -offset_dims[2]],
-            p3: [offset_dims[0], offset_dims[1], -offset_dims[2]],
-            p4: [offset_dims[0], -offset_dims[1], -offset_dims[2]]
-        };
+        VoxelGrid::new_with_transform(dims, VoxelTransform::centred(&dims, [1.0; DIMS]))
+    }
 
-        let far_face: CuboidFace = CuboidFace {
-            p1: [-offset_dims[0], -offset_dims[1], offset_dims[2]],
-            p2: [-offset_dims[0], offset_dims[1], offset_dims[2]],
-            p3:  [offset_dims[0], offset_dims[1], offset_dims[2]],
-            p4:  [offset_dims[0], -offset_dims[1], offset_dims[2]]
-        };
-      
+    /// Grid placed in the world by transform, e.g. microscopy spacing in microns
+    pub fn new_with_transform(dims: Dims3, transform: VoxelTransform) -> Self {
         // FOR EXAMPLE - rh coordinates looking down k,-ijk first (i major, k minor), bottom left, counterclockwise 
         Self {
             dims: dims,
 
-            world_cuboid: Cuboid {
-                f1: near_face, // centered at origin using offsets
-                f2: far_face 
-            },
+            transform: transform,
+
+            channels: Channels::new(),
+
+            world_cuboid: VoxelGrid::world_cuboid(&dims, &transform),
 
             ruf_is_stale: true, // always true on creation
 
@@ -248,7 +344,44 @@ impl VoxelGrid {
 
             onto_plane: [Square::<[i32; 2]>::default(); 2] // 2D PROJECTION
         }
-    }    
+    }
+
+    /// Moves the grid in the world, keeping its channels
+    pub fn set_transform(&mut self, transform: VoxelTransform) {
+        self.transform = transform;
+        self.world_cuboid = VoxelGrid::world_cuboid(&self.dims, &transform);
+        self.ruf_is_stale = true;
+    }
+
+    /// Grid corners in the world: near face (voxel z = 0) then far face, each CCW from bottom left
+    fn world_cuboid(dims: &Dims3, transform: &VoxelTransform) -> Cuboid {
+        let (x, y, z) = (dims[0] as f32, dims[1] as f32, dims[2] as f32);
+        let face = |depth: f32| CuboidFace {
+            p1: transform.to_world([0.0, 0.0, depth]),
+            p2: transform.to_world([0.0, y, depth]),
+            p3: transform.to_world([x, y, depth]),
+            p4: transform.to_world([x, 0.0, depth])
+        };
+        Cuboid {
+            f1: face(0.0),
+            f2: face(z)
+        }
+    }
+
+    /// Voxels per channel
+    pub fn voxels(&self) -> usize {
+        self.dims.iter().map(|&d| d as usize).product()
+    }
+
+    /// Mean of the 8 world vertices
+    pub fn centre(&self) -> P3 {
+        let mut sum = [0.0; DIMS];
+        for i in 0..8 {
+            let vertex = self.world_cuboid.get_vertex_at(i);
+            for axis in 0..DIMS { sum[axis] += vertex[axis]; }
+        }
+        [sum[0] / 8.0, sum[1] / 8.0, sum[2] / 8.0]
+    }
 }
 
 impl Access<SystemGet, SystemSet> for VoxelGrid {
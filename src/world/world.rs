@@ -1,10 +1,18 @@
-use crate::{backend_admin::gpu::gfx_context::GraphicsContext, world::{camera::FPVCamera, voxel_grid::{P2i, Access, SystemGet, SystemSet, VoxelGrid, Dims3, P3}}};
+use winit::dpi::PhysicalSize;
+use crate::{backend_admin::gpu::gfx_context::GraphicsContext, world::{camera::{Camera, OrbitalCamera}, clipping::{ClipPlane, Clipping}, diffusion::SimulationParams, labels::LabelVolume, rendering::RenderSettings, slices::Slices, transfer_function::TransferFunction, voxel_grid::{P2i, Access, SystemGet, SystemSet, VoxelGrid, VoxelTransform, Dims3, P3}}};
 
 /// Manages all World entities
 pub struct World {
     pub voxel_grid: VoxelGrid,
+    pub labels: Option<LabelVolume>, // segmentation drawn over the grid, see State::set_labels()
+    pub clipping: Clipping, // clip planes and crop box in the grid's voxel coordinates
     pub bbox: BoundingBox,
-    pub camera: FPVCamera,
+    pub camera: Camera, // orbits the voxel grid by default, see World::toggle_camera()
+    pub sim: SimulationParams, // D, spacing, timestep and boundary conditions of the diffusion step
+    pub transfer: TransferFunction, // voxel value -> colour and opacity in the raymarch
+    pub render: RenderSettings, // raymarch step size, early exit and jitter
+    pub slices: Slices, // orthogonal slice views
+    pub frame: u32, // frames rendered, seeds the ray jitter
     pub right_sf: f32
 }
 
@@ -13,26 +21,93 @@ pub type BoundingBox = [P2i; 2];
 impl World {
     pub fn new(d: Dims3, gfx_ctx: &GraphicsContext) -> Self {
         assert!(d[0] > 0 && d[1] > 0 && d[2] > 0);
-        let cam_init: P3 = [d[0] as f32 * 2.0, 0.0, 0.0];
+        let voxel_grid = VoxelGrid::new_centered_at_origin(d);
+        let camera = World::framing_camera(&voxel_grid, &gfx_ctx.size);
         World {
-            voxel_grid: VoxelGrid::new_centered_at_origin(d),
+            voxel_grid: voxel_grid,
+            labels: None,
+            clipping: Clipping::new(&d),
             bbox: BoundingBox::default(),
-            camera: FPVCamera::new(cam_init, &gfx_ctx.size),
+            camera: camera,
+            sim: SimulationParams::default(),
+            transfer: TransferFunction::default(),
+            render: RenderSettings::default(),
+            slices: Slices::new(&d),
+            frame: 0,
             right_sf: 0.0
         }
     }
 
-    /// Projects 8 P3 vertices of VoxelGrid onto camera's near plane as 4 P2s
+    /// Places the grid in the world in physical units, reframing the camera around it
+    /// (SimulationParams::spacing is separate, see State::set_voxel_transform())
+    pub fn set_voxel_transform(&mut self, transform: VoxelTransform, size: &PhysicalSize<u32>) {
+        self.voxel_grid.set_transform(transform);
+        self.camera = World::framing_camera(&self.voxel_grid, size);
+    }
+
+    /// OrbitalCamera around the grid centre, at orbit_distance() and with fly_speed()
+    fn framing_camera(voxel_grid: &VoxelGrid, size: &PhysicalSize<u32>) -> Camera {
+        let target: P3 = voxel_grid.centre();
+        let mut camera = Camera::Orbital(OrbitalCamera::new(target, World::orbit_distance(voxel_grid), size));
+        camera.view_mut().max_speed = World::fly_speed(voxel_grid);
+        camera
+    }
+
+    /// Starting distance between an OrbitalCamera and the grid centre
+    fn orbit_distance(voxel_grid: &VoxelGrid) -> f32 {
+        World::longest_edge(voxel_grid) * 2.0
+    }
+
+    /// FPVCamera terminal speed: half the grid's longest edge per second
+    fn fly_speed(voxel_grid: &VoxelGrid) -> f32 {
+        World::longest_edge(voxel_grid) * 0.5
+    }
+
+    /// In world units
+    fn longest_edge(voxel_grid: &VoxelGrid) -> f32 {
+        let e = voxel_grid.transform.extent(&voxel_grid.dims);
+        e[0].max(e[1]).max(e[2])
+    }
+
+    /// Clip plane through the crop box centre facing along the view, cutting away the half nearest the camera
+    /// False once Clipping holds MAX_CLIP_PLANES
+    pub fn add_view_clip_plane(&mut self) -> bool {
+        let crop = self.clipping.crop;
+        let centre = [(crop.min[0] + crop.max[0]) / 2.0, (crop.min[1] + crop.max[1]) / 2.0, (crop.min[2] + crop.max[2]) / 2.0];
+        let normal = self.voxel_grid.transform.normal_to_voxel(self.camera.view().f);
+        self.clipping.add_plane(ClipPlane { point: centre, normal: normal })
+    }
+
+    /// FPV <-> Orbital about the grid centre
+    pub fn toggle_camera(&mut self) {
+        self.camera.toggle(self.voxel_grid.centre(), World::orbit_distance(&self.voxel_grid));
+    }
+
+    /// Widest span of the projected crop box in pixels, unclamped by the window unlike bbox
+    /// Valid after generate_bb_projection(), OmeZarr::level_for() picks a level by it
+    pub fn projected_extent(&self) -> f32 {
+        let (mut min, mut max) = ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]); // MUT
+        for i in 0..8 {
+            if let SystemSet::SQUARE(point) = self.voxel_grid.get_vertex_at(SystemGet::SQUARE(i)) {
+                for a in 0..2 {
+                    min[a] = min[a].min(point[a] as f32);
+                    max[a] = max[a].max(point[a] as f32);
+                }
+            }
+        }
+        (max[0] - min[0]).max(max[1] - min[1])
+    }
+
+    /// Projects 8 P3 vertices of VoxelGrid (its crop box, see Clipping) onto camera's near plane as 4 P2s
     /// This is the minimum enclosing square for the voxel_grid (bounding box)
-    pub fn generate_bb_projection(&mut self, gfx_ctx: &GraphicsContext) {
+    /// size: the storage texture being drawn (window, or an offscreen capture)
+    pub fn generate_bb_projection(&mut self, size: &PhysicalSize<u32>) {
         // First: convert from pixels into world units
-        let (w, h) = (gfx_ctx.size.width as i32, gfx_ctx.size.height as i32);
+        let (w, h) = (size.width as i32, size.height as i32);
 
         let centre_top = h / 2; // 1:1 vertical pixels and up vector
-        self.right_sf = (w as f32 / 2.0) / centre_top as f32; // Right vector's scaling factor from pixels to world garantees FOV 90 in vertical
-
-        println!("Right scale: {}", self.right_sf);
-        let centre_right = centre_top as f32 * self.right_sf; // Opp = tan(theta) * adj
+        self.right_sf = 1.0; // square pixels: FOV 90 in vertical, horizontal FOV widens with the aspect
+        let centre_right = w as f32 / 2.0;
         
         self.bbox = { 
             let mut max_r = f32::NEG_INFINITY;
@@ -40,18 +115,16 @@ impl World {
             let mut min_r = f32::INFINITY;
             let mut min_u = f32::INFINITY;
 
+            // crop box corners stand in for the grid's, the whole grid unless cropped
+            let corners = self.clipping.crop.world_corners(&self.voxel_grid.transform);
             for i in 0..8 {
-                // VOXEL VERTICES INTO RUF
-                match self.voxel_grid.get_vertex_at(SystemGet::WORLD(i)) {
-                    SystemSet::WORLD(point) => {
-                        let ruf_point = self.camera.world_to_ruf(&point);
-                        self.voxel_grid.set_vertex_at(SystemGet::RUF(i), SystemSet::RUF(ruf_point))
-                    },
-                    _ => { println!("Couldn't get voxelgrid WORLD vertex.\n"); } }
+                // CROP BOX VERTICES INTO RUF
+                let ruf_point = self.camera.view().world_to_ruf(&corners[i]);
+                self.voxel_grid.set_vertex_at(SystemGet::RUF(i), SystemSet::RUF(ruf_point));
                 // PROJECT ONTO NEAR PLANE
                 match self.voxel_grid.get_vertex_at(SystemGet::RUF(i)) {
                     SystemSet::RUF(point) => {
-                        let projection = self.camera.ruf_to_ru_plane(&point, &(self.right_sf));
+                        let projection = self.camera.view().ruf_to_ru_plane(&point, &(self.right_sf));
                         self.voxel_grid.set_vertex_at(SystemGet::SQUARE(i), SystemSet::SQUARE(projection));
                     },
                     _ => { println!("Couldn't get voxelgrid RUF vertex.\n"); }
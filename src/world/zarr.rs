@@ -0,0 +1,678 @@
+use std::{error::Error, io::Read, path::{Path, PathBuf}};
+use serde_json::Value;
+use crate::world::{channels::ChannelType, volume::Endian, voxel_grid::{Dims3, P3, VoxelTransform}};
+
+/// Bytes of blosc's own header, before its block offsets
+const BLOSC_HEADER: usize = 16;
+
+/// Codec a Zarr v2 array's chunks are stored with (.zarray "compressor")
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compressor {
+    Blosc, // lz4, lz4hc, zlib or zstd inside, byte shuffled or not (blosclz, snappy and bit shuffle are not supported)
+    Zstd,
+    Gzip,
+    Zlib,
+}
+
+/// One Zarr v2 array on local disk: open() reads its .zarray, chunks are decoded on request
+/// u8/u16/u32/f32/f64 samples in C order, f64 read as F32
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZarrArray {
+    pub path: PathBuf,
+    pub shape: Vec<usize>,
+    pub chunks: Vec<usize>, // chunk shape, edge chunks are stored whole
+    pub dtype: ChannelType,
+    pub endian: Endian,
+    pub compressor: Option<Compressor>,
+    pub fill_value: f32, // for chunks missing on disk
+    pub separator: String, // between chunk indices in chunk file names, "." or "/"
+    sample_bytes: usize,
+}
+
+impl ZarrArray {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let meta: Value = serde_json::from_str(&std::fs::read_to_string(path.join(".zarray"))?)?;
+        if meta["zarr_format"].as_u64() != Some(2) {
+            return Err(format!("{} isn't a Zarr v2 array", path.display()).into());
+        }
+        let sizes = |key: &str| -> Result<Vec<usize>, Box<dyn Error>> {
+            let list = meta[key].as_array().ok_or(format!(".zarray is missing {}", key))?;
+            Ok(list.iter().map(|v| v.as_u64().map(|n| n as usize)).collect::<Option<_>>().ok_or(format!(".zarray {} isn't a list of sizes", key))?)
+        };
+        let (shape, chunks) = (sizes("shape")?, sizes("chunks")?);
+        if shape.len() != chunks.len() || chunks.contains(&0) {
+            return Err(format!("{} chunks {:?} don't fit shape {:?}", path.display(), chunks, shape).into());
+        }
+        if meta["order"].as_str() == Some("F") {
+            return Err("Only C order Zarr arrays are supported".into());
+        }
+        if meta["filters"].as_array().is_some_and(|f| !f.is_empty()) {
+            return Err("Zarr filters are not supported".into());
+        }
+
+        let dtype = meta["dtype"].as_str().ok_or(".zarray dtype isn't a string, structured types are not supported")?;
+        let endian = if dtype.starts_with('>') { Endian::Big } else { Endian::Little };
+        let (channel_type, sample_bytes) = match dtype.trim_start_matches(['<', '>', '|']) {
+            "u1" => (ChannelType::U8, 1),
+            "u2" => (ChannelType::U16, 2),
+            "u4" => (ChannelType::U32, 4),
+            "f4" => (ChannelType::F32, 4),
+            "f8" => (ChannelType::F32, 8),
+            other => return Err(format!("Unsupported Zarr dtype {}", other).into()),
+        };
+        let compressor = match meta["compressor"]["id"].as_str() {
+            None => None,
+            Some("blosc") => Some(Compressor::Blosc),
+            Some("zstd") => Some(Compressor::Zstd),
+            Some("gzip") => Some(Compressor::Gzip),
+            Some("zlib") => Some(Compressor::Zlib),
+            Some(other) => return Err(format!("Unsupported Zarr compressor {}", other).into()),
+        };
+
+        Ok(ZarrArray {
+            path: path.to_path_buf(),
+            shape: shape,
+            chunks: chunks,
+            dtype: channel_type,
+            endian: endian,
+            compressor: compressor,
+            fill_value: meta["fill_value"].as_f64().unwrap_or(0.0) as f32, // null or NaN strings read as 0
+            separator: meta["dimension_separator"].as_str().unwrap_or(".").to_string(),
+            sample_bytes: sample_bytes
+        })
+    }
+
+    /// Chunks along each axis
+    pub fn chunk_grid(&self) -> Vec<usize> {
+        self.shape.iter().zip(&self.chunks).map(|(s, c)| s.div_ceil(*c)).collect()
+    }
+
+    /// Every sample of chunk index (one entry per axis), C order over the full chunk shape
+    /// Chunks missing on disk hold fill_value
+    pub fn read_chunk(&self, index: &[usize]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let count: usize = self.chunks.iter().product();
+        let name: Vec<String> = index.iter().map(|i| i.to_string()).collect();
+        let bytes = match std::fs::read(self.path.join(name.join(&self.separator))) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![self.fill_value; count]),
+            Err(e) => return Err(e.into()),
+        };
+        let raw = match self.compressor {
+            None => bytes,
+            Some(Compressor::Blosc) => ZarrArray::blosc_decompress(&bytes, count * self.sample_bytes)?,
+            Some(Compressor::Zstd) => {
+                let mut out = Vec::new();
+                ruzstd::decoding::StreamingDecoder::new(&bytes[..]).map_err(|e| format!("zstd: {}", e))?.read_to_end(&mut out)?;
+                out
+            },
+            Some(Compressor::Gzip) => {
+                let mut out = Vec::new();
+                flate2::read::MultiGzDecoder::new(&bytes[..]).read_to_end(&mut out)?;
+                out
+            },
+            Some(Compressor::Zlib) => {
+                let mut out = Vec::new();
+                flate2::read::ZlibDecoder::new(&bytes[..]).read_to_end(&mut out)?;
+                out
+            },
+        };
+        if raw.len() < count * self.sample_bytes {
+            return Err(format!("Chunk {:?} of {} holds {} bytes, expected {}", index, self.path.display(), raw.len(), count * self.sample_bytes).into());
+        }
+
+        let big = self.endian == Endian::Big;
+        Ok(raw[..count * self.sample_bytes].chunks_exact(self.sample_bytes).map(|b| {
+            let mut w = [0u8; 8];
+            w[..b.len()].copy_from_slice(b);
+            if big { w[..b.len()].reverse(); } // now little endian
+            match (self.dtype, self.sample_bytes) {
+                (ChannelType::F32, 8) => f64::from_le_bytes(w) as f32,
+                (ChannelType::F32, _) => f32::from_le_bytes([w[0], w[1], w[2], w[3]]),
+                _ => u64::from_le_bytes(w) as f32,
+            }
+        }).collect())
+    }
+
+    /// Blosc 1 frame: 16 byte header (version, codec version, flags, typesize, nbytes, blocksize, cbytes),
+    /// then either the bytes themselves (memcpyed) or an offset per block, each block's splits
+    /// prefixed by their compressed size
+    /// expected: the chunk's size in bytes, a header claiming more is rejected before anything is allocated
+    fn blosc_decompress(bytes: &[u8], expected: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        if bytes.len() < BLOSC_HEADER {
+            return Err("Blosc chunk shorter than its header".into());
+        }
+        let word = |at: usize| -> Result<usize, Box<dyn Error>> {
+            let b = bytes.get(at..at + 4).ok_or("Blosc chunk truncated")?;
+            Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        };
+        let (flags, typesize) = (bytes[2], bytes[3].max(1) as usize);
+        let (nbytes, blocksize) = (word(4)?, word(8)?);
+        if nbytes > expected {
+            return Err(format!("Blosc chunk claims {} bytes, expected at most {}", nbytes, expected).into());
+        }
+        let shuffled = flags & 0x1 != 0;
+        if flags & 0x4 != 0 {
+            return Err("Blosc bit shuffle is not supported".into());
+        }
+        if flags & 0x2 != 0 { // memcpyed, stored uncompressed
+            return Ok(bytes.get(BLOSC_HEADER..BLOSC_HEADER + nbytes).ok_or("Blosc chunk truncated")?.to_vec());
+        }
+        let codec = flags >> 5;
+        if blocksize == 0 {
+            return Ok(Vec::new());
+        }
+
+        let blocks = nbytes.div_ceil(blocksize);
+        let mut out = vec![0u8; nbytes]; // MUT
+        for b in 0..blocks {
+            let size = blocksize.min(nbytes - b * blocksize); // last block may be short
+            let leftover = size < blocksize;
+            let splits = if flags & 0x10 == 0 && !leftover && size % typesize == 0 { typesize } else { 1 };
+            let mut at = word(BLOSC_HEADER + b * 4)?; // MUT
+            let mut block = Vec::with_capacity(size); // MUT
+            for _ in 0..splits {
+                let split = size / splits;
+                let compressed = word(at)?;
+                at += 4;
+                let src = bytes.get(at..at + compressed).ok_or("Blosc chunk truncated")?;
+                at += compressed;
+                if compressed == split { // stored as is
+                    block.extend_from_slice(src);
+                    continue;
+                }
+                let mut part = vec![0u8; split];
+                match codec {
+                    1 => { lz4_flex::block::decompress_into(src, &mut part).map_err(|e| format!("Blosc lz4: {}", e))?; }, // lz4 and lz4hc
+                    3 => { flate2::read::ZlibDecoder::new(src).read_exact(&mut part)?; },
+                    4 => { ruzstd::decoding::StreamingDecoder::new(src).map_err(|e| format!("Blosc zstd: {}", e))?.read_exact(&mut part)?; },
+                    0 => return Err("Blosc blosclz is not supported".into()),
+                    2 => return Err("Blosc snappy is not supported".into()),
+                    other => return Err(format!("Unknown blosc codec {}", other).into()),
+                }
+                block.extend_from_slice(&part);
+            }
+
+            if block.len() != size {
+                return Err(format!("Blosc block {} decodes to {} bytes, expected {}", b, block.len(), size).into());
+            }
+            let dest = &mut out[b * blocksize..b * blocksize + size];
+            if shuffled && typesize > 1 { // byte k of every element stored together, elements then any tail
+                let elements = size / typesize;
+                for e in 0..elements {
+                    for k in 0..typesize { dest[e * typesize + k] = block[k * elements + e]; }
+                }
+                dest[elements * typesize..].copy_from_slice(&block[elements * typesize..]);
+            } else {
+                dest.copy_from_slice(&block);
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Positions of an OME-Zarr image's axes in its arrays' shape
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ZarrAxes {
+    pub t: Option<usize>,
+    pub c: Option<usize>,
+    pub zyx: [usize; 3],
+}
+
+/// One resolution of a multiscale pyramid
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZarrLevel {
+    pub array: ZarrArray,
+    pub dims: Dims3, // x, y, z voxels
+    pub spacing: P3, // x, y, z, microns when the axes give units
+}
+
+/// Chunk-sized region of one level and time point, every channel, as State::stream_zarr() uploads them
+/// Laid out as the voxel buffers (x fastest), rows flipped like TiffStack so image row 0 is the top of the grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZarrBlock {
+    pub origin: [usize; 3], // lowest grid voxel, x, y, z
+    pub size: [usize; 3],
+    pub values: Vec<Vec<f32>>, // per channel
+}
+
+/// OME-Zarr multiscale image (NGFF 0.1 to 0.4) on local disk, opened lazily: open() reads metadata only,
+/// read_block() decodes the chunks of one block
+/// Levels run finest (0) to coarsest, each a Zarr v2 array over (t, c, z, y, x), t and c optional
+#[derive(Debug, Clone, PartialEq)]
+pub struct OmeZarr {
+    pub path: PathBuf,
+    pub name: String,
+    pub axes: ZarrAxes,
+    pub levels: Vec<ZarrLevel>,
+    pub channel_names: Vec<String>,
+    pub windows: Vec<Option<[f32; 2]>>, // per channel display range from the omero metadata
+}
+
+impl OmeZarr {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let attrs: Value = serde_json::from_str(&std::fs::read_to_string(path.join(".zattrs"))?)?;
+        let multiscale = &attrs["multiscales"][0];
+        let datasets = multiscale["datasets"].as_array().filter(|d| !d.is_empty()).ok_or(format!("{} has no multiscales datasets", path.display()))?;
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or("zarr".to_string());
+        let name = multiscale["name"].as_str().map(|s| s.to_string()).unwrap_or(stem);
+
+        // AXES, named in 0.3, objects with type and unit from 0.4, otherwise the last of t, c, z, y, x //
+        let first = ZarrArray::open(&path.join(datasets[0]["path"].as_str().ok_or("Multiscales dataset without a path")?))?;
+        let rank = first.shape.len();
+        let names: Vec<String> = match multiscale["axes"].as_array() {
+            Some(axes) => axes.iter().map(|a| a["name"].as_str().or(a.as_str()).unwrap_or("").to_lowercase()).collect(),
+            None => ["t", "c", "z", "y", "x"][5usize.saturating_sub(rank)..].iter().map(|s| s.to_string()).collect(),
+        };
+        let units: Vec<f32> = match multiscale["axes"].as_array() {
+            Some(axes) => axes.iter().map(|a| match a["unit"].as_str() { // to microns
+                Some("nanometer") => 0.001,
+                Some("millimeter") => 1000.0,
+                Some("centimeter") => 10000.0,
+                Some("meter") => 1.0e6,
+                _ => 1.0,
+            }).collect(),
+            None => vec![1.0; rank],
+        };
+        let position = |axis: &str| names.iter().position(|n| n == axis);
+        if names.len() != rank || names.iter().any(|n| !["t", "c", "z", "y", "x"].contains(&n.as_str())) {
+            return Err(format!("Axes {:?} don't fit a rank {} array of t, c, z, y and x", names, rank).into());
+        }
+        let axes = ZarrAxes {
+            t: position("t"),
+            c: position("c"),
+            zyx: [position("z").ok_or("OME-Zarr image has no z axis")?, position("y").ok_or("OME-Zarr image has no y axis")?, position("x").ok_or("OME-Zarr image has no x axis")?]
+        };
+
+        // LEVELS, scale from each dataset's transformations times the multiscale's own //
+        let scale_of = |transforms: &Value| -> Option<Vec<f32>> {
+            transforms.as_array()?.iter().find(|t| t["type"] == "scale")?["scale"].as_array()?.iter().map(|v| v.as_f64().map(|s| s as f32)).collect()
+        };
+        let global = scale_of(&multiscale["coordinateTransformations"]).unwrap_or(vec![1.0; rank]);
+        let mut levels = Vec::with_capacity(datasets.len()); // MUT
+        for (l, dataset) in datasets.iter().enumerate() {
+            let array = if l == 0 { first.clone() } else {
+                ZarrArray::open(&path.join(dataset["path"].as_str().ok_or("Multiscales dataset without a path")?))?
+            };
+            if array.shape.len() != rank {
+                return Err(format!("Level {} has rank {}, level 0 has {}", l, array.shape.len(), rank).into());
+            }
+            for axis in [axes.t, axes.c].into_iter().flatten() {
+                if array.shape[axis] != first.shape[axis] {
+                    return Err(format!("Level {} has {} along {}, level 0 has {}", l, array.shape[axis], names[axis], first.shape[axis]).into());
+                }
+            }
+            // before 0.4 levels carry no scale, they're downsampled level 0 voxels
+            let scale = scale_of(&dataset["coordinateTransformations"]).unwrap_or((0..rank).map(|a| first.shape[a] as f32 / array.shape[a] as f32).collect());
+            let along = |a: usize| scale.get(a).copied().unwrap_or(1.0) * global.get(a).copied().unwrap_or(1.0) * units[a];
+            let [z, y, x] = axes.zyx;
+            let spacing = [along(x), along(y), along(z)];
+            if spacing.iter().any(|s| !s.is_finite() || *s <= 0.0) {
+                return Err(format!("Level {} has spacing {:?}, scales need to be positive", l, spacing).into());
+            }
+            if [x, y, z].iter().any(|&a| array.shape[a] == 0) {
+                return Err(format!("Level {} has an empty axis, shape {:?}", l, array.shape).into());
+            }
+            levels.push(ZarrLevel {
+                dims: [array.shape[x] as u32, array.shape[y] as u32, array.shape[z] as u32],
+                spacing: spacing,
+                array: array
+            });
+        }
+
+        // CHANNELS, labels and display windows from omero //
+        let channels = axes.c.map(|c| first.shape[c]).unwrap_or(1);
+        let omero = attrs["omero"]["channels"].as_array();
+        let channel_names = (0..channels).map(|c| {
+            omero.and_then(|o| o.get(c)).and_then(|o| o["label"].as_str()).map(|s| s.to_string()).unwrap_or(if channels == 1 { name.clone() } else { format!("{}_{}", name, c) })
+        }).collect();
+        let windows = (0..channels).map(|c| {
+            let window = &omero?.get(c)?["window"];
+            let (start, end) = (window["start"].as_f64()? as f32, window["end"].as_f64()? as f32);
+            if start < end { Some([start, end]) } else { None }
+        }).collect();
+
+        Ok(OmeZarr {
+            path: path.to_path_buf(),
+            name: name,
+            axes: axes,
+            levels: levels,
+            channel_names: channel_names,
+            windows: windows
+        })
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channel_names.len()
+    }
+
+    pub fn time_points(&self) -> usize {
+        self.axes.t.map(|t| self.levels[0].array.shape[t]).unwrap_or(1)
+    }
+
+    /// Level whose voxels come closest to one per screen pixel: the coarsest with at least pixels voxels
+    /// across the longest side of the cropped fraction of the grid (see World::projected_extent()), the finest if none has
+    /// max_voxels: the voxel buffers' limit, levels above it are skipped (the coarsest is taken if none fit)
+    pub fn level_for(&self, pixels: f32, fraction: P3, max_voxels: usize) -> usize {
+        let fits = |l: &usize| self.levels[*l].dims.iter().map(|&d| d as usize).product::<usize>() <= max_voxels;
+        let across = |l: &usize| (0..3).map(|a| self.levels[*l].dims[a] as f32 * fraction[a]).fold(0.0, f32::max);
+        (0..self.levels.len()).rev().filter(fits).find(|l| across(l) >= pixels)
+            .or((0..self.levels.len()).find(fits))
+            .unwrap_or(self.levels.len() - 1)
+    }
+
+    /// Level's voxels centred on the origin at its spacing, so every level spans the same physical box
+    pub fn transform(&self, level: usize) -> VoxelTransform {
+        VoxelTransform::centred(&self.levels[level].dims, self.levels[level].spacing)
+    }
+
+    /// Chunk grid of level over z, y and x, as x, y, z block indices
+    pub fn blocks(&self, level: usize) -> Vec<[usize; 3]> {
+        let grid = self.levels[level].array.chunk_grid();
+        let [nz, ny, nx] = self.axes.zyx.map(|a| grid[a]);
+        (0..nz).flat_map(|k| (0..ny).flat_map(move |j| (0..nx).map(move |i| [i, j, k]))).collect()
+    }
+
+    /// Every channel of block (see blocks()) at time point t, decoding each chunk it spans along c
+    pub fn read_block(&self, level: usize, t: usize, block: [usize; 3]) -> Result<ZarrBlock, Box<dyn Error>> {
+        let array = &self.levels[level].array;
+        let dims = self.levels[level].dims;
+        let [za, ya, xa] = self.axes.zyx;
+        let start = [block[0] * array.chunks[xa], block[1] * array.chunks[ya], block[2] * array.chunks[za]];
+        let end = [(start[0] + array.chunks[xa]).min(array.shape[xa]), (start[1] + array.chunks[ya]).min(array.shape[ya]), (start[2] + array.chunks[za]).min(array.shape[za])];
+        if (0..3).any(|a| start[a] >= end[a]) {
+            return Err(format!("Block {:?} is outside level {}", block, level).into());
+        }
+        let size = [end[0] - start[0], end[1] - start[1], end[2] - start[2]];
+
+        // C order strides within a chunk
+        let mut strides = vec![1; array.chunks.len()]; // MUT
+        for a in (0..array.chunks.len() - 1).rev() { strides[a] = strides[a + 1] * array.chunks[a + 1]; }
+
+        let channels = self.channels();
+        let chunk_c = self.axes.c.map(|c| array.chunks[c]).unwrap_or(1);
+        let mut values = vec![vec![0.0; size[0] * size[1] * size[2]]; channels]; // MUT
+        for first_c in (0..channels).step_by(chunk_c) {
+            let mut index = vec![0; array.shape.len()]; // MUT
+            index[xa] = block[0];
+            index[ya] = block[1];
+            index[za] = block[2];
+            if let Some(c) = self.axes.c { index[c] = first_c / chunk_c; }
+            if let Some(ta) = self.axes.t { index[ta] = t / array.chunks[ta]; }
+            let chunk = array.read_chunk(&index)?;
+
+            let base = self.axes.t.map(|ta| (t % array.chunks[ta]) * strides[ta]).unwrap_or(0);
+            for (c, channel) in values.iter_mut().enumerate().skip(first_c).take(chunk_c) {
+                let base = base + self.axes.c.map(|ca| (c - first_c) * strides[ca]).unwrap_or(0);
+                for z in 0..size[2] {
+                    for y in 0..size[1] {
+                        let row = base + z * strides[za] + y * strides[ya];
+                        let out = (z * size[1] + (size[1] - 1 - y)) * size[0]; // flipped, see ZarrBlock
+                        for x in 0..size[0] { channel[out + x] = chunk[row + x * strides[xa]]; }
+                    }
+                }
+            }
+        }
+
+        Ok(ZarrBlock {
+            origin: [start[0], dims[1] as usize - end[1], start[2]],
+            size: size,
+            values: values
+        })
+    }
+
+    /// Per channel value range: the omero window where there is one, the coarsest level's min and max otherwise
+    pub fn ranges(&self, t: usize) -> Result<Vec<[f32; 2]>, Box<dyn Error>> {
+        let coarsest = self.levels.len() - 1;
+        let mut ranges = vec![[f32::INFINITY, f32::NEG_INFINITY]; self.channels()]; // MUT
+        if self.windows.iter().any(|w| w.is_none()) {
+            for block in self.blocks(coarsest) {
+                for (c, values) in self.read_block(coarsest, t, block)?.values.iter().enumerate() {
+                    ranges[c] = values.iter().fold(ranges[c], |[l, h], &v| [l.min(v), h.max(v)]);
+                }
+            }
+        }
+        Ok(ranges.into_iter().zip(&self.windows).map(|(range, window)| match window {
+            Some(w) => *w,
+            None if range[0] < range[1] => range,
+            None => [range[0].min(0.0), range[0].max(0.0) + 1.0], // flat channel
+        }).collect())
+    }
+}
+
+/// Blocks of one level and time point still to upload, taken nearest the camera first
+/// (see State::stream_zarr(), which also picks the level)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZarrStream {
+    pub source: OmeZarr,
+    pub level: usize,
+    pub t: usize,
+    pending: Vec<[usize; 3]>,
+}
+
+impl ZarrStream {
+    pub fn new(source: OmeZarr, level: usize, t: usize) -> Self {
+        let pending = source.blocks(level);
+        ZarrStream { source: source, level: level, t: t, pending: pending }
+    }
+
+    /// Queues every block again, e.g. after the voxel buffers were reallocated or for another time point
+    pub fn requeue(&mut self) {
+        self.pending = self.source.blocks(self.level);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Removes up to count pending blocks, those whose centres lie nearest eye (in the level's voxel coordinates)
+    pub fn next_blocks(&mut self, count: usize, eye: P3) -> Vec<[usize; 3]> {
+        let level = &self.source.levels[self.level];
+        let chunks = &level.array.chunks;
+        let [za, ya, xa] = self.source.axes.zyx;
+        let height = level.dims[1] as f32;
+        let distance = |b: &[usize; 3]| -> f32 {
+            let centre = [
+                (b[0] as f32 + 0.5) * chunks[xa] as f32,
+                height - (b[1] as f32 + 0.5) * chunks[ya] as f32, // rows flipped, see ZarrBlock
+                (b[2] as f32 + 0.5) * chunks[za] as f32
+            ];
+            (0..3).map(|a| (centre[a] - eye[a]).powi(2)).sum()
+        };
+        self.pending.sort_unstable_by(|a, b| distance(b).total_cmp(&distance(a))); // nearest last
+        let keep = self.pending.len().saturating_sub(count);
+        self.pending.split_off(keep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 64 u16 (i * 3), typesize 2, blocksize 48, lz4 byte shuffled: each full block's low byte split is stored as is,
+    // its high byte split compressed, and the short last block is a single split
+    const LZ4_SHUFFLE: [u8; 146] = [
+        0x02, 0x01, 0x21, 0x02, 0x80, 0x00, 0x00, 0x00, 0x30, 0x00, 0x00, 0x00, 0x92, 0x00, 0x00, 0x00,
+        0x1c, 0x00, 0x00, 0x00, 0x47, 0x00, 0x00, 0x00, 0x72, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0x00, 0x03, 0x06, 0x09, 0x0c, 0x0f, 0x12, 0x15, 0x18, 0x1b, 0x1e, 0x21, 0x24, 0x27, 0x2a, 0x2d,
+        0x30, 0x33, 0x36, 0x39, 0x3c, 0x3f, 0x42, 0x45, 0x0b, 0x00, 0x00, 0x00, 0x1d, 0x00, 0x01, 0x00,
+        0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00, 0x48, 0x4b, 0x4e, 0x51, 0x54,
+        0x57, 0x5a, 0x5d, 0x60, 0x63, 0x66, 0x69, 0x6c, 0x6f, 0x72, 0x75, 0x78, 0x7b, 0x7e, 0x81, 0x84,
+        0x87, 0x8a, 0x8d, 0x0b, 0x00, 0x00, 0x00, 0x1d, 0x00, 0x01, 0x00, 0x60, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x1c, 0x00, 0x00, 0x00, 0xf5, 0x02, 0x90, 0x93, 0x96, 0x99, 0x9c, 0x9f, 0xa2, 0xa5,
+        0xa8, 0xab, 0xae, 0xb1, 0xb4, 0xb7, 0xba, 0xbd, 0x00, 0x01, 0x00, 0x60, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ];
+
+    // 16 f32 (i % 4), typesize 4, one 64 byte zstd block with the no split flag
+    const ZSTD_NO_SPLIT: [u8; 75] = [
+        0x02, 0x01, 0x90, 0x04, 0x40, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x4b, 0x00, 0x00, 0x00,
+        0x14, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00, 0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x38, 0x35, 0x01,
+        0x00, 0x0c, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x3f, 0x00, 0x00, 0x00, 0x40,
+        0x00, 0x00, 0x40, 0x40, 0x02, 0xa8, 0x10, 0xe3, 0xdf, 0x0f, 0x10, 0x26, 0x7e, 0x10, 0xfe, 0x89,
+        0xf1, 0xef, 0x07, 0x63, 0x26, 0x84, 0x11, 0xa2, 0xc1, 0x19, 0x0b,
+    ];
+
+    // 4 u16 (1, 2, 3, 4), typesize 2, memcpyed
+    const MEMCPYED: [u8; 24] = [
+        0x02, 0x01, 0x02, 0x02, 0x08, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00,
+    ];
+
+    #[test]
+    fn blosc_lz4_shuffle() {
+        let expected: Vec<u8> = (0..64u16).flat_map(|i| (i * 3).to_le_bytes()).collect();
+        assert_eq!(ZarrArray::blosc_decompress(&LZ4_SHUFFLE, 128).unwrap(), expected);
+    }
+
+    #[test]
+    fn blosc_zstd_no_split() {
+        let expected: Vec<u8> = (0..16).flat_map(|i| ((i % 4) as f32).to_le_bytes()).collect();
+        assert_eq!(ZarrArray::blosc_decompress(&ZSTD_NO_SPLIT, 64).unwrap(), expected);
+    }
+
+    #[test]
+    fn blosc_memcpyed() {
+        assert_eq!(ZarrArray::blosc_decompress(&MEMCPYED, 8).unwrap(), [1, 0, 2, 0, 3, 0, 4, 0]);
+    }
+
+    #[test]
+    fn blosc_rejects_bad_frames() {
+        assert!(ZarrArray::blosc_decompress(&LZ4_SHUFFLE, 64).is_err()); // nbytes past the chunk
+        let mut huge = MEMCPYED;
+        huge[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(ZarrArray::blosc_decompress(&huge, 8).is_err());
+        assert!(ZarrArray::blosc_decompress(&LZ4_SHUFFLE[..100], 128).is_err()); // truncated
+        assert!(ZarrArray::blosc_decompress(&MEMCPYED[..12], 8).is_err());
+    }
+
+    // (t, c, z, y, x) sample, distinct everywhere
+    fn sample(t: usize, c: usize, z: usize, y: usize, x: usize) -> u16 {
+        (t * 10000 + c * 1000 + z * 100 + y * 10 + x) as u16
+    }
+
+    /// Uncompressed u16 level with full edge chunks, every chunk but skip written
+    fn write_level(root: &Path, level: &str, shape: [usize; 5], chunks: [usize; 5], skip: Option<[usize; 5]>) {
+        let dir = root.join(level);
+        std::fs::create_dir_all(&dir).unwrap();
+        let zarray = serde_json::json!({ "zarr_format": 2, "shape": shape, "chunks": chunks, "dtype": "<u2",
+            "compressor": null, "fill_value": 7, "order": "C", "filters": null });
+        std::fs::write(dir.join(".zarray"), zarray.to_string()).unwrap();
+        let grid: Vec<usize> = (0..5).map(|a| shape[a].div_ceil(chunks[a])).collect();
+        for i in 0..grid.iter().product::<usize>() {
+            let mut index = [0; 5];
+            let mut rest = i; // MUT
+            for a in (0..5).rev() { index[a] = rest % grid[a]; rest /= grid[a]; }
+            if Some(index) == skip { continue; }
+            let mut bytes = Vec::new();
+            for j in 0..chunks.iter().product::<usize>() {
+                let mut p = [0; 5];
+                let mut rest = j; // MUT
+                for a in (0..5).rev() { p[a] = index[a] * chunks[a] + rest % chunks[a]; rest /= chunks[a]; }
+                let inside = (0..5).all(|a| p[a] < shape[a]);
+                let v = if inside { sample(p[0], p[1], p[2], p[3], p[4]) } else { 0 };
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            let name: Vec<String> = index.iter().map(|i| i.to_string()).collect();
+            std::fs::write(dir.join(name.join(".")), bytes).unwrap();
+        }
+    }
+
+    fn write_image(root: &Path, scale: f64) {
+        let _ = std::fs::remove_dir_all(root);
+        write_level(root, "0", [2, 3, 4, 4, 6], [1, 2, 2, 4, 4], Some([0, 1, 0, 0, 0]));
+        write_level(root, "1", [2, 3, 2, 2, 3], [1, 2, 2, 2, 3], None);
+        let zattrs = serde_json::json!({
+            "multiscales": [{ "version": "0.4", "name": "embryo",
+                "axes": [{ "name": "t", "type": "time" }, { "name": "c", "type": "channel" },
+                    { "name": "z", "type": "space", "unit": "millimeter" }, { "name": "y", "type": "space", "unit": "micrometer" },
+                    { "name": "x", "type": "space", "unit": "micrometer" }],
+                "datasets": [
+                    { "path": "0", "coordinateTransformations": [{ "type": "scale", "scale": [1.0, 1.0, 0.5, 0.25, scale] }] },
+                    { "path": "1", "coordinateTransformations": [{ "type": "scale", "scale": [1.0, 1.0, 1.0, 0.5, 0.5] }] }
+                ] }],
+            "omero": { "channels": [{ "label": "DAPI", "window": { "start": 10, "end": 200 } }, { "label": "GFP" }] }
+        });
+        std::fs::write(root.join(".zattrs"), zattrs.to_string()).unwrap();
+    }
+
+    #[test]
+    fn open_reads_axes_levels_and_channels() {
+        let root = std::env::temp_dir().join("bocs_zarr_open_test.zarr");
+        write_image(&root, 0.25);
+        let image = OmeZarr::open(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        let image = image.unwrap();
+
+        assert_eq!(image.name, "embryo");
+        assert_eq!(image.axes, ZarrAxes { t: Some(0), c: Some(1), zyx: [2, 3, 4] });
+        assert_eq!(image.levels.len(), 2);
+        assert_eq!(image.levels[0].dims, [6, 4, 4]);
+        assert_eq!(image.levels[1].dims, [3, 2, 2]);
+        assert_eq!(image.levels[0].spacing, [0.25, 0.25, 500.0]); // z in millimetres
+        assert_eq!(image.levels[1].spacing, [0.5, 0.5, 1000.0]);
+        assert_eq!(image.channel_names, ["DAPI", "GFP", "embryo_2"]);
+        assert_eq!(image.windows, [Some([10.0, 200.0]), None, None]);
+        assert_eq!(image.time_points(), 2);
+        let (fine, coarse) = (image.transform(0).extent(&image.levels[0].dims), image.transform(1).extent(&image.levels[1].dims));
+        assert_eq!(fine, coarse); // every level spans the same box
+    }
+
+    #[test]
+    fn open_rejects_zero_and_negative_scales() {
+        for (scale, file) in [(0.0, "bocs_zarr_zero_scale_test.zarr"), (-0.25, "bocs_zarr_negative_scale_test.zarr")] {
+            let root = std::env::temp_dir().join(file);
+            write_image(&root, scale);
+            let image = OmeZarr::open(&root);
+            std::fs::remove_dir_all(&root).unwrap();
+            assert!(image.is_err());
+        }
+    }
+
+    #[test]
+    fn read_block_flips_rows_and_spans_channel_chunks() {
+        let root = std::env::temp_dir().join("bocs_zarr_block_test.zarr");
+        write_image(&root, 0.25);
+        let image = OmeZarr::open(&root).unwrap();
+        assert_eq!(image.blocks(0).len(), 2 * 2); // x, z chunks
+        let block = image.read_block(0, 1, [1, 0, 1]); // x 4..6, y 0..4, z 2..4 of time point 1
+        let missing = image.read_block(0, 0, [0, 0, 0]); // channel chunk 1 of t 0 isn't on disk
+        let outside = image.read_block(0, 0, [2, 0, 0]);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let block = block.unwrap();
+        assert_eq!(block.origin, [4, 0, 2]);
+        assert_eq!(block.size, [2, 4, 2]);
+        assert_eq!(block.values.len(), 3); // channels 0 and 1 from one chunk, 2 from the next
+        for (c, values) in block.values.iter().enumerate() {
+            for z in 0..2 {
+                for y in 0..4 {
+                    for x in 0..2 {
+                        let row = 3 - y; // image row 0 is the top of the grid
+                        assert_eq!(values[(z * 4 + row) * 2 + x], sample(1, c, 2 + z, y, 4 + x) as f32);
+                    }
+                }
+            }
+        }
+
+        let missing = missing.unwrap();
+        assert_eq!(missing.values[0][3 * 4], sample(0, 0, 0, 0, 0) as f32);
+        assert!(missing.values[2].iter().all(|&v| v == 7.0)); // fill_value
+        assert!(outside.is_err());
+    }
+
+    #[test]
+    fn level_for_picks_the_coarsest_detailed_enough() {
+        let root = std::env::temp_dir().join("bocs_zarr_level_test.zarr");
+        write_image(&root, 0.25);
+        let image = OmeZarr::open(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        let image = image.unwrap();
+
+        assert_eq!(image.level_for(2.0, [1.0; 3], usize::MAX), 1);
+        assert_eq!(image.level_for(5.0, [1.0; 3], usize::MAX), 0);
+        assert_eq!(image.level_for(2.0, [0.5; 3], usize::MAX), 0); // cropped to half, level 1 is 1.5 voxels across
+        assert_eq!(image.level_for(100.0, [1.0; 3], usize::MAX), 0); // none enough, the finest
+        assert_eq!(image.level_for(5.0, [1.0; 3], 50), 1); // level 0 doesn't fit
+        assert_eq!(image.level_for(5.0, [1.0; 3], 5), 1); // nothing fits, the coarsest
+    }
+}